@@ -0,0 +1,710 @@
+/* Modulo per l'esecuzione dell'applicazione da riga di comando, senza mostrare la gui.
+Il sottocomando <i>capture</i> esegue uno screenshot utilizzando gli stessi thread worker usati dalla gui
+(ScreensManager e image_coding) e attende i risultati in modo bloccante, dato che non c'è un event loop da non bloccare.
+Il sottocomando <i>edit</i>, invece, lancia la gui aprendo direttamente un'immagine nell'editor.
+Il sottocomando <i>info</i> mostra i metadati di cattura scritti in un file salvato dall'applicazione.
+Il sottocomando <i>windows</i> elenca le finestre che possono essere catturate con l'opzione <i>--window</i> (solo X11).
+Le opzioni globali <i>--config-dir</i> e <i>--portable</i>, che precedono il sottocomando, scelgono la cartella dei
+file di configurazione (vedi il modulo <i>config</i>).
+
+Ogni possibile fallimento è segnalato con un codice di uscita diverso, in modo che possa essere gestito dagli script.
+ */
+
+use crate::config::{self, ConfigLocation};
+use crate::gui::save_settings::SaveSettings;
+use crate::image_coding::{self, ImageFormat};
+use crate::itc::{CaptureMetadata, ScreenshotDim, WindowCaptureSettings, WindowTarget};
+use crate::screens_manager::ScreensManager;
+use crate::window_capture;
+use eframe::emath::{pos2, Rect};
+use image::RgbaImage;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Codici di uscita del processo, uno per ogni possibile causa di fallimento.
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_USAGE: i32 = 1;
+pub const EXIT_NO_SCREEN: i32 = 2;
+pub const EXIT_CAPTURE_FAILED: i32 = 3;
+pub const EXIT_INVALID_REGION: i32 = 4;
+pub const EXIT_NO_OUTPUT: i32 = 5;
+pub const EXIT_SAVE_FAILED: i32 = 6;
+pub const EXIT_CLIPBOARD_FAILED: i32 = 7;
+pub const EXIT_NO_INPUT: i32 = 8;
+pub const EXIT_NO_METADATA: i32 = 9;
+pub const EXIT_NO_WINDOW: i32 = 10;
+
+/// Larghezza delle icone prodotte da ScreensManager: da riga di comando non vengono mostrate, quindi sono piccole.
+const ICON_WIDTH: u32 = 16;
+
+const USAGE: &str = "Usage:
+    project-m1 [GLOBAL OPTIONS] [COMMAND]
+    project-m1                      launch the graphical interface
+    project-m1 capture [OPTIONS]    take a screenshot without showing any window
+    project-m1 edit <FILE>          open an existing image in the editor
+    project-m1 info <FILE>          print the capture metadata stored in a PNG or JPEG file
+    project-m1 windows              list the windows that can be captured with --window (X11 only)
+
+Global options:
+    --config-dir <DIR>    read and write the settings in this directory
+                          (default: $XDG_CONFIG_HOME/project-m1, or ~/.config/project-m1)
+    --portable            keep the settings in the config directory next to the executable
+                          (also enabled by a file named portable next to the executable)
+
+Capture options:
+    --screen <N|all>      screen to capture, numbered from 1, or all screens stitched together
+                          (default: primary screen)
+    --window <ID|pick>    capture a single window, by id (decimal or 0x hex, see the windows command)
+                          or by clicking on it; cannot be combined with --screen
+    --no-decorations      with --window, leave out the title bar and borders drawn by the window manager
+    --delay <SECONDS>     wait before capturing (default: 0)
+    --format <FORMAT>     output format: png, jpeg, gif, webp, webp-lossy, bmp, tiff, qoi
+                          (default: from --out extension, or png)
+    --out <PATH>          output file (default: directory and name from the save settings)
+    --region <X,Y,W,H>    capture only this rectangle, in physical pixels (relative to the window with --window)
+    --clipboard           copy the captured image to the clipboard
+    --help                print this message
+
+Exit codes:
+    0 success, 1 invalid arguments, 2 screen not found, 3 capture failed, 4 invalid region,
+    5 no output path available, 6 save failed, 7 clipboard copy failed, 8 input file not found,
+    9 no capture metadata in the file, 10 window not found";
+
+/// Opzioni del sottocomando <i>capture</i>, ottenute dal parsing degli argomenti.
+#[derive(Debug, Default, PartialEq)]
+struct CaptureOptions {
+    /// Numerazione a partire da 1, come nella gui.
+    screen: Option<usize>,
+    /// Cattura di tutti gli schermi, composti in un'unica immagine (<i>--screen all</i>).
+    all_screens: bool,
+    /// Finestra da catturare al posto di uno schermo (<i>--window</i>).
+    window: Option<WindowTarget>,
+    /// Esclude le decorazioni della finestra (<i>--no-decorations</i>).
+    no_decorations: bool,
+    delay: f64,
+    format: Option<ImageFormat>,
+    out: Option<PathBuf>,
+    region: Option<Rect>,
+    clipboard: bool,
+    help: bool,
+}
+
+/// Esito dell'esecuzione della riga di comando.
+#[derive(Debug, PartialEq)]
+pub enum CliOutcome {
+    /// Il sottocomando è stato eseguito: il processo deve terminare con il codice di uscita indicato.
+    Exit(i32),
+    /// Deve essere lanciata la gui; se presente, il file indicato deve essere aperto nell'editor.
+    LaunchGui(Option<PathBuf>),
+}
+
+/// Punto di ingresso della riga di comando.<br>
+/// Legge le opzioni globali e sceglie la cartella di configurazione, prima che venga letta qualsiasi impostazione.
+/// Ritorna <i>CliOutcome::LaunchGui</i> se gli argomenti non contengono nessun sottocomando, oppure se è stato richiesto
+/// il sottocomando <i>edit</i>.
+/// Altrimenti, esegue il sottocomando e ritorna <i>CliOutcome::Exit(codice di uscita)</i>.
+pub fn run(args: &[String]) -> CliOutcome {
+    let args = match parse_global_options(args) {
+        Ok((location, rest)) => {
+            config::init(location);
+            rest
+        }
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            return CliOutcome::Exit(EXIT_USAGE);
+        }
+    };
+    match args.first().map(|s| s.as_str()) {
+        None => CliOutcome::LaunchGui(None),
+        Some("capture") => {
+            let code = run_capture(&args[1..]);
+            //su X11 il contenuto della clipboard deve essere ceduto al clipboard manager prima della terminazione
+            image_coding::stop_clipboard_service();
+            CliOutcome::Exit(code)
+        }
+        Some("edit") => run_edit(&args[1..]),
+        Some("info") => CliOutcome::Exit(run_info(&args[1..])),
+        Some("windows") => CliOutcome::Exit(run_windows(&args[1..])),
+        Some("--help") | Some("-h") | Some("help") => {
+            println!("{}", USAGE);
+            CliOutcome::Exit(EXIT_OK)
+        }
+        Some(other) => {
+            eprintln!("Error: unknown command '{}'\n\n{}", other, USAGE);
+            CliOutcome::Exit(EXIT_USAGE)
+        }
+    }
+}
+
+/// Separa le opzioni globali, che precedono il sottocomando, dal resto degli argomenti.
+fn parse_global_options(args: &[String]) -> Result<(ConfigLocation, &[String]), String> {
+    let (location, n) = match (args.first().map(|s| s.as_str()), args.get(1)) {
+        (Some("--config-dir"), Some(dir)) => (ConfigLocation::Dir(PathBuf::from(dir)), 2),
+        (Some("--config-dir"), None) => return Err("missing value for --config-dir".to_string()),
+        (Some("--portable"), _) => (ConfigLocation::Portable, 1),
+        _ => (ConfigLocation::Default, 0),
+    };
+    match args.get(n).map(|s| s.as_str()) {
+        Some("--config-dir") | Some("--portable") => {
+            Err("only one of --config-dir and --portable can be given".to_string())
+        }
+        _ => Ok((location, &args[n..])),
+    }
+}
+
+/// Esegue il sottocomando <i>edit</i>: controlla che sia stato indicato esattamente un file esistente e, in tal caso,
+/// chiede di lanciare la gui aprendo il file nell'editor.
+/// La decodifica dell'immagine avviene nella gui: eventuali errori saranno mostrati in un alert.
+fn run_edit(args: &[String]) -> CliOutcome {
+    match args {
+        [flag] if flag == "--help" || flag == "-h" => {
+            println!("{}", USAGE);
+            CliOutcome::Exit(EXIT_OK)
+        }
+        [file] => {
+            let path = PathBuf::from(file);
+            if path.is_file() {
+                CliOutcome::LaunchGui(Some(path))
+            } else {
+                eprintln!("Error: file '{}' not found", file);
+                CliOutcome::Exit(EXIT_NO_INPUT)
+            }
+        }
+        _ => {
+            eprintln!("Error: edit requires exactly one file\n\n{}", USAGE);
+            CliOutcome::Exit(EXIT_USAGE)
+        }
+    }
+}
+
+/// Esegue il sottocomando <i>info</i>: stampa i metadati di cattura letti dal file indicato, uno per riga.
+///
+/// Ritorna il codice di uscita del processo.
+fn run_info(args: &[String]) -> i32 {
+    let file = match args {
+        [file] => PathBuf::from(file),
+        _ => {
+            eprintln!("Error: info requires exactly one file\n\n{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    if !file.is_file() {
+        eprintln!("Error: file '{}' not found", file.display());
+        return EXIT_NO_INPUT;
+    }
+    match image_coding::read_metadata(&file) {
+        Ok(md) => {
+            println!("Captured at:       {}", md.timestamp.to_rfc3339());
+            println!("Screen:            {}", md.screen_id);
+            println!("Screen resolution: {}x{}", md.screen_size.0, md.screen_size.1);
+            println!("Scale factor:      {}", md.scale_factor);
+            println!("Capture mode:      {}", <&str>::from(&md.mode));
+            if let Some((x, y, w, h)) = md.crop {
+                println!("Crop rectangle:    {},{},{},{}", x, y, w, h);
+            }
+            if let Some((x, y, w, h)) = md.changed_area {
+                println!("Changed rectangle: {},{},{},{}", x, y, w, h);
+            }
+            if let Some(dpi) = image_coding::read_dpi(&file) {
+                println!("Resolution:        {} dpi", dpi);
+            }
+            println!("App version:       {}", md.app_version);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("Error: no capture metadata found ({})", e);
+            EXIT_NO_METADATA
+        }
+    }
+}
+
+/// Esegue il sottocomando <i>windows</i>: stampa id, geometria, classe e titolo delle finestre di primo livello visibili,
+/// una per riga.
+///
+/// Ritorna il codice di uscita del processo.
+fn run_windows(args: &[String]) -> i32 {
+    if !args.is_empty() {
+        eprintln!("Error: windows does not accept arguments\n\n{}", USAGE);
+        return EXIT_USAGE;
+    }
+    match window_capture::list_windows() {
+        Ok(windows) => {
+            for w in windows {
+                let b = w.bounds;
+                println!(
+                    "{:#010x}  {}x{}+{}+{}  {}  {}",
+                    w.id, b.width, b.height, b.x, b.y, w.class, w.title
+                );
+            }
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_CAPTURE_FAILED
+        }
+    }
+}
+
+/// Esegue il sottocomando <i>capture</i>:
+/// 1. rileva gli schermi e seleziona quello richiesto (o il primario), oppure controlla che esista la finestra richiesta;
+/// 2. attende il delay richiesto ed esegue lo screenshot (dello schermo o della finestra);
+/// 3. se richiesto, ritaglia la regione indicata;
+/// 4. se richiesto, copia l'immagine nella clipboard;
+/// 5. salva l'immagine nel path indicato o, in sua assenza, in quello ricavato dalle impostazioni di salvataggio.
+///
+/// Ritorna il codice di uscita del processo.
+fn run_capture(args: &[String]) -> i32 {
+    let opts = match CaptureOptions::parse(args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    if opts.help {
+        println!("{}", USAGE);
+        return EXIT_OK;
+    }
+
+    let save_settings = SaveSettings::new(Rc::new(RefCell::new(None)));
+    let (out, format) = match output_path(&opts, &save_settings) {
+        Ok(res) => res,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return EXIT_NO_OUTPUT;
+        }
+    };
+
+    let screens_mgr = ScreensManager::new(ICON_WIDTH);
+    if screens_mgr.get_screens_count() == 0 {
+        eprintln!("Error: no screen detected");
+        return EXIT_NO_SCREEN;
+    }
+    if let Some(n) = opts.screen {
+        if n == 0 || n > screens_mgr.get_screens_count() {
+            eprintln!(
+                "Error: screen {} not found ({} available)",
+                n,
+                screens_mgr.get_screens_count()
+            );
+            return EXIT_NO_SCREEN;
+        }
+        screens_mgr.select_screen(n - 1);
+    }
+    if opts.all_screens {
+        screens_mgr.select_all_screens();
+    }
+    if let Some(WindowTarget::Id(id)) = opts.window {
+        match window_capture::list_windows() {
+            Ok(windows) if windows.iter().any(|w| w.id == id) => (),
+            Ok(_) => {
+                eprintln!("Error: window {:#x} not found", id);
+                return EXIT_NO_WINDOW;
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_CAPTURE_FAILED;
+            }
+        }
+    }
+
+    if opts.delay > 0.0 {
+        std::thread::sleep(Duration::from_secs_f64(opts.delay));
+    }
+    let region = opts.region.map(|r| {
+        (
+            r.left() as u32,
+            r.top() as u32,
+            r.width() as u32,
+            r.height() as u32,
+        )
+    });
+
+    let (mut img, metadata) = match opts.window {
+        Some(target) => {
+            if target == WindowTarget::Pick {
+                eprintln!("Click on the window to capture (press any key to cancel)");
+            }
+            let rx = window_capture::start_thread_window_screenshot(WindowCaptureSettings {
+                target,
+                decorations: !opts.no_decorations,
+            });
+            match rx.recv() {
+                Ok(Ok((img, rect))) => {
+                    //i metadati si riferiscono allo schermo su cui si trova la finestra e il ritaglio, relativo alla
+                    //finestra, viene riportato nelle coordinate di quello schermo
+                    let mut metadata =
+                        screens_mgr.window_metadata((rect.x, rect.y, rect.width, rect.height));
+                    if let (Some(md), Some(cut)) = (metadata.as_mut(), region) {
+                        md.apply_cut(cut);
+                    }
+                    (img, metadata)
+                }
+                Ok(Err(e)) => {
+                    eprintln!("{}", e);
+                    return EXIT_CAPTURE_FAILED;
+                }
+                Err(_) => {
+                    eprintln!("Error: unable to perform screenshot");
+                    return EXIT_CAPTURE_FAILED;
+                }
+            }
+        }
+        None => match screens_mgr.start_thread_fullscreen_screenshot().recv() {
+            Ok(Ok(img)) => {
+                let mode = if region.is_some() {
+                    ScreenshotDim::Rectangle
+                } else {
+                    ScreenshotDim::Fullscreen
+                };
+                let metadata = screens_mgr
+                    .get_current_screen_infos()
+                    .map(|display| CaptureMetadata::new(&display, mode, region));
+                (img, metadata)
+            }
+            Ok(Err(e)) => {
+                eprintln!("{}", e);
+                return EXIT_CAPTURE_FAILED;
+            }
+            Err(_) => {
+                eprintln!("Error: unable to perform screenshot");
+                return EXIT_CAPTURE_FAILED;
+            }
+        },
+    };
+
+    if let Some(rect) = opts.region {
+        if !region_fits(rect, &img) {
+            eprintln!(
+                "Error: region {}x{}+{}+{} is outside of the captured screen ({}x{})",
+                rect.width(),
+                rect.height(),
+                rect.left(),
+                rect.top(),
+                img.width(),
+                img.height()
+            );
+            return EXIT_INVALID_REGION;
+        }
+        img = match image_coding::start_thread_crop_image(rect, img).recv() {
+            Ok(Ok(cropped)) => cropped,
+            _ => {
+                eprintln!("Error: unable to crop the image");
+                return EXIT_INVALID_REGION;
+            }
+        };
+    }
+
+    if opts.clipboard {
+        match image_coding::start_thread_copy_to_clipboard(&img).recv() {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => {
+                eprintln!("Error: impossible to copy the image on the clipboard ({})", e);
+                return EXIT_CLIPBOARD_FAILED;
+            }
+            Err(_) => {
+                eprintln!("Error: impossible to copy the image on the clipboard");
+                return EXIT_CLIPBOARD_FAILED;
+            }
+        }
+    }
+
+    if let Some(path) = out {
+        match image_coding::start_thread_save_image(
+            with_extension(path, format),
+            img,
+            format,
+            save_settings.get_encoder_options(),
+            metadata,
+        )
+        .recv()
+        {
+            Ok(Ok(saved)) => println!("{}", saved.path),
+            Ok(Err(e)) => {
+                eprintln!("Error: image not saved ({})", e);
+                return EXIT_SAVE_FAILED;
+            }
+            Err(_) => {
+                eprintln!("Error: image not saved");
+                return EXIT_SAVE_FAILED;
+            }
+        }
+    }
+
+    EXIT_OK
+}
+
+/// Determina il path di output e il formato dell'immagine:
+/// - il formato è quello indicato con <i>--format</i>, oppure quello ricavato dall'estensione di <i>--out</i>, oppure Png;
+/// - il path è quello indicato con <i>--out</i>, oppure quello composto dalle impostazioni di salvataggio
+///   (directory e nome di default), con le stesse regole usate dalla gui.
+///
+/// Ritorna <i>Ok((None, ..))</i> se non c'è un path, ma è stata richiesta la sola copia nella clipboard.
+fn output_path(
+    opts: &CaptureOptions,
+    save_settings: &SaveSettings,
+) -> Result<(Option<PathBuf>, ImageFormat), String> {
+    let format = match (opts.format, &opts.out) {
+        (Some(f), _) => f,
+        (None, Some(out)) => out
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(parse_format)
+            .unwrap_or(ImageFormat::Png),
+        (None, None) => ImageFormat::Png,
+    };
+
+    if let Some(out) = &opts.out {
+        return Ok((Some(out.clone()), format));
+    }
+
+    match save_settings.compose_default_output_file_path(format) {
+        Some(path) => Ok((Some(path), format)),
+        None if opts.clipboard => Ok((None, format)),
+        None => Err(
+            "no output path: pass --out or enable both default directory and default name in the save settings"
+                .to_string(),
+        ),
+    }
+}
+
+/// Imposta l'estensione corrispondente al formato, a meno che il path non abbia già un'estensione equivalente
+/// (ad esempio "png" per il formato Png).
+fn with_extension(mut path: PathBuf, format: ImageFormat) -> PathBuf {
+    let matches = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|ext| ImageFormat::try_from(ext).ok())
+        .map(|f| f.extension())
+        == Some(format.extension());
+    if !matches {
+        path.set_extension(format.extension());
+    }
+    path
+}
+
+/// Controlla che il rettangolo non sia vuoto e sia interamente contenuto nell'immagine.
+fn region_fits(rect: Rect, img: &RgbaImage) -> bool {
+    rect.width() >= 1.0
+        && rect.height() >= 1.0
+        && rect.right() <= img.width() as f32
+        && rect.bottom() <= img.height() as f32
+}
+
+/// Converte il nome di un formato, senza distinzione tra maiuscole e minuscole, nella corrispondente <i>ImageFormat</i>.
+/// Oltre alle estensioni riconosciute da <i>ImageFormat::try_from()</i>, accetta "webp-lossy".
+fn parse_format(s: &str) -> Option<ImageFormat> {
+    if s.eq_ignore_ascii_case("webp-lossy") {
+        return Some(ImageFormat::WebPLossy);
+    }
+    ImageFormat::try_from(s).ok()
+}
+
+/// Converte una stringa nel formato "x,y,w,h" in un rettangolo.
+fn parse_region(s: &str) -> Result<Rect, String> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<u32>())
+        .collect::<Result<Vec<u32>, _>>()
+        .map_err(|_| format!("invalid region '{}': expected x,y,w,h", s))?;
+    //l'area deve avere dimensioni positive e non superare il massimo delle coordinate
+    let rect = match values[..] {
+        [x, y, w, h] if w > 0 && h > 0 => {
+            x.checked_add(w)
+                .zip(y.checked_add(h))
+                .map(|(max_x, max_y)| {
+                    Rect::from_min_max(pos2(x as f32, y as f32), pos2(max_x as f32, max_y as f32))
+                })
+        }
+        _ => None,
+    };
+    rect.ok_or_else(|| format!("invalid region '{}': expected x,y,w,h", s))
+}
+
+/// Converte il valore di <i>--window</i>: "pick" oppure l'id della finestra, in decimale o in esadecimale con prefisso "0x"
+/// (come stampato dal sottocomando <i>windows</i>).
+fn parse_window(s: &str) -> Result<WindowTarget, String> {
+    if s.eq_ignore_ascii_case("pick") {
+        return Ok(WindowTarget::Pick);
+    }
+    let id = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    id.map(WindowTarget::Id)
+        .map_err(|_| format!("invalid window '{}': expected an id or 'pick'", s))
+}
+
+impl CaptureOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut opts = CaptureOptions::default();
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            let mut value = |name: &str| {
+                it.next()
+                    .cloned()
+                    .ok_or_else(|| format!("missing value for {}", name))
+            };
+            match arg.as_str() {
+                "--screen" => {
+                    let v = value("--screen")?;
+                    if v.eq_ignore_ascii_case("all") {
+                        opts.all_screens = true;
+                        opts.screen = None;
+                    } else {
+                        opts.all_screens = false;
+                        opts.screen = Some(
+                            v.parse()
+                                .map_err(|_| format!("invalid screen number '{}'", v))?,
+                        );
+                    }
+                }
+                "--window" => {
+                    let v = value("--window")?;
+                    opts.window = Some(parse_window(&v)?);
+                }
+                "--no-decorations" => opts.no_decorations = true,
+                "--delay" => {
+                    let v = value("--delay")?;
+                    opts.delay = match v.parse::<f64>() {
+                        Ok(d) if d >= 0.0 && d.is_finite() => d,
+                        _ => return Err(format!("invalid delay '{}'", v)),
+                    };
+                }
+                "--format" => {
+                    let v = value("--format")?;
+                    opts.format =
+                        Some(parse_format(&v).ok_or_else(|| format!("unsupported format '{}'", v))?);
+                }
+                "--out" => opts.out = Some(PathBuf::from(value("--out")?)),
+                "--region" => opts.region = Some(parse_region(&value("--region")?)?),
+                "--clipboard" => opts.clipboard = true,
+                "--help" | "-h" => opts.help = true,
+                other => return Err(format!("unknown option '{}'", other)),
+            }
+        }
+        if opts.window.is_some() && (opts.screen.is_some() || opts.all_screens) {
+            return Err("--window cannot be combined with --screen".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_capture_options_test() {
+        let opts = CaptureOptions::parse(&args(
+            "--screen 2 --delay 1.5 --format jpg --out shot.jpeg --region 10,20,30,40 --clipboard",
+        ))
+        .unwrap();
+        assert_eq!(opts.screen, Some(2));
+        assert_eq!(opts.delay, 1.5);
+        assert_eq!(opts.format, Some(ImageFormat::Jpeg));
+        assert_eq!(opts.out, Some(PathBuf::from("shot.jpeg")));
+        assert_eq!(
+            opts.region,
+            Some(Rect::from_min_max(pos2(10.0, 20.0), pos2(40.0, 60.0)))
+        );
+        assert!(opts.clipboard);
+
+        let all = CaptureOptions::parse(&args("--screen all")).unwrap();
+        assert!(all.all_screens);
+        assert_eq!(all.screen, None);
+
+        assert!(CaptureOptions::parse(&args("--screen")).is_err());
+        assert!(CaptureOptions::parse(&args("--delay -1")).is_err());
+        assert!(CaptureOptions::parse(&args("--format xyz")).is_err());
+        assert!(CaptureOptions::parse(&args("--region 1,2,3")).is_err());
+        assert!(CaptureOptions::parse(&args("--region 1,2,0,4")).is_err());
+        assert!(CaptureOptions::parse(&args("--unknown")).is_err());
+    }
+
+    #[test]
+    fn parse_global_options_test() {
+        let a = args("--config-dir /tmp/conf capture --screen 1");
+        let (location, rest) = parse_global_options(&a).unwrap();
+        assert_eq!(location, ConfigLocation::Dir(PathBuf::from("/tmp/conf")));
+        assert_eq!(rest, &a[2..]);
+        let a = args("--portable");
+        assert_eq!(
+            parse_global_options(&a),
+            Ok((ConfigLocation::Portable, &a[1..]))
+        );
+        let a = args("info shot.png");
+        assert_eq!(
+            parse_global_options(&a),
+            Ok((ConfigLocation::Default, &a[..]))
+        );
+
+        assert!(parse_global_options(&args("--config-dir")).is_err());
+        assert!(parse_global_options(&args("--portable --config-dir /tmp/conf")).is_err());
+    }
+
+    #[test]
+    fn parse_window_test() {
+        let opts = CaptureOptions::parse(&args("--window 0x1a00005 --no-decorations")).unwrap();
+        assert_eq!(opts.window, Some(WindowTarget::Id(0x1a00005)));
+        assert!(opts.no_decorations);
+        let opts = CaptureOptions::parse(&args("--window pick")).unwrap();
+        assert_eq!(opts.window, Some(WindowTarget::Pick));
+        assert!(!opts.no_decorations);
+        assert_eq!(parse_window("42"), Ok(WindowTarget::Id(42)));
+
+        assert!(CaptureOptions::parse(&args("--window")).is_err());
+        assert!(CaptureOptions::parse(&args("--window 0xzz")).is_err());
+        assert!(CaptureOptions::parse(&args("--window pick --screen 1")).is_err());
+        assert!(CaptureOptions::parse(&args("--screen all --window 42")).is_err());
+        assert_eq!(run(&args("windows extra")), CliOutcome::Exit(EXIT_USAGE));
+    }
+
+    #[test]
+    fn output_path_test() {
+        let ss = SaveSettings::new(Rc::new(RefCell::new(None)));
+        let opts = CaptureOptions::parse(&args("--out shot.gif")).unwrap();
+        let (out, format) = output_path(&opts, &ss).unwrap();
+        assert_eq!(format, ImageFormat::Gif);
+        assert_eq!(with_extension(out.unwrap(), format), PathBuf::from("shot.gif"));
+
+        let opts = CaptureOptions::parse(&args("--out shot --format png")).unwrap();
+        let (out, format) = output_path(&opts, &ss).unwrap();
+        assert_eq!(with_extension(out.unwrap(), format), PathBuf::from("shot.Png"));
+
+        let opts = CaptureOptions::parse(&args("--out shot.webp --format webp-lossy")).unwrap();
+        let (out, format) = output_path(&opts, &ss).unwrap();
+        assert_eq!(format, ImageFormat::WebPLossy);
+        assert_eq!(with_extension(out.unwrap(), format), PathBuf::from("shot.webp"));
+    }
+
+    #[test]
+    fn edit_command_test() {
+        assert_eq!(run(&args("edit")), CliOutcome::Exit(EXIT_USAGE));
+        assert_eq!(run(&args("edit a.png b.png")), CliOutcome::Exit(EXIT_USAGE));
+        assert_eq!(
+            run(&args("edit ./missing_image.png")),
+            CliOutcome::Exit(EXIT_NO_INPUT)
+        );
+        assert_eq!(
+            run(&args("edit Cargo.toml")),
+            CliOutcome::LaunchGui(Some(PathBuf::from("Cargo.toml")))
+        );
+        assert_eq!(run(&[]), CliOutcome::LaunchGui(None));
+    }
+
+    #[test]
+    fn region_fits_test() {
+        let img = RgbaImage::new(100, 50);
+        assert!(region_fits(parse_region("0,0,100,50").unwrap(), &img));
+        assert!(!region_fits(parse_region("10,10,100,10").unwrap(), &img));
+        assert!(parse_region("4294967295,0,10,10").is_err());
+        assert!(parse_region("0,4294967295,10,10").is_err());
+    }
+}
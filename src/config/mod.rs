@@ -0,0 +1,377 @@
+/* Modulo per la lettura e la scrittura dei file di configurazione dell'applicazione.
+Tutti i file si trovano in un'unica cartella, indipendente dalla cartella da cui l'applicazione è stata lanciata:
+- di default, <i>$XDG_CONFIG_HOME/project-m1/</i> (oppure <i>~/.config/project-m1/</i>, o <i>%APPDATA%\project-m1\</i> su Windows);
+- la cartella indicata con l'opzione <i>--config-dir</i>;
+- in modalità portable (opzione <i>--portable</i>, oppure un file <i>portable</i> accanto all'eseguibile), la cartella
+<i>config</i> accanto all'eseguibile.
+
+Ogni file è un documento json con un campo <i>version</i>, che indica la versione dello schema con cui è stato scritto,
+e i campi della struttura salvata (vedi il trait <i>ConfigFile</i>). La scrittura è atomica: il contenuto viene scritto
+in un file temporaneo con nome univoco, sincronizzato su disco, che poi sostituisce quello precedente, in modo che
+un'interruzione non lasci un file troncato. Le scritture sono serializzate: quelle lanciate dalla gui vengono eseguite,
+nell'ordine in cui sono richieste, da un unico thread di scrittura, in modo che l'ultima richiesta sia quella che resta
+su disco.
+
+Le versioni precedenti salvavano i file nella cartella corrente: se un file non esiste ancora nella cartella di
+configurazione, viene cercato lì e, se trovato, spostato nella cartella di configurazione.
+ */
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Mutex, OnceLock};
+
+///Nome della sottocartella dell'applicazione all'interno della cartella di configurazione dell'utente.
+const APP_DIR_NAME: &str = "project-m1";
+///Nome del file che, se presente accanto all'eseguibile, attiva la modalità portable.
+const PORTABLE_MARKER: &str = "portable";
+
+static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+///Canale verso il thread di scrittura, avviato alla prima richiesta di <i>start_thread_save()</i>.
+static WRITER: OnceLock<Sender<Box<dyn FnOnce() + Send>>> = OnceLock::new();
+///Impedisce che due scritture avvengano contemporaneamente (ad esempio una di <i>save()</i> e una del thread di
+///scrittura).
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+///Contatore usato per dare un nome univoco ai file temporanei.
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+///Posizione della cartella di configurazione, scelta con le opzioni della riga di comando.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigLocation {
+    ///Cartella di configurazione dell'utente (oppure portable, se accanto all'eseguibile c'è il file <i>portable</i>).
+    Default,
+    ///Cartella indicata con <i>--config-dir</i>.
+    Dir(PathBuf),
+    ///Cartella <i>config</i> accanto all'eseguibile (<i>--portable</i>).
+    Portable,
+}
+
+///Struttura salvata in un file della cartella di configurazione.
+pub trait ConfigFile: Serialize + DeserializeOwned + Send + 'static {
+    ///Nome del file nella cartella di configurazione.
+    const FILE_NAME: &'static str;
+    ///Nome con cui le versioni precedenti salvavano il file nella cartella corrente.
+    const LEGACY_FILE_NAME: &'static str;
+    ///Versione corrente dello schema: i file con una versione maggiore non vengono letti.
+    const VERSION: u32 = 1;
+
+    ///Converte il documento <i>doc</i>, scritto con la versione <i>version</i> dello schema, minore di quella corrente
+    ///(0 per i file scritti prima dell'introduzione delle versioni).<br>
+    ///L'implementazione di default lo legge come se avesse lo schema corrente.
+    fn migrate(version: u32, doc: serde_json::Value) -> Result<Self, String> {
+        let _ = version;
+        serde_json::from_value(doc).map_err(|e| e.to_string())
+    }
+}
+
+///Sceglie la cartella di configurazione: deve essere richiamata all'avvio, prima di leggere qualsiasi impostazione.
+///Se non viene richiamata, è usata <i>ConfigLocation::Default</i>.
+pub fn init(location: ConfigLocation) {
+    let dir = resolve(&location, |var| std::env::var(var).ok(), exe_dir());
+    let _ = CONFIG_DIR.set(dir);
+}
+
+///Cartella che contiene i file di configurazione.
+pub fn config_dir() -> &'static Path {
+    CONFIG_DIR.get_or_init(|| {
+        resolve(
+            &ConfigLocation::Default,
+            |var| std::env::var(var).ok(),
+            exe_dir(),
+        )
+    })
+}
+
+///Legge la struttura <i>T</i> dal suo file di configurazione, migrando il file delle versioni precedenti se necessario.<br>
+///Ritorna <i>Ok(None)</i> se il file non esiste (ad esempio al primo avvio), un errore se non è valido.
+pub fn load<T: ConfigFile>() -> Result<Option<T>, String> {
+    load_from(config_dir(), Path::new("."))
+}
+
+///Scrive la struttura <i>T</i> nel suo file di configurazione.
+pub fn save<T: ConfigFile>(value: &T) -> Result<(), String> {
+    save_to(config_dir(), value)
+}
+
+///Come <i>save()</i>, ma nel thread di scrittura per non bloccare la gui: le richieste vengono eseguite una alla volta,
+///nell'ordine in cui sono state fatte. Eventuali errori vengono scritti su stderr.
+pub fn start_thread_save<T: ConfigFile>(value: T) {
+    let writer = WRITER.get_or_init(|| {
+        let (tx, rx) = channel::<Box<dyn FnOnce() + Send>>();
+        std::thread::spawn(move || {
+            for job in rx {
+                job();
+            }
+        });
+        tx
+    });
+    let _ = writer.send(Box::new(move || {
+        if let Err(e) = save(&value) {
+            eprintln!("Error: {}", e);
+        }
+    }));
+}
+
+///Legge il file <i>name</i> scritto dalle versioni precedenti nella cartella corrente, per i file di configurazione
+///che non erano in formato json. Una volta salvato il contenuto nel nuovo formato, il file deve essere cancellato con
+///<i>remove_legacy()</i>.
+pub fn read_legacy(name: &str) -> Option<String> {
+    std::fs::read_to_string(name).ok()
+}
+
+pub fn remove_legacy(name: &str) {
+    let _ = std::fs::remove_file(name);
+}
+
+///Cartella che contiene l'eseguibile.
+fn exe_dir() -> Option<PathBuf> {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(Path::to_path_buf))
+}
+
+///Calcola la cartella di configurazione per <i>location</i>; <i>env</i> ritorna il valore di una variabile d'ambiente
+///e <i>exe_dir</i> è la cartella che contiene l'eseguibile.
+fn resolve(
+    location: &ConfigLocation,
+    env: impl Fn(&str) -> Option<String>,
+    exe_dir: Option<PathBuf>,
+) -> PathBuf {
+    let portable = exe_dir.as_ref().map(|d| d.join("config"));
+    match location {
+        ConfigLocation::Dir(dir) => dir.clone(),
+        ConfigLocation::Portable => portable.unwrap_or_else(|| PathBuf::from(".")),
+        ConfigLocation::Default => {
+            if let Some(dir) = exe_dir.filter(|d| d.join(PORTABLE_MARKER).is_file()) {
+                return dir.join("config");
+            }
+            //sono ignorati i valori vuoti o relativi, come richiesto dalla specifica XDG
+            let var = |name: &str| env(name).map(PathBuf::from).filter(|p| p.is_absolute());
+            let base = var("XDG_CONFIG_HOME")
+                .or_else(|| if cfg!(windows) { var("APPDATA") } else { None })
+                .or_else(|| var("HOME").map(|home| home.join(".config")));
+            match base {
+                Some(base) => base.join(APP_DIR_NAME),
+                None => PathBuf::from("."),
+            }
+        }
+    }
+}
+
+///Legge <i>T</i> dalla cartella <i>dir</i>; se il file non esiste, cerca quello delle versioni precedenti in
+///<i>legacy_dir</i> e, se lo trova, lo sposta in <i>dir</i>.
+fn load_from<T: ConfigFile>(dir: &Path, legacy_dir: &Path) -> Result<Option<T>, String> {
+    let path = dir.join(T::FILE_NAME);
+    let legacy = legacy_dir.join(T::LEGACY_FILE_NAME);
+    match std::fs::read_to_string(&path) {
+        Ok(text) => from_json(&text)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(_) => match std::fs::read_to_string(&legacy) {
+            Ok(text) => {
+                let value: T =
+                    from_json(&text).map_err(|e| format!("{}: {}", legacy.display(), e))?;
+                if save_to(dir, &value).is_ok() {
+                    let _ = std::fs::remove_file(&legacy);
+                }
+                Ok(Some(value))
+            }
+            Err(_) => Ok(None),
+        },
+    }
+}
+
+///Scrive <i>value</i> nella cartella <i>dir</i>, creandola se non esiste.<br>
+///Il file temporaneo ha un nome univoco (id del processo e contatore), in modo che due scritture dello stesso file,
+///anche da processi diversi, non si sovrascrivano a vicenda; se la scrittura fallisce, viene cancellato.
+fn save_to<T: ConfigFile>(dir: &Path, value: &T) -> Result<(), String> {
+    let path = dir.join(T::FILE_NAME);
+    let tmp = dir.join(format!(
+        "{}.{}-{}.tmp",
+        T::FILE_NAME,
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let res = std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::File::create(&tmp))
+        .and_then(|mut file| {
+            file.write_all(to_json(value)?.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&tmp, &path));
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    res.map_err(|e| format!("unable to write {}: {}", path.display(), e))
+}
+
+///Legge il documento <i>text</i>, aggiornandolo alla versione corrente dello schema se necessario.
+pub fn from_json<T: ConfigFile>(text: &str) -> Result<T, String> {
+    let mut doc: serde_json::Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let version = match doc.as_object_mut().and_then(|o| o.remove("version")) {
+        None => 0,
+        Some(v) => v.as_u64().ok_or_else(|| "invalid version".to_string())? as u32,
+    };
+    if version > T::VERSION {
+        Err(format!("unsupported version {}", version))
+    } else if version < T::VERSION {
+        T::migrate(version, doc)
+    } else {
+        serde_json::from_value(doc).map_err(|e| e.to_string())
+    }
+}
+
+///Serializza <i>value</i>, aggiungendo il campo <i>version</i>.
+pub fn to_json<T: ConfigFile>(value: &T) -> std::io::Result<String> {
+    let mut doc = serde_json::to_value(value)?;
+    if let Some(o) = doc.as_object_mut() {
+        o.insert("version".to_string(), T::VERSION.into());
+    }
+    Ok(serde_json::to_string_pretty(&doc)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestConfig {
+        name: String,
+        size: u32,
+    }
+
+    impl ConfigFile for TestConfig {
+        const FILE_NAME: &'static str = "test.json";
+        const LEGACY_FILE_NAME: &'static str = ".config_test.json";
+        const VERSION: u32 = 2;
+
+        fn migrate(version: u32, mut doc: serde_json::Value) -> Result<Self, String> {
+            //nella versione 1 il campo size si chiamava width
+            if version == 1 {
+                let width = doc["width"].take();
+                doc["size"] = width;
+            }
+            serde_json::from_value(doc).map_err(|e| e.to_string())
+        }
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("project-m1-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn resolve_test() {
+        let env = |var: &str| match var {
+            "XDG_CONFIG_HOME" => Some("/xdg".to_string()),
+            "HOME" => Some("/home/user".to_string()),
+            _ => None,
+        };
+        let exe = Some(PathBuf::from("/opt/app"));
+        assert_eq!(
+            resolve(&ConfigLocation::Default, env, exe.clone()),
+            PathBuf::from("/xdg/project-m1")
+        );
+        //XDG_CONFIG_HOME vuoto: viene usata la cartella .config della home
+        let no_xdg = |var: &str| env(var).filter(|_| var == "HOME");
+        assert_eq!(
+            resolve(&ConfigLocation::Default, no_xdg, exe.clone()),
+            PathBuf::from("/home/user/.config/project-m1")
+        );
+        assert_eq!(
+            resolve(&ConfigLocation::Portable, env, exe.clone()),
+            PathBuf::from("/opt/app/config")
+        );
+        assert_eq!(
+            resolve(&ConfigLocation::Dir(PathBuf::from("/tmp/conf")), env, exe),
+            PathBuf::from("/tmp/conf")
+        );
+    }
+
+    #[test]
+    fn load_save_test() {
+        let dir = test_dir("load-save");
+        assert_eq!(load_from::<TestConfig>(&dir, &dir), Ok(None));
+
+        let value = TestConfig {
+            name: "a".to_string(),
+            size: 3,
+        };
+        save_to(&dir, &value).unwrap();
+        //il file temporaneo viene rinominato: nella cartella resta solo il file di configurazione
+        let names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["test.json"]);
+        let text = std::fs::read_to_string(dir.join("test.json")).unwrap();
+        assert!(text.contains("\"version\": 2"));
+        assert_eq!(load_from::<TestConfig>(&dir, &dir), Ok(Some(value)));
+
+        assert_eq!(
+            from_json::<TestConfig>(r#"{"version":1,"name":"b","width":4}"#),
+            Ok(TestConfig {
+                name: "b".to_string(),
+                size: 4
+            })
+        );
+        assert!(from_json::<TestConfig>(r#"{"version":3,"name":"b","size":4}"#).is_err());
+        assert!(from_json::<TestConfig>("not json").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn concurrent_save_test() {
+        let dir = test_dir("concurrent");
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let dir = dir.clone();
+                std::thread::spawn(move || {
+                    let value = TestConfig {
+                        name: "x".repeat(i * 100),
+                        size: i as u32,
+                    };
+                    save_to(&dir, &value)
+                })
+            })
+            .collect();
+        for h in handles {
+            assert_eq!(h.join().unwrap(), Ok(()));
+        }
+        //il file finale è quello completo di una delle scritture
+        let value = load_from::<TestConfig>(&dir, &dir).unwrap().unwrap();
+        assert_eq!(value.name.len(), value.size as usize * 100);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn legacy_migration_test() {
+        let (dir, legacy_dir) = (test_dir("migrated"), test_dir("legacy"));
+        std::fs::create_dir_all(&legacy_dir).unwrap();
+        std::fs::write(
+            legacy_dir.join(".config_test.json"),
+            r#"{"name":"old","size":7}"#,
+        )
+        .unwrap();
+
+        let expected = TestConfig {
+            name: "old".to_string(),
+            size: 7,
+        };
+        assert_eq!(
+            load_from::<TestConfig>(&dir, &legacy_dir),
+            Ok(Some(expected))
+        );
+        assert!(!legacy_dir.join(".config_test.json").exists());
+        assert!(dir.join("test.json").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+        let _ = std::fs::remove_dir_all(&legacy_dir);
+    }
+}
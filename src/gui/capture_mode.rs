@@ -1,38 +1,124 @@
-use super::screens_manager::ScreensManager;
+use super::screens_manager::{virtual_desktop_infos, ScreenIcon, ScreensManager};
 use eframe::egui::{self, ScrollArea, vec2};
 extern crate image;
-use super::super::itc::{Delay, ScreenshotDim};
-use eframe::egui::ColorImage;
+use super::super::itc::{
+    Delay, LastCapture, RecordingSettings, ScreenshotDim, TimelapseSettings, WindowCaptureSettings, WindowTarget,
+};
+use crate::image_coding::{AnimationFormat, DiffSettings, ImageFormat};
+use crate::region_presets::{RegionPreset, RegionPresets};
+use crate::window_capture::{start_thread_list_windows, WindowInfo};
+use eframe::egui::{Color32, ColorImage};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::mpsc::{Receiver, TryRecvError};
 use std::sync::Arc;
 
 ///Stato della parte di interfaccia con la funzione di selezionare la modalità di cattura e avviarla. (schermata home)
 pub struct CaptureMode {
     area: ScreenshotDim,
     delay: Delay,
+    ///Se true, alla pressione del bottone viene avviata una registrazione animata invece di uno screenshot.
+    recording: bool,
+    recording_settings: RecordingSettings,
+    ///Se true, alla pressione del bottone viene avviata una timelapse: catture periodiche salvate direttamente su file.
+    timelapse: bool,
+    timelapse_settings: TimelapseSettings,
     screens_mgr: Arc<ScreensManager>,
+    ///Finestra da catturare e inclusione delle decorazioni, usate se l'area selezionata è <i>ScreenshotDim::Window</i>.
+    window_settings: WindowCaptureSettings,
+    ///Ultimo elenco delle finestre ricevuto, oppure il messaggio di errore.
+    windows: Result<Vec<WindowInfo>, &'static str>,
+    ///Se != None, è in corso l'aggiornamento dell'elenco delle finestre.
+    windows_receiver: Option<Receiver<Result<Vec<WindowInfo>, &'static str>>>,
+    ///Preset di regione salvati, condivisi con lo stato globale dell'applicazione.
+    region_presets: Rc<RefCell<RegionPresets>>,
+    ///Posizione del preset da catturare, usata se l'area selezionata è <i>ScreenshotDim::Region</i>.
+    region_preset: usize,
+    ///Bozza del preset in fase di creazione.
+    new_preset: RegionPreset,
+    ///Errore dell'ultimo tentativo di aggiungere <i>new_preset</i>.
+    preset_error: Option<&'static str>,
+    ///Ultima cattura eseguita, condivisa con lo stato globale dell'applicazione.
+    last_capture: Rc<RefCell<Option<LastCapture>>>,
+    ///Se true, l'utente ha premuto il bottone "Repeat last capture".
+    repeat_requested: bool,
+    ///Se true, negli screenshot viene incluso il puntatore del mouse. Condiviso con lo stato globale dell'applicazione,
+    ///così da valere anche per le catture avviate con le hotkeys.
+    include_cursor: Rc<Cell<bool>>,
 }
 impl CaptureMode {
-    pub fn new(screens_mgr: Arc<ScreensManager>) -> Self {
+    pub fn new(
+        screens_mgr: Arc<ScreensManager>,
+        region_presets: Rc<RefCell<RegionPresets>>,
+        last_capture: Rc<RefCell<Option<LastCapture>>>,
+        include_cursor: Rc<Cell<bool>>,
+    ) -> Self {
+        let new_preset = RegionPreset {
+            name: String::new(),
+            screen_id: screens_mgr.get_current_screen_infos().map(|di| di.id).unwrap_or(0),
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 100,
+        };
+        let region_preset = region_presets.borrow().list().next().map_or(0, |(i, _)| i);
         Self {
             area: ScreenshotDim::Fullscreen,
             delay: Delay {
                 delayed: false,
                 scalar: 0.0,
             },
+            recording: false,
+            recording_settings: RecordingSettings::default(),
+            timelapse: false,
+            timelapse_settings: TimelapseSettings::default(),
             screens_mgr,
+            window_settings: WindowCaptureSettings::default(),
+            windows: Ok(vec![]),
+            windows_receiver: None,
+            region_presets,
+            region_preset,
+            new_preset,
+            preset_error: None,
+            last_capture,
+            repeat_requested: false,
+            include_cursor,
         }
     }
 
-    ///Ritorna Some(ScreenshotDim, f64) se l'utente ha premuto il bottone "Acquire"
+    ///Ritorna true (una sola volta) se dall'ultima chiamata l'utente ha premuto il bottone "Repeat last capture".
+    pub fn repeat_requested(&mut self) -> bool {
+        std::mem::take(&mut self.repeat_requested)
+    }
+
+    ///Preset da catturare quando <i>update()</i> ritorna <i>ScreenshotDim::Region</i>. None se non esistono preset.
+    pub fn region_preset(&self) -> Option<RegionPreset> {
+        self.region_presets.borrow().get(self.region_preset).cloned()
+    }
+
+    ///Parametri della timelapse, se l'utente l'ha abilitata: in tal caso, il valore ritornato da <i>update()</i> indica
+    ///l'area e il delay della timelapse invece che di uno screenshot.
+    pub fn timelapse_settings(&self) -> Option<TimelapseSettings> {
+        self.timelapse.then_some(self.timelapse_settings)
+    }
+
+    ///Parametri della cattura di una finestra, da usare quando <i>update()</i> ritorna <i>ScreenshotDim::Window</i>.
+    pub fn window_settings(&self) -> WindowCaptureSettings {
+        self.window_settings
+    }
+
+    ///Ritorna Some(ScreenshotDim, f64, Option<RecordingSettings>) se l'utente ha premuto il bottone "Acquire" (o "Record")
     /// - ScreenshotDim è la modalità di selezione dell'area coinvolta nello screenshot;<br>
-    /// - f64 sono i secondi di delay impostati.<br>
+    /// - f64 sono i secondi di delay impostati;<br>
+    /// - Option<RecordingSettings> contiene i parametri della registrazione, se è stata richiesta una registrazione
+    ///   animata invece di uno screenshot.<br>
     /// Non è necessario che il metodo ritorni anche indicazione sullo schermo selezionato,
     /// perché l'informazione viene già memorizzata dentro alla variabile di tipo Arc<ScreensManager>, unica a livello di applicazione.
     pub fn update(
         &mut self,
         ui: &mut egui::Ui,
         ctx: &egui::Context,
-    ) -> Option<(ScreenshotDim, f64)> {
+    ) -> Option<(ScreenshotDim, f64, Option<RecordingSettings>)> {
         let mut ret = None;
 
         ScrollArea::new([true, false]).show(ui, |ui| {
@@ -53,14 +139,28 @@ impl CaptureMode {
                                 ui.set_min_width(60.0);
                                 ui.selectable_value(&mut self.area, ScreenshotDim::Fullscreen, "Full Screen");
                                 ui.selectable_value(&mut self.area, ScreenshotDim::Rectangle, "Rectangle");
+                                //le registrazioni e le timelapse non supportano la cattura di una finestra o di un preset
+                                if !self.recording && !self.timelapse {
+                                    if ui.selectable_value(&mut self.area, ScreenshotDim::Window, "Window").clicked() {
+                                        self.refresh_windows();
+                                    }
+                                    ui.selectable_value(&mut self.area, ScreenshotDim::Region, "Region");
+                                }
                             });
                         ui.label("❓")
-                            .on_hover_text("Choose if you want to select a restricted area (Rectangle) or not (Fullscreen).");
+                            .on_hover_text("Choose if you want to select a restricted area (Rectangle), a single window (Window), a saved region (Region) or not (Fullscreen).");
                     //});
 
 
                         ui.end_row();
 
+                        if self.area == ScreenshotDim::Window {
+                            self.window_rows(ui);
+                        }
+                        if self.area == ScreenshotDim::Region {
+                            self.region_rows(ui);
+                        }
+
                         self.screens_combobox(ui, self.screens_mgr.clone(), ctx);
 
                         ui.end_row();
@@ -72,11 +172,28 @@ impl CaptureMode {
                             if self.delay.delayed {
                                 ui.add(egui::Slider::new(&mut self.delay.scalar, 0.0..=5.0));
                             }
+
+                        //le registrazioni e le timelapse non includono il puntatore
+                        if !self.recording && !self.timelapse {
+                            ui.end_row();
+                            ui.label("Cursor:");
+                            let mut include_cursor = self.include_cursor.get();
+                            if ui
+                                .add(egui::Checkbox::new(&mut include_cursor, "  "))
+                                .on_hover_text("To include the mouse pointer in the screenshot (X11 only)")
+                                .changed()
+                            {
+                                self.include_cursor.set(include_cursor);
+                            }
+                        }
              
 
 
                         ui.end_row();
 
+                        self.recording_rows(ui);
+                        self.timelapse_rows(ui);
+
                     });
 
 
@@ -84,28 +201,356 @@ impl CaptureMode {
            
             ui.style_mut().visuals.widgets.hovered.weak_bg_fill = egui::Color32::from_rgb(0,140,250);
 // gestione della pressione del pulsante "Acquire": la funzione ritorna Some(..) al posto di None
+            ui.horizontal(|ui| {
+                if self.recording {
+                    if ui
+                        .button("Record")
+                        .on_hover_text("The recording can be stopped earlier with the Stop button or its hotkey")
+                        .clicked()
+                    {
+                        ret = Some((self.area.clone(), self.delay.scalar, Some(self.recording_settings)));
+                    }
+                } else if self.timelapse {
+                    if ui
+                        .button("Start timelapse")
+                        .on_hover_text("The shots are saved in the default directory, named as set in the save settings")
+                        .clicked()
+                    {
+                        ret = Some((self.area.clone(), self.delay.scalar, None));
+                    }
+                } else if ui
+                    .add_enabled(
+                        self.area != ScreenshotDim::Region || self.region_preset().is_some(),
+                        egui::Button::new("Acquire"),
+                    )
+                    .on_hover_text(
+                        "After acquisition, the image can be automatically copied to the clipboard",
+                    )
+                    .clicked()
+                {
+                    ret = Some((self.area.clone(), self.delay.scalar, None));
+                }
+
+                let last = self.last_capture.borrow().as_ref().map(|l| l.description());
+                if ui
+                    .add_enabled(last.is_some(), egui::Button::new("Repeat last capture"))
+                    .on_hover_text(last.unwrap_or_default())
+                    .on_disabled_hover_text("No capture to repeat")
+                    .clicked()
+                {
+                    self.repeat_requested = true;
+                }
+            });
+
+        });
+        ret
+    }
+
+    /// Righe della griglia dedicate alla registrazione animata: una checkbox per attivarla e, se attiva,
+    /// i controlli per frame al secondo, durata massima e formato di output.
+    fn recording_rows(&mut self, ui: &mut egui::Ui) {
+        ui.label("Record:");
+        if ui
+            .add(egui::Checkbox::new(&mut self.recording, "  "))
+            .on_hover_text("To record an animation instead of taking a screenshot")
+            .changed()
+            && self.recording
+        {
+            self.timelapse = false;
+            if matches!(self.area, ScreenshotDim::Window | ScreenshotDim::Region) {
+                self.area = ScreenshotDim::Fullscreen;
+            }
+        }
+        ui.end_row();
+
+        if self.recording {
+            ui.label("Frames per second:");
+            ui.add(egui::Slider::new(&mut self.recording_settings.fps, 1..=30));
+            ui.end_row();
+
+            ui.label("Max duration (s):");
+            ui.add(egui::Slider::new(&mut self.recording_settings.duration, 1.0..=60.0));
+            ui.end_row();
+
+            ui.label("Animation format:");
+            egui::ComboBox::from_id_source("animation_format")
+                .selected_text(<AnimationFormat as Into<&str>>::into(self.recording_settings.format))
+                .show_ui(ui, |ui| {
+                    for f in AnimationFormat::available_formats() {
+                        ui.selectable_value(
+                            &mut self.recording_settings.format,
+                            f,
+                            <AnimationFormat as Into<&str>>::into(f),
+                        );
+                    }
+                });
+            ui.end_row();
+        }
+    }
+
+    /// Righe della griglia dedicate alla timelapse: una checkbox per attivarla (in alternativa alla registrazione) e,
+    /// se attiva, i controlli per intervallo tra gli scatti, numero massimo di scatti, scarto degli scatti identici al
+    /// precedente e formato dei file.<br>
+    /// Una seconda checkbox trasforma la timelapse nella sorveglianza dei cambiamenti dello schermo: in tal caso lo
+    /// scarto degli scatti identici è sostituito dai parametri del confronto (soglia e dimensione dei blocchi).
+    fn timelapse_rows(&mut self, ui: &mut egui::Ui) {
+        ui.label("Timelapse:");
+        if ui
+            .add(egui::Checkbox::new(&mut self.timelapse, "  "))
+            .on_hover_text("To capture periodically and save each shot, instead of taking a single screenshot")
+            .changed()
+            && self.timelapse
+        {
+            self.recording = false;
+            if matches!(self.area, ScreenshotDim::Window | ScreenshotDim::Region) {
+                self.area = ScreenshotDim::Fullscreen;
+            }
+        }
+        ui.end_row();
+
+        if self.timelapse {
+            ui.label("Interval (s):");
+            ui.add(egui::Slider::new(&mut self.timelapse_settings.interval, 1.0..=3600.0).logarithmic(true));
+            ui.end_row();
+
+            ui.label("Max shots:");
+            ui.add(egui::Slider::new(&mut self.timelapse_settings.max_shots, 1..=10000).logarithmic(true));
+            ui.end_row();
+
+            ui.label("Watch changes:");
+            let mut watch = self.timelapse_settings.watch.is_some();
             if ui
-                .button("Acquire")
-                .on_hover_text(
-                    "After acquisition, the image can be automatically copied to the clipboard",
-                )
-                .clicked()
+                .add(egui::Checkbox::new(&mut watch, "  "))
+                .on_hover_text("To save a shot only when the content changed since the last saved one")
+                .changed()
             {
-                ret = Some((self.area.clone(), self.delay.scalar));
+                self.timelapse_settings.watch = watch.then(DiffSettings::default);
             }
+            ui.end_row();
 
+            match &mut self.timelapse_settings.watch {
+                Some(diff) => {
+                    ui.label("Change threshold:");
+                    ui.add(egui::Slider::new(&mut diff.threshold, 1..=255)).on_hover_text(
+                        "Minimum difference of a color channel, averaged over a block, to count the block as changed",
+                    );
+                    ui.end_row();
+
+                    ui.label("Block size (px):");
+                    ui.add(egui::Slider::new(&mut diff.block_size, 1..=64))
+                        .on_hover_text("The shots are compared block by block: larger blocks ignore smaller changes");
+                    ui.end_row();
+                }
+                None => {
+                    ui.label("Skip identical:");
+                    ui.add(egui::Checkbox::new(&mut self.timelapse_settings.skip_identical, "  "))
+                        .on_hover_text("To discard the shots identical to the previous one: they are not counted");
+                    ui.end_row();
+                }
+            }
+
+            ui.label("Image format:");
+            egui::ComboBox::from_id_source("timelapse_format")
+                .selected_text(<ImageFormat as Into<&str>>::into(self.timelapse_settings.format))
+                .show_ui(ui, |ui| {
+                    for f in ImageFormat::available_formats() {
+                        ui.selectable_value(
+                            &mut self.timelapse_settings.format,
+                            f,
+                            <ImageFormat as Into<&str>>::into(f),
+                        );
+                    }
+                });
+            ui.end_row();
+        }
+    }
+
+    /// Lancia il thread che aggiorna l'elenco delle finestre mostrato da <i>window_rows()</i>.
+    fn refresh_windows(&mut self) {
+        self.windows_receiver = Some(start_thread_list_windows());
+    }
+
+    /// Righe della griglia dedicate alla cattura di una finestra: una combobox per scegliere la finestra dall'elenco
+    /// (oppure con un click, al momento della cattura), con il bottone per aggiornare l'elenco, e una checkbox per
+    /// includere le decorazioni.<br>
+    /// Finché l'elenco è in aggiornamento, al suo posto viene mostrato uno spinner; se l'aggiornamento fallisce, viene
+    /// mostrato il messaggio di errore.
+    fn window_rows(&mut self, ui: &mut egui::Ui) {
+        if let Some(rx) = &self.windows_receiver {
+            match rx.try_recv() {
+                Ok(windows) => {
+                    self.windows = windows;
+                    self.windows_receiver = None;
+                }
+                Err(TryRecvError::Empty) => (),
+                Err(TryRecvError::Disconnected) => {
+                    self.windows = Err("Error: unable to list the windows");
+                    self.windows_receiver = None;
+                }
+            }
+        }
+
+        ui.label("Window:");
+        let selected_text = match self.window_settings.target {
+            WindowTarget::Pick => "Pick by clicking".to_string(),
+            WindowTarget::Id(id) => match &self.windows {
+                Ok(windows) => windows
+                    .iter()
+                    .find(|w| w.id == id)
+                    .map(|w| w.label())
+                    .unwrap_or_else(|| format!("{:#x}", id)),
+                Err(_) => format!("{:#x}", id),
+            },
+        };
+        egui::ComboBox::from_id_source("window_target")
+            .selected_text(selected_text)
+            .width(200.0)
+            .show_ui(ui, |ui| {
+                ui.style_mut().wrap = Some(false);
+                ui.selectable_value(&mut self.window_settings.target, WindowTarget::Pick, "Pick by clicking")
+                    .on_hover_text("Click on the window to capture after the timer; press any key to cancel");
+                if self.windows_receiver.is_some() {
+                    ui.spinner();
+                    return;
+                }
+                match &self.windows {
+                    Ok(windows) => {
+                        for w in windows {
+                            ui.selectable_value(&mut self.window_settings.target, WindowTarget::Id(w.id), w.label());
+                        }
+                    }
+                    Err(error_message) => {
+                        ui.label(*error_message);
+                    }
+                }
+            });
+        if ui.button("↺").on_hover_text("Refresh").clicked() {
+            self.refresh_windows();
+        }
+        ui.end_row();
+
+        ui.label("Decorations:");
+        ui.add(egui::Checkbox::new(&mut self.window_settings.decorations, "  "))
+            .on_hover_text("To include the title bar and the borders drawn by the window manager");
+        ui.end_row();
+    }
+
+    /// Righe della griglia dedicate ai preset di regione:
+    /// - una combobox per scegliere il preset da catturare, con un bottone per eliminarlo;
+    /// - un form per creare un nuovo preset, digitando nome, schermo e rettangolo (in pixel fisici), oppure partendo
+    ///   dall'area dell'ultimo screenshot rettangolare.
+    ///
+    /// Le modifiche ai preset vengono salvate subito nel file di configurazione.
+    fn region_rows(&mut self, ui: &mut egui::Ui) {
+        let mut presets = self.region_presets.borrow_mut();
+
+        ui.label("Preset:");
+        let selected_text = match presets.get(self.region_preset) {
+            Some(p) => format!("{}. {}", self.region_preset + 1, p.label()),
+            None => "No preset".to_string(),
+        };
+        egui::ComboBox::from_id_source("region_preset")
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                ui.style_mut().wrap = Some(false);
+                for (i, p) in presets.list() {
+                    //la numerazione corrisponde a quella delle hotkeys "Region preset N"
+                    ui.selectable_value(&mut self.region_preset, i, format!("{}. {}", i + 1, p.label()));
+                }
+            });
+        if ui.button("🗑").on_hover_text("Delete preset").clicked()
+            && presets.get(self.region_preset).is_some()
+        {
+            presets.remove(self.region_preset);
+            presets.start_thread_serialize();
+            self.region_preset = presets.list().next().map_or(0, |(i, _)| i);
+        }
+        ui.end_row();
+
+        ui.label("New preset:");
+        ui.add(egui::TextEdit::singleline(&mut self.new_preset.name).hint_text("Name").desired_width(120.0));
+        if ui
+            .add_enabled(presets.get_last_selection().is_some(), egui::Button::new("Use last selection"))
+            .on_hover_text("Fill in the area of the last Rectangle screenshot")
+            .clicked()
+        {
+            if let Some(last) = presets.get_last_selection() {
+                self.new_preset = RegionPreset {
+                    name: std::mem::take(&mut self.new_preset.name),
+                    ..last.clone()
+                };
+            }
+        }
+        ui.end_row();
+
+        ui.label("");
+        ui.horizontal(|ui| {
+            ui.label("x");
+            ui.add(egui::DragValue::new(&mut self.new_preset.x).clamp_range(0..=u16::MAX));
+            ui.label("y");
+            ui.add(egui::DragValue::new(&mut self.new_preset.y).clamp_range(0..=u16::MAX));
+            ui.label("w");
+            ui.add(egui::DragValue::new(&mut self.new_preset.width).clamp_range(1..=u16::MAX));
+            ui.label("h");
+            ui.add(egui::DragValue::new(&mut self.new_preset.height).clamp_range(1..=u16::MAX));
         });
-        ret
+        ui.end_row();
+
+        ui.label("");
+        ui.horizontal(|ui| {
+            Self::preset_screen_combobox(ui, &self.screens_mgr, &mut self.new_preset.screen_id);
+            if ui.button("Add").clicked() {
+                match presets.add(self.new_preset.clone()) {
+                    Ok(i) => {
+                        presets.start_thread_serialize();
+                        self.region_preset = i;
+                        self.new_preset.name.clear();
+                        self.preset_error = None;
+                    }
+                    Err(e) => self.preset_error = Some(e),
+                }
+            }
+        });
+        if let Some(e) = self.preset_error {
+            ui.colored_label(Color32::RED, e);
+        }
+        ui.end_row();
+    }
+
+    /// Combobox per scegliere lo schermo di un nuovo preset, tra quelli rilevati dallo screen manager e, se sono più
+    /// di uno, il desktop virtuale (id 0).
+    fn preset_screen_combobox(ui: &mut egui::Ui, screens_manager: &Arc<ScreensManager>, screen_id: &mut u32) {
+        let screens: Vec<_> = match screens_manager.try_get_screens() {
+            Some(g) => g.iter().map(|s| s.0).collect(),
+            None => vec![],
+        };
+        let name = |id: u32| match screens.iter().position(|di| di.id == id) {
+            Some(i) => format!("Screen {}", i + 1),
+            None if id == 0 => "All screens".to_string(),
+            None => format!("Screen id {}", id),
+        };
+        egui::ComboBox::from_id_source("preset_screen")
+            .selected_text(name(*screen_id))
+            .show_ui(ui, |ui| {
+                ui.style_mut().wrap = Some(false);
+                for di in screens.iter() {
+                    ui.selectable_value(screen_id, di.id, format!("{} ({}x{})", name(di.id), di.width, di.height));
+                }
+                if screens.len() > 1 {
+                    ui.selectable_value(screen_id, 0, name(0));
+                }
+            });
     }
 
     /// Combobox che mostra l'elenco di screen messo a disposizione dallo screen manager.<br>
     /// Si itera su ogni schermo, ottenendo le info da visualizzare ed eseguendo try_lock()
     /// sul mutex che contiene l'icona dello screen.<br>
-    /// Se l'icona è ancora in caricamento (la rispettiva Option contiene None), oppure try_lock()
+    /// Se l'icona è ancora in caricamento (<i>ScreenIcon::Loading</i>), oppure try_lock()
     /// fallisce, allora viene mostrato uno spinner al posto dell'icona nella corrispondente
-    /// entry della combobox.<br>
+    /// entry della combobox; se la cattura dello schermo è fallita, un segnaposto con il messaggio di errore.<br>
     /// Una selezione su questa combobox scatena la modifica dello screen che lo screen manager
     /// etichetta come "selected".<br>
+    /// Se sono presenti più schermi, l'ultima voce permette di selezionarli tutti, per catturare l'intero desktop virtuale.<br>
     /// Esiste un bottone per chiedere il refresh dell'intera lista di screen allo screen manager.
     /// Se la combobox viene aperta mentre la lista è ancora in aggiornamento, la chiamata a
     ///<i>try_get_screens()</i>, che nasconde un <i>try_lock()</i>, fallisce: viene visualizzato uno spinner.
@@ -118,10 +563,14 @@ impl CaptureMode {
         
             ui.label("Screen:");
             egui::ComboBox::from_label(" ") 
-                .selected_text(format!(
-                    "{:?}",
-                    screens_manager.get_current_screen_index() + 1  //per avere numerazione a partire da 1, più intuitiva per l'utente 
-                ))
+                .selected_text(if screens_manager.is_all_screens_selected() {
+                    "All".to_string()
+                } else {
+                    format!(
+                        "{:?}",
+                        screens_manager.get_current_screen_index() + 1  //per avere numerazione a partire da 1, più intuitiva per l'utente 
+                    )
+                })
                 .show_ui(ui, |ui| {
                     ui.style_mut().wrap = Some(false);
                     ui.set_min_width(60.0);
@@ -129,12 +578,12 @@ impl CaptureMode {
                         Some(g) => 
                         {
                             for (i, s) in g.iter().enumerate() {
-                                let di = s.0.display_info;
+                                let di = s.0;
                                 let str = format!("{} ({}x{})", i + 1, di.width, di.height);
         
                                 ui.horizontal(|ui| {
-                                    if let Ok(guard) = s.1.try_lock() {
-                                        if let Some(rgba) = guard.clone() {
+                                    match s.1.try_lock().as_deref() {
+                                        Ok(ScreenIcon::Ready(rgba)) => {
                                             let txt = ctx.load_texture(
                                                 "icon",
                                                 ColorImage::from_rgba_unmultiplied(
@@ -144,18 +593,35 @@ impl CaptureMode {
                                                 Default::default(),
                                             );
                                             ui.image(txt.id(), txt.size_vec2());
-                                        } else {
+                                        }
+                                        Ok(ScreenIcon::Failed(msg)) => {
+                                            ui.label("⚠").on_hover_text(*msg);
+                                        }
+                                        _ => {
                                             ui.spinner();
                                         }
-                                    } else {
-                                        ui.spinner();
                                     }
         
-                                    let mut curr = screens_manager.get_current_screen_index();
-                                    ui.selectable_value(&mut curr, i, &str);
-                                    screens_manager.select_screen(curr);
+                                    let selected = !screens_manager.is_all_screens_selected()
+                                        && screens_manager.get_current_screen_index() == i;
+                                    if ui.selectable_label(selected, &str).clicked() {
+                                        screens_manager.select_screen(i);
+                                    }
                                 });
                             }
+                            //voce per catturare l'intero desktop virtuale, utile solo con più schermi
+                            if g.len() > 1 {
+                                let infos: Vec<_> = g.iter().map(|s| s.0).collect();
+                                if let Some(di) = virtual_desktop_infos(&infos) {
+                                    let str = format!("All screens ({}x{})", di.width, di.height);
+                                    if ui
+                                        .selectable_label(screens_manager.is_all_screens_selected(), str)
+                                        .clicked()
+                                    {
+                                        screens_manager.select_all_screens();
+                                    }
+                                }
+                            }
                         },
                         None =>  {ui.spinner();}
                     }
@@ -163,7 +629,7 @@ impl CaptureMode {
                 });
 
             if ui.button("↺").on_hover_text("Refresh").clicked() {
-                screens_manager.update_available_screens();
+                screens_manager.refresh_screens();
             }
         
     }
@@ -2,7 +2,8 @@ pub mod utils;
 
 use crate::gui::edit_image::utils::{color_ui, create_line, shape_ui, stroke_preview, width_ui};
 use crate::gui::loading::show_loading;
-use crate::image_coding::{self, ImageFormat};
+use crate::image_coding::{self, ImageFormat, TextExport};
+use crate::window_capture::CursorImage;
 use eframe::egui::{
     pos2, vec2, Align, CentralPanel, Color32, ColorImage, Context, InnerResponse, Key, Layout,
     Painter, Pos2, Rect, Response, Rounding, Sense, Shape, Stroke, TextureHandle, TextureOptions,
@@ -12,6 +13,7 @@ use eframe::egui::{ComboBox, CursorIcon};
 use image::imageops::crop_imm;
 use image::RgbaImage;
 use imageproc::drawing::Blend;
+use std::path::PathBuf;
 use std::sync::mpsc::{channel, Receiver, TryRecvError};
 use std::thread;
 use utils::{
@@ -24,12 +26,22 @@ use utils::{
 /// Lo stato Nil indica che non e' stato premuto nessuno dei due pulsanti
 /// Lo stato Aborted indica che e' stato premuto il pulsante Abort
 /// Lo stato Saved indica che e' stato premuto il pulsante Save; in questo caso, verrà ritornata l'immagine da salvare
-/// (`RgbaImage`), e il suo formato (`ImageFormat`)
+/// (`RgbaImage`), e il suo formato (`ImageFormat`). Se e' stato premuto il pulsante Overwrite, `overwrite` contiene
+/// il path del file da cui era stata aperta l'immagine, che deve essere sovrascritto. `cut` e' il rettangolo
+/// (x, y, larghezza, altezza) dell'immagine originale a cui l'immagine e' stata ritagliata, None se non e' stata ritagliata
+/// Lo stato CopyAsText indica che e' stata scelta una delle voci del menu "Copy as": l'immagine (`RgbaImage`) va
+/// copiata nella clipboard come testo nel formato `export`, senza uscire dall'editor
 pub enum FrameEvent {
     Saved {
         image: RgbaImage,
         format: ImageFormat,
         clipboard_receiver: Receiver<Result<(), arboard::Error>>,
+        overwrite: Option<PathBuf>,
+        cut: Option<(u32, u32, u32, u32)>,
+    },
+    CopyAsText {
+        image: RgbaImage,
+        export: TextExport,
     },
     Aborted,
     Nil,
@@ -87,12 +99,30 @@ pub struct EditImage {
     annotations: Vec<Shape>,
     scale_ratio: f32,
     receive_thread: Receiver<RgbaImage>,
+    /// path del file da cui e' stata aperta l'immagine, se non si tratta di uno screenshot appena acquisito
+    source_path: Option<PathBuf>,
+    /// indica se l'immagine in fase di elaborazione dovra' sovrascrivere il file `source_path`
+    overwrite: bool,
+    /// se Some, l'immagine in fase di elaborazione dovra' essere copiata come testo invece che salvata
+    copy_as: Option<TextExport>,
+    /// puntatore del mouse catturato assieme allo screenshot, con la texture usata per mostrarlo. Viene disegnato
+    /// sull'immagine solo al momento del salvataggio, e solo se `show_cursor` e' true
+    cursor: Option<(CursorImage, TextureHandle)>,
+    show_cursor: bool,
 }
 
 impl EditImage {
     /// crea una nuova istanza della schermata di modifica dello screenshot. Lo screenshot acquisito viene passato come
-    /// parametro.
-    pub fn new(rgba: RgbaImage, ctx: &Context) -> EditImage {
+    /// parametro. Se l'immagine e' stata aperta da un file, `source_path` ne contiene il path: in tal caso, il formato
+    /// selezionato inizialmente e' quello del file e viene offerta la possibilita' di sovrascriverlo.
+    /// Se e' stato catturato anche il puntatore del mouse (`cursor`), viene mostrato sopra l'immagine e puo' essere
+    /// nascosto prima del salvataggio.
+    pub fn new(
+        rgba: RgbaImage,
+        ctx: &Context,
+        source_path: Option<PathBuf>,
+        cursor: Option<CursorImage>,
+    ) -> EditImage {
         let texture_handle = ctx.load_texture(
             "screenshot_image",
             ColorImage::from_rgba_unmultiplied(
@@ -101,13 +131,29 @@ impl EditImage {
             ),
             TextureOptions::default(),
         );
+        let cursor = cursor.map(|c| {
+            let texture = ctx.load_texture(
+                "cursor_image",
+                ColorImage::from_rgba_unmultiplied(
+                    [c.image.width() as usize, c.image.height() as usize],
+                    c.image.as_raw(),
+                ),
+                TextureOptions::default(),
+            );
+            (c, texture)
+        });
         let (_, rx) = channel();
+        let format = source_path
+            .as_ref()
+            .and_then(|p| p.extension())
+            .and_then(|ext| ImageFormat::try_from(ext.to_str().unwrap_or_default()).ok())
+            .unwrap_or(ImageFormat::Png);
         EditImage {
             cut_rect: Rect::from_min_size(pos2(0.0, 0.0), texture_handle.size_vec2()),
             current_tool: Tool::Pen { line: Vec::new() },
             texture_handle,
             image: rgba,
-            format: ImageFormat::Png,
+            format,
             annotations: Vec::new(),
             scale_ratio: Default::default(),
             stroke: Stroke {
@@ -116,20 +162,65 @@ impl EditImage {
             },
             fill_shape: false,
             receive_thread: rx,
+            source_path,
+            overwrite: false,
+            copy_as: None,
+            cursor,
+            show_cursor: true,
+        }
+    }
+
+    /// formato con cui sovrascrivere il file di origine: quello selezionato, se compatibile con l'estensione del file
+    /// (ad esempio WebP lossy per un file .webp), altrimenti quello corrispondente all'estensione.
+    /// Ritorna None se l'immagine non e' stata aperta da un file o se l'estensione non e' supportata
+    fn overwrite_format(&self) -> Option<ImageFormat> {
+        let ext = self.source_path.as_ref()?.extension()?.to_str()?;
+        let source_format = ImageFormat::try_from(ext).ok()?;
+        if self.format.extension() == source_format.extension() {
+            Some(self.format)
+        } else {
+            Some(source_format)
         }
     }
 
+    /// rettangolo di ritaglio (x, y, larghezza, altezza) in pixel dell'immagine, come applicato da
+    /// `start_thread_render()`; None se comprende l'intera immagine
+    fn cut_area(&self) -> Option<(u32, u32, u32, u32)> {
+        let cut = (
+            self.cut_rect.left_top().x as u32,
+            self.cut_rect.left_top().y as u32,
+            self.cut_rect.width() as u32,
+            self.cut_rect.height() as u32,
+        );
+        (cut != (0, 0, self.image.width(), self.image.height())).then_some(cut)
+    }
+
     /// questa e' la funzione di ingresso. Ad ogni frame viene chiamata questa funzione che determina che cosa va
     /// disegnato sulla finestra
     pub fn update(&mut self, ctx: &Context, enabled: bool) -> FrameEvent {
         CentralPanel::default()
             .show(ctx, |ui| match self.receive_thread.try_recv() {
                 Ok(image) => {
+                    if let Some(export) = self.copy_as.take() {
+                        return FrameEvent::CopyAsText { image, export };
+                    }
                     let clipboard_receiver = image_coding::start_thread_copy_to_clipboard(&image);
-                    FrameEvent::Saved {
-                        image,
-                        format: self.format,
-                        clipboard_receiver,
+                    let cut = self.cut_area();
+                    match (self.overwrite, self.overwrite_format()) {
+                        (true, Some(format)) => FrameEvent::Saved {
+                            image,
+                            format,
+                            clipboard_receiver,
+                            overwrite: self.source_path.clone(),
+                            cut,
+                        },
+                        _ => FrameEvent::Saved {
+                            image,
+                            format: self.format,
+                            clipboard_receiver,
+                            overwrite: None,
+                            cut,
+                        },
                     }
                 }
                 Err(error) => match error {
@@ -173,11 +264,28 @@ impl EditImage {
             Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
             Color32::WHITE,
         );
+        self.draw_cursor(painter);
         self.draw_previous_annotations(painter);
         self.draw_current_annotation(painter);
         self.draw_cutting_region(painter);
     }
 
+    /// disegna il puntatore del mouse nella sua posizione, scalata come l'immagine
+    fn draw_cursor(&self, painter: &Painter) {
+        if let (Some((cursor, texture)), true) = (&self.cursor, self.show_cursor) {
+            let rect = Rect::from_min_size(
+                painter.clip_rect().min + vec2(cursor.x as f32, cursor.y as f32) * self.scale_ratio,
+                texture.size_vec2() * self.scale_ratio,
+            );
+            painter.image(
+                texture.id(),
+                rect,
+                Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+                Color32::WHITE,
+            );
+        }
+    }
+
     /// disegna la regione di ritaglio. Puo' essere bianca o gialla a seconda che stia venendo modificata o no.
     fn draw_cutting_region(&mut self, painter: &Painter) {
         if let Tool::Cut { .. } = self.current_tool {
@@ -541,12 +649,16 @@ impl EditImage {
             if let Tool::Rect { .. } | Tool::Circle { .. } = self.current_tool {
                 shape_ui(ui, &mut self.fill_shape);
             }
+            if self.cursor.is_some() {
+                ui.checkbox(&mut self.show_cursor, "Cursor")
+                    .on_hover_text("Include the mouse pointer in the saved image");
+            }
             self.draw_undo_clear(ui);
         });
         ret
     }
 
-    /// disegna i bottoni per salvare, tornare alla home e decidere il formato del file di salvataggio
+    /// disegna i bottoni per salvare, copiare come testo, tornare alla home e decidere il formato del file di salvataggio
     fn draw_save_ui(&mut self, ret: &mut FrameEvent, ui: &mut Ui) -> InnerResponse<()> {
         ui.with_layout(Layout::right_to_left(Align::TOP), |ui| {
             ui.style_mut().visuals.widgets.hovered.weak_bg_fill = Color32::RED;
@@ -563,30 +675,34 @@ impl EditImage {
                 .on_hover_text("The format will be the selected one.")
                 .clicked()
             {
-                let (tx, rx) = channel();
-                self.receive_thread = rx;
-                let annotations = self.annotations.clone();
-                let image = self.image.clone();
-                let cut_rect = self.cut_rect;
-                thread::spawn(move || {
-                    let mut image_blend = Blend(image);
-                    for annotation in annotations {
-                        write_annotation_to_image(&annotation, &mut image_blend);
-                    }
-                    tx.send(
-                        crop_imm(
-                            &image_blend.0,
-                            cut_rect.left_top().x as u32,
-                            cut_rect.left_top().y as u32,
-                            cut_rect.width() as u32,
-                            cut_rect.height() as u32,
-                        )
-                        .to_image(),
-                    )
-                });
+                self.overwrite = false;
+                self.copy_as = None;
+                self.start_thread_render();
+            }
+            if let (Some(path), Some(_)) = (&self.source_path, self.overwrite_format()) {
+                if ui
+                    .button("Overwrite 📝")
+                    .on_hover_text(format!("Save over {}", path.display()))
+                    .clicked()
+                {
+                    self.overwrite = true;
+                    self.copy_as = None;
+                    self.start_thread_render();
+                }
             }
+            ui.menu_button("Copy as 📋", |ui| {
+                for export in TextExport::available_exports() {
+                    if ui.button(<TextExport as Into<&str>>::into(export)).clicked() {
+                        self.copy_as = Some(export);
+                        self.start_thread_render();
+                        ui.close_menu();
+                    }
+                }
+            })
+            .response
+            .on_hover_text("Copy the image to the clipboard as text, to paste it into a Markdown or HTML document.");
             ComboBox::from_label("") //menù a tendina per la scelta del formato di output
-                .selected_text(format!("{:?}", self.format))
+                .selected_text(<ImageFormat as Into<&str>>::into(self.format))
                 .show_ui(ui, |ui| {
                     for f in &ImageFormat::available_formats() {
                         ui.selectable_value(
@@ -600,6 +716,39 @@ impl EditImage {
         })
     }
 
+    /// lancia il thread che applica il puntatore del mouse (se visibile) e le annotazioni all'immagine e la ritaglia:
+    /// il risultato verra' ricevuto in `update()` tramite `receive_thread`
+    fn start_thread_render(&mut self) {
+        let (tx, rx) = channel();
+        self.receive_thread = rx;
+        let annotations = self.annotations.clone();
+        let mut image = self.image.clone();
+        let cursor = match &self.cursor {
+            Some((cursor, _)) if self.show_cursor => Some(cursor.clone()),
+            _ => None,
+        };
+        let cut_rect = self.cut_rect;
+        thread::spawn(move || {
+            if let Some(cursor) = cursor {
+                cursor.draw_onto(&mut image);
+            }
+            let mut image_blend = Blend(image);
+            for annotation in annotations {
+                write_annotation_to_image(&annotation, &mut image_blend);
+            }
+            tx.send(
+                crop_imm(
+                    &image_blend.0,
+                    cut_rect.left_top().x as u32,
+                    cut_rect.left_top().y as u32,
+                    cut_rect.width() as u32,
+                    cut_rect.height() as u32,
+                )
+                .to_image(),
+            )
+        });
+    }
+
     /// disegna il selettore dello strumento in uso
     fn draw_tool_radio(&mut self, ui: &mut Ui) {
         ui.label("Tool:");
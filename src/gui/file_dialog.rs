@@ -28,7 +28,7 @@ pub fn show_save_dialog(format: ImageFormat, start_dir: Option<String>) -> Optio
     };
 
     FileDialog::new()
-        .add_filter("image", &[format.into()])
+        .add_filter(format.into(), &[format.extension()])
         .set_directory(dir)
         .save_file()
 }
@@ -57,6 +57,39 @@ pub fn show_directory_dialog(start_dir: Option<String>) -> Option<PathBuf> {
     FileDialog::new().set_directory(dir).pick_folder()
 }
 
+/// Mostra un file dialog in modalità "Open", che permette di selezionare un'immagine tra quelle nei formati supportati.<br>
+///
+/// Parametri:
+/// - <b>start_dir</b>: se specificata: directory inizialmente aperta.<br>
+///   Se non specificata, la directory inizialmente aperta sarà "/".<br>
+///
+/// Ritorna <b>Option</b>:
+/// - None, se l'user ha annullato;<br>
+/// - Some(PathBuf), se l'user ha selezionato un file e premuto su "Open".
+pub fn show_open_image_dialog(start_dir: Option<String>) -> Option<PathBuf> {
+    let dir = match start_dir {
+        Some(s) if !s.is_empty() && Path::new(&s).exists() => s,
+        _ => "/".to_string(),
+    };
+    let extensions = [
+        "png", "jpg", "jpeg", "gif", "webp", "bmp", "tif", "tiff", "qoi", "PNG", "JPG", "JPEG",
+        "GIF", "WEBP", "BMP", "TIF", "TIFF", "QOI",
+    ];
+
+    FileDialog::new()
+        .add_filter("Images", &extensions)
+        .set_directory(dir)
+        .pick_file()
+}
+
+pub fn start_thread_open_image_dialog(start_dir: Option<String>) -> Receiver<Option<PathBuf>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(show_open_image_dialog(start_dir));
+    });
+    rx
+}
+
 pub fn start_thread_directory_dialog(start_dir: Option<String>) -> Receiver<Option<PathBuf>> {
     let (tx, rx) = channel();
     std::thread::spawn(move || {
@@ -6,13 +6,17 @@ use eframe::egui::KeyboardShortcut;
 use std::sync::Arc;
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, channel, TryRecvError};
+use std::time::Duration;
 
 #[derive(Clone)]
 enum HotkeySettingsState
 {
     Idle,
-    Registering(HotkeyName)
+    Registering(HotkeyName),
+    ///Registrazione di una sequenza di due passi: contiene il primo, quando è già stato registrato.
+    RegisteringSequence(HotkeyName, Option<String>)
 }
 
 impl PartialEq for HotkeySettingsState
@@ -23,11 +27,41 @@ impl PartialEq for HotkeySettingsState
         {
             (HotkeySettingsState::Idle, HotkeySettingsState::Idle) => true,
             (HotkeySettingsState::Registering(hn1), HotkeySettingsState::Registering(hn2)) => hn1 == hn2,
+            (HotkeySettingsState::RegisteringSequence(hn1, s1), HotkeySettingsState::RegisteringSequence(hn2, s2)) => hn1 == hn2 && s1 == s2,
             _ => false
         }
     }
 }
 
+impl HotkeySettingsState
+{
+    /// Hotkey di cui è in corso la registrazione, se c'è.
+    fn action(&self) -> Option<HotkeyName>
+    {
+        match self
+        {
+            HotkeySettingsState::Idle => None,
+            HotkeySettingsState::Registering(hn) | HotkeySettingsState::RegisteringSequence(hn, _) => Some(*hn)
+        }
+    }
+}
+
+/// Thread worker che registra una combinazione di tasti dal server X11 (vedi <i>hotkeys::start_thread_record_hotkey()</i>).
+/// Quando la struct viene distrutta, il worker viene fermato, in modo che la tastiera non resti catturata.
+struct Recorder
+{
+    receiver: Receiver<Result<Option<String>, String>>,
+    stop: Arc<AtomicBool>
+}
+
+impl Drop for Recorder
+{
+    fn drop(&mut self)
+    {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
 /// Stato della parte della gui che visualizza la schermata di impostazione delle Hotkeys.<br>
 /// Si appoggia sul modulo RegisteredHotkeys: la struct sottostante realizza la parte di gui per settare le hotkeys,
 /// registrando le modifiche all'interno di RegisteredHotkeys.
@@ -37,7 +71,9 @@ pub struct HotkeysSettings
     state: HotkeySettingsState,
     registered_hotkeys: Arc<RegisteredHotkeys>,
     alert: Rc<RefCell<Option<String>>>,
-    workers_channel: Rc<(Sender<Result<(), &'static str>>, Receiver<Result<(), &'static str>>)> 
+    workers_channel: Rc<(Sender<Result<(), &'static str>>, Receiver<Result<(), &'static str>>)>,
+    ///Se != None, la combinazione di tasti in corso di registrazione viene letta dal server X11, altrimenti dagli eventi di egui.
+    recorder: Option<Rc<Recorder>>
 }
 
 impl HotkeysSettings
@@ -54,7 +90,7 @@ impl HotkeysSettings
     ///     di questo metodo.
     pub fn new(alert: Rc<RefCell<Option<String>>>, registered_hotkeys: Arc<RegisteredHotkeys>) -> Self
     {
-        Self {state: HotkeySettingsState::Idle, registered_hotkeys, alert, workers_channel: Rc::new(channel())}
+        Self {state: HotkeySettingsState::Idle, registered_hotkeys, alert, workers_channel: Rc::new(channel()), recorder: None}
     }
 
     /// Mostra, per ogni possibile hotkey in RegisteredHotkeys, un form per la sua configurazione.<br>
@@ -82,29 +118,34 @@ impl HotkeysSettings
         }
 
         //controllo se è in corso la registrazione di una hotkey
-        if let HotkeySettingsState::Registering(hn) = &mut self.state
+        let recorded = match self.state
         {
-            let hn_clone = hn.clone(); //necessario clonare per poter distruggere il riferimento &mut creato sopra
-            if let Some(new_hk) = self.registration_phase(ui)
+            HotkeySettingsState::Idle => None,
+            _ if self.recorder.is_some() => self.recording_phase(ui),
+            _ =>
             {
-                let str_kh = new_hk.format(&eframe::egui::ModifierNames::NAMES, std::env::consts::OS == "macos" );
-                self.state = HotkeySettingsState::Idle;
-                self.registered_hotkeys.request_register(str_kh, hn_clone, self.workers_channel.0.clone());
+                let plain_keys = self.plain_keys();
+                self.registration_phase(ui, plain_keys)
+                    .map(|new_hk| new_hk.format(&eframe::egui::ModifierNames::NAMES, std::env::consts::OS == "macos" ))
             }
+        };
+        if let Some(str_kh) = recorded
+        {
+            self.step_recorded(str_kh);
         }
         
 
-        //gui
-        ScrollArea::new([true, false]).show(ui, |ui|
+        //gui: le hotkeys dei preset di regione rendono la lista più alta della finestra
+        ScrollArea::new([true, true]).show(ui, |ui|
         {
             ui.vertical(|ui|
                 {
     
-                    for i in 0..hotkeys::N_HOTK
+                    for action in hotkeys::actions()
                     {
-                        let value = match self.registered_hotkeys.get_hotkey_string(HotkeyName::from(i)) {Some(str) => str.clone(), None => String::from("")};
+                        let values = self.registered_hotkeys.get_hotkey_strings(action.name);
     
-                        self.row_gui(ui, HotkeyName::from(i), value);
+                        self.row_gui(ui, action.name, values);
                     }
     
                     ui.separator();
@@ -126,9 +167,9 @@ impl HotkeysSettings
                                 }
                             });
                             ui.style_mut().visuals.widgets.hovered.weak_bg_fill = eframe::egui::Color32::RED;
-                            if ui.button("Abort").clicked() {ret = SettingsEvent::Aborted;}
+                            if ui.button("Abort").clicked() {self.stop_registration(); ret = SettingsEvent::Aborted;}
                             ui.add_space(10.0);
-                            ui.heading("❓").on_hover_text("Hotkeys are combinations of keys pressed simultaneously with an associated action.\nThese combinations must be composed by at least one control button and only one key button.\nIf you press one of such hotkeys, the associated action is executed, even if this application is not in focus.\nRemember that these will eventually override other system's hotkeys (such as Ctrl+C) if you select the same combination of keys.")
+                            ui.heading("❓").on_hover_text("Hotkeys are combinations of keys pressed simultaneously with an associated action.\nThese combinations must be composed by at least one control button and only one key button.\nIf you press one of such hotkeys, the associated action is executed, even if this application is not in focus.\nAn action can have several hotkeys, and also sequences of two steps (e.g. Ctrl+Shift+S, then R):\nthe second step must be pressed within two seconds and does not need modifiers.\nRemember that these will eventually override other system's hotkeys (such as Ctrl+C) if you select the same combination of keys.")
                         });
     
                     //messaggio di help che viene visualizzato mentre si sta registrando una hotkey
@@ -140,8 +181,20 @@ impl HotkeysSettings
                             ui.horizontal(|ui|
                             {
                                 ui.heading("?");
-                                ui.code("HELP: press at least one modifier and an alphabetic key.\nThe letter must be the last button to be pressed.\nWhen you press the letter, also the modifiers have to be pressed simultaneously.\nIf it doesn't work, make the pressure last longer.");
-                            })
+                                if let HotkeySettingsState::RegisteringSequence(_, Some(first)) = &self.state
+                                {
+                                    ui.code(format!("HELP: sequence {}, ...\nPress the second key of the sequence, with or without modifiers.\nPress Esc to cancel.", first));
+                                }
+                                else if self.recorder.is_some()
+                                {
+                                    ui.code("HELP: hold any modifiers (Ctrl, Alt, Shift, Super) and press one key.\nLetters, digits and symbols need at least one modifier,\nwhile keys such as PrintScreen, F1-F12 or the volume keys can also be used alone.\nPress Esc to cancel.");
+                                }
+                                else
+                                {
+                                    ui.code("HELP: press at least one modifier and an alphabetic key.\nThe letter must be the last button to be pressed.\nWhen you press the letter, also the modifiers have to be pressed simultaneously.\nIf it doesn't work, make the pressure last longer.");
+                                }
+                            });
+                            if ui.button("Cancel").clicked() {self.stop_registration();}
                         });
                     }
                 });
@@ -150,61 +203,161 @@ impl HotkeysSettings
         ret
     }
 
-    /// Mostra una riga con etichetta (della hotkey), stringa che rappresenta la combinazione di tasti, bottoni per 
-    /// avviare la registrazione o per eliminare la hotkey.<br>
+    /// Mostra una riga con etichetta (della hotkey) e bottoni per avviare la registrazione di una nuova combinazione di
+    /// tasti o di una sequenza, seguita da una riga per ogni combinazione associata, con il bottone per eliminarla.<br>
     /// Parametri:
     /// - <b>hn<b>, identificativo della hotkey;
-    /// - <b>value</b>, combinazione di tasti associata;
-    /// Se è in corso la registrazione di un'altra hotkey, i bottoni di questa riga vengono disabilitati.
-    fn row_gui(&mut self, ui: &mut Ui, hn: HotkeyName, value: String)
+    /// - <b>values</b>, combinazioni di tasti associate;
+    /// Se è in corso la registrazione di un'altra hotkey, i bottoni di queste righe vengono disabilitati.
+    fn row_gui(&mut self, ui: &mut Ui, hn: HotkeyName, values: Vec<String>)
     {
-        let mut label: String = hn.into();
-        label.push_str(": ");
+        let label = format!("{}: ", hn.action().label);
         ui.style_mut().visuals.widgets.hovered.weak_bg_fill = eframe::egui::Color32::from_rgb(0,140,250);
-        ui.add_enabled_ui(self.state == HotkeySettingsState::Idle || self.state == HotkeySettingsState::Registering(hn), |ui|
+        ui.add_enabled_ui(self.state.action().is_none() || self.state.action() == Some(hn), |ui|
         {
             ui.horizontal(|ui|
                 {
                     ui.label(label);
-                    ui.label(value);
+                    if values.is_empty()
+                    {
+                        ui.weak("none");
+                    }
                     
                     ui.with_layout(eframe::egui::Layout::right_to_left(eframe::egui::Align::TOP), |ui|
                     {   
-
-                        if ui.button("Delete hotkey").clicked()
+                        if ui.button("Add sequence").clicked()
                         {
-                            self.registered_hotkeys.request_unregister(hn)
-                        } 
+                            //avvia la registrazione del primo passo della sequenza
+                            self.start_registration(HotkeySettingsState::RegisteringSequence(hn, None));
+                        }
 
-                        
-                        if ui.button("Set hotkey").clicked()
+                        if ui.button("Add hotkey").clicked()
                         {
                             //avvia la registrazione della hotkey
-                            self.state = HotkeySettingsState::Registering(hn);
+                            self.start_registration(HotkeySettingsState::Registering(hn));
                         }
  
                     });
                     
                 });
+
+            for value in values
+            {
+                ui.horizontal(|ui|
+                {
+                    ui.add_space(20.0);
+                    ui.label(&value);
+                    ui.with_layout(eframe::egui::Layout::right_to_left(eframe::egui::Align::TOP), |ui|
+                    {
+                        if ui.button("Remove").clicked()
+                        {
+                            self.registered_hotkeys.request_unregister(hn, &value)
+                        }
+                    });
+                });
+            }
         });
         
     }
 
+    /// Avvia la registrazione di una combinazione di tasti, entrando nello stato <b>state</b>: se possibile, la combinazione
+    /// viene letta dal server X11 da un thread worker (vedi <i>Recorder</i>), altrimenti dagli eventi di egui.
+    fn start_registration(&mut self, state: HotkeySettingsState)
+    {
+        self.state = state;
+        let stop = Arc::new(AtomicBool::new(false));
+        self.recorder = hotkeys::start_thread_record_hotkey(stop.clone(), self.plain_keys())
+            .map(|receiver| Rc::new(Recorder {receiver, stop}));
+    }
+
+    /// I passi di una sequenza successivi al primo possono essere anche tasti senza modificatori.
+    fn plain_keys(&self) -> bool
+    {
+        matches!(self.state, HotkeySettingsState::RegisteringSequence(_, Some(_)))
+    }
+
+    /// Gestisce la combinazione di tasti <b>str_kh</b> appena registrata:
+    /// - se è il primo passo di una sequenza, avvia la registrazione del secondo;
+    /// - altrimenti termina la registrazione e richiede a RegisteredHotkeys di aggiungere la combinazione (o la sequenza)
+    ///   alla hotkey.
+    fn step_recorded(&mut self, str_kh: String)
+    {
+        match self.state.clone()
+        {
+            HotkeySettingsState::Idle => (),
+            HotkeySettingsState::RegisteringSequence(hn, None) =>
+            {
+                self.start_registration(HotkeySettingsState::RegisteringSequence(hn, Some(str_kh)));
+            },
+            HotkeySettingsState::RegisteringSequence(hn, Some(first)) =>
+            {
+                self.stop_registration();
+                self.registered_hotkeys.request_register(format!("{}, {}", first, str_kh), hn, self.workers_channel.0.clone());
+            },
+            HotkeySettingsState::Registering(hn) =>
+            {
+                self.stop_registration();
+                self.registered_hotkeys.request_register(str_kh, hn, self.workers_channel.0.clone());
+            }
+        }
+    }
+
+    /// Controlla se il thread worker ha registrato una combinazione di tasti:
+    /// - in caso affermativo, ferma il thread worker e ritorna la combinazione;
+    /// - se la registrazione è stata annullata o non è riuscita, la termina e, in caso di errore, lo segnala inserendo una
+    ///   stringa nell'alert globale;
+    /// - altrimenti, chiede un nuovo repaint per controllare di nuovo a breve.
+    fn recording_phase(&mut self, ui: &mut Ui) -> Option<String>
+    {
+        let res = match &self.recorder
+        {
+            Some(recorder) => recorder.receiver.try_recv(),
+            None => return None
+        };
+        match res
+        {
+            Ok(Ok(Some(ret))) => { self.recorder = None; Some(ret) },
+            Ok(Ok(None)) => { self.stop_registration(); None },
+            Ok(Err(e)) => 
+            {
+                self.alert.borrow_mut().replace(format!("Unable to record the hotkey: {}", e));
+                self.stop_registration();
+                None
+            },
+            Err(TryRecvError::Empty) => { ui.ctx().request_repaint_after(Duration::from_millis(50)); None },
+            Err(TryRecvError::Disconnected) => { self.stop_registration(); None }
+        }
+    }
+
+    /// Termina la registrazione in corso, fermando il thread worker che legge la combinazione dal server X11.
+    fn stop_registration(&mut self)
+    {
+        self.state = HotkeySettingsState::Idle;
+        self.recorder = None;
+    }
+
     /// Controlla tutti gli input events. Se tra questi c'è la pressione di un tasto lettera, controlla se contemporaneamente
     /// sono premuti altri tasti di controllo:
     /// - in caso affermativo, costruisce un oggetto <b>KeyboardShortcut</b> con i tasti premuti e lo ritorna;
     /// - in caso negativo, segnala, inserendo una stringa nell'alert globale, che la combinazione non è valida 
     /// (<i>una hotkey valida è composta da almeno un tasto di controllo e un'unica lettera</i>)
-    fn registration_phase(&mut self, ui: &mut Ui) -> Option<KeyboardShortcut>
+    ///
+    /// Se <b>plain_keys</b> è true (secondo passo di una sequenza), la lettera può essere premuta anche senza tasti di controllo.
+    fn registration_phase(&mut self, ui: &mut Ui, plain_keys: bool) -> Option<KeyboardShortcut>
     {
         let mut ret = None;
         let events = ui.input(|i| {i.events.clone()});
         for event in &events
         {
             //la prima lettera premuta termina il processo di registrazione della hotkey
-            if let Event::Key{key, pressed: _ , modifiers, repeat}  = event  
+            if let Event::Key{key, pressed, modifiers, repeat}  = event  
             {
-                if modifiers.any() && !(*repeat)
+                //il rilascio della lettera del primo passo non deve essere registrato come secondo passo
+                if plain_keys && !(*pressed)
+                {
+                    continue;
+                }
+                if (modifiers.any() || plain_keys) && !(*repeat)
                 {
                     ret = Some(KeyboardShortcut::new(*modifiers, *key));
                 }else {
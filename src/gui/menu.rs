@@ -2,7 +2,11 @@ use super::hotkeys_settings::HotkeysSettings;
 use super::{capture_mode::CaptureMode, loading, save_settings::SaveSettings};
 use crate::{
     hotkeys::RegisteredHotkeys,
-    itc::{ScreenshotDim, SettingsEvent},
+    itc::{
+        LastCapture, RecordingSettings, ScreenshotDim, SettingsEvent, TimelapseSettings,
+        WindowCaptureSettings,
+    },
+    region_presets::{RegionPreset, RegionPresets},
     screens_manager::ScreensManager,
 };
 use eframe::egui::{CentralPanel, Context, Ui};
@@ -10,13 +14,20 @@ use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::mpsc::Receiver;
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     sync::{mpsc::TryRecvError, Arc},
 };
 
 pub enum MainMenuEvent {
     ScreenshotRequest(ScreenshotDim, f64),
+    RecordingRequest(ScreenshotDim, f64, RecordingSettings),
+    TimelapseRequest(ScreenshotDim, f64, TimelapseSettings),
+    WindowScreenshotRequest(WindowCaptureSettings, f64),
+    RegionScreenshotRequest(RegionPreset, f64),
+    RepeatLastCapture,
     OpenDirectoryDialog,
+    OpenImageDialog,
+    OpenClipboardImage,
     Nil,
 }
 /// Enum che descrive che cosa viene mostrato di fianco al side menu.
@@ -36,6 +47,9 @@ pub struct MainMenu {
     screens_mgr: Arc<ScreensManager>,
     save_settings: Rc<RefCell<SaveSettings>>,
     registered_hotkeys: Arc<RegisteredHotkeys>,
+    region_presets: Rc<RefCell<RegionPresets>>,
+    last_capture: Rc<RefCell<Option<LastCapture>>>,
+    include_cursor: Rc<Cell<bool>>,
 }
 
 impl MainMenu {
@@ -45,18 +59,31 @@ impl MainMenu {
         screens_mgr: Arc<ScreensManager>,
         save_settings: Rc<RefCell<SaveSettings>>,
         registered_hotkeys: Arc<RegisteredHotkeys>,
+        region_presets: Rc<RefCell<RegionPresets>>,
+        last_capture: Rc<RefCell<Option<LastCapture>>>,
+        include_cursor: Rc<Cell<bool>>,
     ) -> Self {
         Self {
-            state: MainMenuState::CaptureMode(CaptureMode::new(screens_mgr.clone())),
+            state: MainMenuState::CaptureMode(CaptureMode::new(
+                screens_mgr.clone(),
+                region_presets.clone(),
+                last_capture.clone(),
+                include_cursor.clone(),
+            )),
             screens_mgr,
             alert,
             save_settings,
             registered_hotkeys,
+            region_presets,
+            last_capture,
+            include_cursor,
         }
     }
 
     /// Mostra:
-    /// - a sinistra, un bottone "☰", che permette la visualizzazione del menu';
+    /// - a sinistra, un bottone "☰", che permette la visualizzazione del menu' (da cui è anche possibile aprire
+    ///   un'immagine già salvata o contenuta nella clipboard, ritornando <i>MainMenuEvent::OpenImageDialog</i> o
+    ///   <i>MainMenuEvent::OpenClipboardImage</i>);
     /// - a destra, una schermata dipendente dalla voce del menu' selezionata.
     /// L'intero contenuto è disabilitato se il parametro enabled è settato a false.
     pub fn update(&mut self, enabled: bool, ctx: &Context) -> MainMenuEvent {
@@ -71,6 +98,14 @@ impl MainMenu {
                                 ui.close_menu();
                                 self.switch_to_main_window();
                             }
+                            if ui.button("Open image…").clicked() {
+                                ui.close_menu();
+                                ret = MainMenuEvent::OpenImageDialog;
+                            }
+                            if ui.button("Open clipboard image").clicked() {
+                                ui.close_menu();
+                                ret = MainMenuEvent::OpenClipboardImage;
+                            }
                             ui.menu_button("Settings...", |ui| {
                                 if ui.button("Save Settings").clicked() {
                                     ui.close_menu();
@@ -119,14 +154,24 @@ impl MainMenu {
         match self.state {
             MainMenuState::CaptureMode(..) => (), //non c'è niente di nuovo da visualizzare
             _ => {
-                self.state = MainMenuState::CaptureMode(CaptureMode::new(self.screens_mgr.clone()))
+                self.state = MainMenuState::CaptureMode(CaptureMode::new(
+                    self.screens_mgr.clone(),
+                    self.region_presets.clone(),
+                    self.last_capture.clone(),
+                    self.include_cursor.clone(),
+                ))
             }
         }
     }
 
     /// Chiama il metodo update() della struct CaptureMode memorizzata nello stato corrente.
     /// Gestisce i valori di ritorno di update(): se CaptureMode::update() ritorna i dettagli di una richiesta di
-    /// screenshot, essi vengono incapsulati in MainMenuEvent::ScreenshotRequest.
+    /// screenshot, essi vengono incapsulati in MainMenuEvent::ScreenshotRequest (oppure MainMenuEvent::RecordingRequest,
+    /// nel caso di una registrazione, MainMenuEvent::TimelapseRequest, nel caso di una timelapse,
+    /// MainMenuEvent::WindowScreenshotRequest, con i parametri della finestra da catturare,
+    /// nel caso di una finestra, e MainMenuEvent::RegionScreenshotRequest, con il preset selezionato, nel caso di una
+    /// regione fissa). La pressione del bottone "Repeat last capture" è invece segnalata con
+    /// MainMenuEvent::RepeatLastCapture.
     ///
     /// <h3>Panics:</h3>
     /// Se <i>self.state</i> è diverso da <i>MainMenuState::CaptureMode</i>.
@@ -134,8 +179,28 @@ impl MainMenu {
         let mut ret = MainMenuEvent::Nil;
         if let MainMenuState::CaptureMode(ref mut cm) = self.state {
             //controllo l'output della main window: se è diverso da None, significa che è stata creata una nuova richiesta di screenshot
-            if let Some((area, delay)) = cm.update(ui, ctx) {
-                ret = MainMenuEvent::ScreenshotRequest(area, delay);
+            match cm.update(ui, ctx) {
+                Some((area, delay, None)) if cm.timelapse_settings().is_some() => {
+                    if let Some(ts) = cm.timelapse_settings() {
+                        ret = MainMenuEvent::TimelapseRequest(area, delay, ts)
+                    }
+                }
+                Some((ScreenshotDim::Window, delay, None)) => {
+                    ret = MainMenuEvent::WindowScreenshotRequest(cm.window_settings(), delay)
+                }
+                Some((ScreenshotDim::Region, delay, None)) => {
+                    if let Some(preset) = cm.region_preset() {
+                        ret = MainMenuEvent::RegionScreenshotRequest(preset, delay)
+                    }
+                }
+                Some((area, delay, None)) => ret = MainMenuEvent::ScreenshotRequest(area, delay),
+                Some((area, delay, Some(rs))) => {
+                    ret = MainMenuEvent::RecordingRequest(area, delay, rs)
+                }
+                None => (),
+            }
+            if cm.repeat_requested() {
+                ret = MainMenuEvent::RepeatLastCapture;
             }
         } else {
             unreachable!();
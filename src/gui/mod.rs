@@ -18,27 +18,41 @@ pub mod file_dialog;
 mod hotkeys_settings;
 mod loading;
 mod menu;
+mod recording;
 mod rect_selection;
-mod save_settings;
+pub mod save_settings;
+mod saved_alert;
+mod timelapse;
 
 use self::edit_image::FrameEvent;
 use self::menu::MainMenuEvent;
 use crate::gui::loading::show_loading;
 use crate::hotkeys::{self, HotkeyName, RegisteredHotkeys};
-use crate::image_coding::{start_thread_copy_to_clipboard, ImageFormat};
-use crate::itc::ScreenshotDim;
-use crate::{image_coding, screens_manager};
+use crate::image_coding::{
+    start_thread_copy_to_clipboard, ExportSource, ImageFormat, SavedFile, TextExport,
+};
+use crate::itc::{
+    CaptureMetadata, LastCapture, RecordingSettings, ScreenshotDim, TimelapseSettings, WindowCaptureSettings,
+};
+use crate::region_presets::{RegionPreset, RegionPresets};
+use crate::screens_manager::{ExcludedArea, ScreensChange, ShotGate, MAX_RECORDING_BYTES};
+use crate::window_capture::{CursorImage, WindowRect};
+use crate::{image_coding, screens_manager, window_capture};
 use edit_image::EditImage;
-use eframe::egui::Rect;
+use eframe::egui::{pos2, Rect};
 use image::{ImageError, RgbaImage};
 use menu::MainMenu;
+use recording::Recording;
+use timelapse::Timelapse;
 use rect_selection::RectSelection;
 use save_settings::SaveSettings;
-use std::cell::RefCell;
+use saved_alert::{SavedAlert, SavedAlertEvent};
+use std::cell::{Cell, RefCell};
 use std::fmt::Formatter;
 use std::io::Write;
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
 use std::sync::mpsc::{channel, Receiver, TryRecvError};
 use std::sync::Arc;
 use std::thread::JoinHandle;
@@ -52,9 +66,33 @@ pub enum EnumGuiState {
     WaitingForDelay(Option<JoinHandle<()>>, ScreenshotDim),
     LoadingRectSelection(Receiver<Result<RgbaImage, &'static str>>),
     RectSelection(RectSelection),
+    LoadingWindow(Receiver<Result<(RgbaImage, WindowRect), &'static str>>),
     LoadingEditImage(Receiver<Result<RgbaImage, &'static str>>),
     EditImage(EditImage),
-    Saving(Receiver<Result<String, ImageError>>),
+    Recording(Recording),
+    Timelapse(Timelapse),
+    Saving(Receiver<Result<SavedFile, ImageError>>),
+}
+
+/// Provenienza dell'immagine in fase di caricamento nell'editor.
+enum ImageSource {
+    Screenshot,
+    /// Screenshot di una finestra: gli errori (ad esempio la scelta annullata dall'utente) sono già messaggi da mostrare.
+    Window,
+    /// Cattura di un preset di regione: anche in questo caso gli errori (ad esempio lo schermo del preset non più
+    /// collegato) sono messaggi da mostrare.
+    Region,
+    /// Immagine aperta da file: il path viene passato all'editor per permettere di sovrascrivere il file.
+    File(PathBuf),
+    Clipboard,
+}
+
+/// Contenuto di una richiesta di salvataggio in attesa che sia disponibile il path di destinazione.
+enum SavePayload {
+    /// Immagine da salvare, con gli eventuali metadati della cattura da cui è stata ottenuta.
+    Image(RgbaImage, ImageFormat, Option<CaptureMetadata>),
+    /// Frame di una registrazione, con la durata di ciascun frame e i parametri della registrazione.
+    Animation(Vec<RgbaImage>, Duration, RecordingSettings),
 }
 
 impl std::fmt::Debug for EnumGuiState {
@@ -67,7 +105,10 @@ impl std::fmt::Debug for EnumGuiState {
             }
             EnumGuiState::RectSelection(..) => write!(f, "EnumGuiState::RectSelection"),
             EnumGuiState::EditImage(..) => write!(f, "EnumGuiState::EditImage"),
+            EnumGuiState::LoadingWindow(_) => write!(f, "EnumGuiState::LoadingWindow"),
             EnumGuiState::LoadingEditImage(_) => write!(f, "EnumGuiState::LoadingEdiImage"),
+            EnumGuiState::Recording(_) => write!(f, "EnumGuiState::Recording"),
+            EnumGuiState::Timelapse(_) => write!(f, "EnumGuiState::Timelapse"),
             EnumGuiState::Saving(_) => write!(f, "EnumGuiState::Saving"),
         }
     }
@@ -85,63 +126,159 @@ pub struct GlobalGuiState {
     save_settings: Rc<RefCell<SaveSettings>>,
     /// Gestore delle hotkeys registrate.
     registered_hotkeys: Arc<RegisteredHotkeys>,
+    /// Preset di regioni fisse, catturabili senza selezionare l'area.
+    region_presets: Rc<RefCell<RegionPresets>>,
     /// Contiene Some() se è stato lanciato un worker per copiare dati sulla clipboard.
     clipboard: Option<Receiver<Result<(), arboard::Error>>>,
     /// Receiver del canale di comunicazione con il thread dedicato all'ascolto delle hotkeys
     hotkey_receiver: Option<Receiver<HotkeyName>>,
+    /// Receiver del canale di comunicazione con il thread che rileva il collegamento e lo scollegamento degli schermi
+    screens_receiver: Option<Receiver<ScreensChange>>,
     ///Se != None, allora l'applicazione ha avviato un thread worker per costruire il path di destinazione
     /// prima del salvataggio dell'immagine: la finestra principale deve essere mostrata ma disabilitata
-    pending_save_request: Option<(Receiver<Option<PathBuf>>, RgbaImage)>,
+    pending_save_request: Option<(Receiver<Option<PathBuf>>, SavePayload)>,
+    ///Se != None, la cattura in corso è una registrazione animata: al termine dell'attesa del delay (ed eventualmente
+    /// della selezione dell'area) verrà avviata la registrazione invece di aprire l'editor.
+    pending_recording: Option<RecordingSettings>,
+    ///Se != None, la cattura in corso è una timelapse: al termine dell'attesa del delay (ed eventualmente della
+    /// selezione dell'area) verrà avviata la timelapse invece di aprire l'editor.
+    pending_timelapse: Option<TimelapseSettings>,
+    ///Se != None, la cattura in corso è quella di una finestra: al termine dell'attesa del delay verrà catturata la
+    /// finestra indicata.
+    pending_window: Option<WindowCaptureSettings>,
+    ///Se != None, la cattura in corso è quella di un preset di regione: al termine dell'attesa del delay verrà catturata
+    /// la regione del preset.
+    pending_region: Option<RegionPreset>,
+    ///Ultima cattura eseguita, ripetibile dalla schermata principale o con la hotkey dedicata.
+    last_capture: Rc<RefCell<Option<LastCapture>>>,
+    ///Se != None, la cattura in corso è la ripetizione dell'ultima cattura: al termine dell'attesa del delay verrà
+    /// catturata la stessa area.
+    pending_repeat: Option<LastCapture>,
+    ///Se true, l'immagine ottenuta ripetendo l'ultima cattura viene salvata senza aprire l'editor.
+    repeat_save: bool,
+    ///Secondi di delay della cattura in corso, memorizzati nell'ultima cattura.
+    capture_delay: f64,
+    ///Se true, negli screenshot degli schermi viene catturato anche il puntatore del mouse.
+    include_cursor: Rc<Cell<bool>>,
+    ///Se != None, è stato lanciato un worker per leggere il puntatore del mouse al momento dello screenshot in corso.
+    cursor_receiver: Option<Receiver<Result<CursorImage, &'static str>>>,
+    ///Puntatore del mouse ricevuto da <i>self.cursor_receiver</i>, in coordinate del desktop virtuale.
+    cursor: Option<CursorImage>,
     ///Se != None, allora l'applicazione è in attesa che l'utente chiuda il file dialog
     directory_dialog_receiver: Option<Receiver<Option<PathBuf>>>,
+    ///Se != None, allora l'applicazione è in attesa che l'utente scelga l'immagine da aprire nell'editor
+    open_image_dialog_receiver: Option<Receiver<Option<PathBuf>>>,
+    ///Provenienza dell'immagine in fase di caricamento nell'editor.
+    image_source: ImageSource,
+    ///Metadati dell'ultimo screenshot, scritti nel file al momento del salvataggio. None se l'immagine nell'editor
+    /// non proviene da uno screenshot.
+    capture_metadata: Option<CaptureMetadata>,
+    ///Se != None, è mostrata la finestra di conferma dell'ultimo salvataggio, con le opzioni per copiarlo come testo.
+    saved_alert: Option<SavedAlert>,
+    ///Se != None, è stato lanciato un worker per copiare l'immagine nella clipboard come testo nel formato indicato.
+    text_export: Option<(TextExport, Receiver<Result<(), &'static str>>)>,
 }
 
 impl GlobalGuiState {
     /// Crea una nuova istanza della macchina a stati, del gestore delle hotkeys e degli schermi.
-    /// Lo stato iniziale è <i>EnumGuiState::MainMenu</i>.
-    fn new() -> Self {
+    /// Lo stato iniziale è <i>EnumGuiState::MainMenu</i>, oppure <i>EnumGuiState::LoadingEditImage</i> se è stato
+    /// indicato un file da aprire nell'editor (<b>open_file</b>).
+    fn new(open_file: Option<PathBuf>) -> Self {
         let alert: Rc<RefCell<Option<String>>> = Rc::new(RefCell::new(None));
-        let registered_hotkeys = RegisteredHotkeys::new();
+        let (registered_hotkeys, hotkeys_loaded) = RegisteredHotkeys::new();
+        if let Err(e) = hotkeys_loaded {
+            alert.borrow_mut().replace(e);
+        }
         let save_settings = Rc::new(RefCell::new(SaveSettings::new(alert.clone())));
         let screens_manager = screens_manager::ScreensManager::new(150);
-        GlobalGuiState {
+        let region_presets = Rc::new(RefCell::new(RegionPresets::new(&alert)));
+        let last_capture = Rc::new(RefCell::new(LastCapture::load(&alert)));
+        let include_cursor = Rc::new(Cell::new(false));
+        let mut ret = GlobalGuiState {
             state: EnumGuiState::MainMenu(MainMenu::new(
                 alert.clone(),
                 screens_manager.clone(),
                 save_settings.clone(),
                 registered_hotkeys.clone(),
+                region_presets.clone(),
+                last_capture.clone(),
+                include_cursor.clone(),
             )),
             alert,
             screens_manager,
             save_settings,
             registered_hotkeys,
+            region_presets,
+            last_capture,
             clipboard: None,
             hotkey_receiver: None,
+            screens_receiver: None,
             pending_save_request: None,
+            pending_recording: None,
+            pending_timelapse: None,
+            pending_window: None,
+            pending_region: None,
+            pending_repeat: None,
+            repeat_save: false,
+            capture_delay: 0.0,
+            include_cursor,
+            cursor_receiver: None,
+            cursor: None,
             directory_dialog_receiver: None,
+            open_image_dialog_receiver: None,
+            image_source: ImageSource::Screenshot,
+            capture_metadata: None,
+            saved_alert: None,
+            text_export: None,
+        };
+        if let Some(path) = open_file {
+            ret.switch_to_open_image(path);
         }
+        ret
     }
 
     /// Modifica lo stato della macchina a stati in <i>EnumGuiState::MainMenu</i>, in cui memorizza una nuova istanza di MainMenu.
     fn switch_to_main_menu(&mut self, frame: &mut eframe::Frame) {
         frame.set_decorations(true);
+        frame.set_always_on_top(false);
         frame.set_fullscreen(false);
         frame.set_maximized(false);
         frame.set_window_size(Vec2::new(600.0, 300.0));
         frame.set_visible(true);
+        self.pending_recording = None;
+        self.pending_timelapse = None;
+        self.pending_window = None;
+        self.pending_region = None;
+        self.pending_repeat = None;
+        self.repeat_save = false;
+        self.capture_delay = 0.0;
+        self.cursor_receiver = None;
+        self.cursor = None;
+        self.image_source = ImageSource::Screenshot;
+        self.capture_metadata = None;
         self.state = EnumGuiState::MainMenu(MainMenu::new(
             self.alert.clone(),
             self.screens_manager.clone(),
             self.save_settings.clone(),
             self.registered_hotkeys.clone(),
+            self.region_presets.clone(),
+            self.last_capture.clone(),
+            self.include_cursor.clone(),
         ));
     }
 
     /// Esegue il metodo <i>MainMenu::update()</i>, a cui passa il parametro enabled.
     /// Gestisce il caso in cui <i>MainMenu::update()</i> restituisca <i>MainMenuEvent::ScreenshotRequest</i>, richiamando
     /// <i>Self::start_wait_delay()</i> per soddisfare la richiesta dopo il delay impostato.
+    /// Allo stesso modo gestisce <i>MainMenuEvent::RecordingRequest</i>, dopo aver memorizzato i parametri della
+    /// registrazione in <i>self.pending_recording</i> (e <i>MainMenuEvent::TimelapseRequest</i>, con
+    /// <i>self.pending_timelapse</i>, se è abilitata la cartella di default in cui salvare gli scatti),
+    /// e <i>MainMenuEvent::WindowScreenshotRequest</i>, memorizzando i
+    /// parametri della finestra da catturare in <i>self.pending_window</i> (e allo stesso modo
+    /// <i>MainMenuEvent::RegionScreenshotRequest</i>, con <i>self.pending_region</i>).
+    /// <i>MainMenuEvent::RepeatLastCapture</i> viene gestito con <i>Self::start_repeat_last_capture()</i>.
     /// Oppure <i>MainMenuEvent:: OpenDirectoryDialog</i>, richiamando il metodo per rendere disabilitata la finestra
-    ///corrente e aprire il file dialog.
+    ///corrente e aprire il file dialog. Allo stesso modo gestisce <i>MainMenuEvent::OpenImageDialog</i>.
     ///  
     /// <h3>Panics:</h3>
     /// Nel caso <i>self.state</i> sia diverso da <i>EnumGuiState::MainMenu</i>.
@@ -154,7 +291,30 @@ impl GlobalGuiState {
         if let EnumGuiState::MainMenu(m) = &mut self.state {
             match m.update(enabled, ctx) {
                 MainMenuEvent::ScreenshotRequest(sd, d) => self.start_wait_delay(d, sd, frame, ctx),
+                MainMenuEvent::RecordingRequest(sd, d, rs) => {
+                    self.pending_recording = Some(rs);
+                    self.start_wait_delay(d, sd, frame, ctx);
+                }
+                MainMenuEvent::TimelapseRequest(sd, d, ts) => {
+                    if self.save_settings.borrow().get_default_dir().is_some() {
+                        self.pending_timelapse = Some(ts);
+                        self.start_wait_delay(d, sd, frame, ctx);
+                    } else {
+                        self.alert.borrow_mut().replace(Self::TIMELAPSE_DIR_MISSING.to_string());
+                    }
+                }
+                MainMenuEvent::WindowScreenshotRequest(ws, d) => {
+                    self.pending_window = Some(ws);
+                    self.start_wait_delay(d, ScreenshotDim::Window, frame, ctx);
+                }
+                MainMenuEvent::RegionScreenshotRequest(preset, d) => {
+                    self.pending_region = Some(preset);
+                    self.start_wait_delay(d, ScreenshotDim::Region, frame, ctx);
+                }
+                MainMenuEvent::RepeatLastCapture => self.start_repeat_last_capture(frame, ctx),
                 MainMenuEvent::OpenDirectoryDialog => self.open_directory_dialog(),
+                MainMenuEvent::OpenImageDialog => self.open_image_dialog(),
+                MainMenuEvent::OpenClipboardImage => self.switch_to_clipboard_image(),
                 MainMenuEvent::Nil => (),
             }
         } else {
@@ -185,6 +345,39 @@ impl GlobalGuiState {
         }
     }
 
+    ///Lancia il thread che gestisce il file dialog per la scelta dell'immagine da aprire nell'editor.
+    /// Il file dialog mostrato sarà inizialmente aperto nel path indicato come default directory nelle save settings.
+    fn open_image_dialog(&mut self) {
+        let rx = file_dialog::start_thread_open_image_dialog(
+            self.save_settings.borrow().get_default_dir(),
+        );
+        self.open_image_dialog_receiver = Some(rx);
+    }
+
+    ///Gestisce l'attesa che il thread che gestisce il file dialog per l'apertura di un'immagine invii un risultato
+    /// sul canale:
+    /// - se è stato selezionato un file, richiama <i>Self::switch_to_open_image()</i>;
+    /// - se l'utente ha annullato, elimina il receiver;
+    /// - se il canale è stato chiuso inaspettatamente, segnala l'errore ed elimina il receiver.
+    fn wait_open_image_dialog(&mut self) {
+        if let Some(rx) = &self.open_image_dialog_receiver {
+            match rx.try_recv() {
+                Ok(Some(path)) => {
+                    self.open_image_dialog_receiver = None;
+                    self.switch_to_open_image(path);
+                }
+                Ok(None) => self.open_image_dialog_receiver = None,
+                Err(TryRecvError::Empty) => (),
+                Err(TryRecvError::Disconnected) => {
+                    self.alert
+                        .borrow_mut()
+                        .replace("Error: unable to open the file dialog".to_string());
+                    self.open_image_dialog_receiver = None;
+                }
+            }
+        }
+    }
+
     /// Data una richiesta di screenshot, rende invisibile l'applicazione e
     /// lancia il thread che esegue una sleep.<br/>
     /// La durata della sleep corrisponde a:
@@ -215,14 +408,36 @@ impl GlobalGuiState {
         });
         frame.set_visible(false);
         ctx.request_repaint();
+        self.capture_delay = d;
         self.state = EnumGuiState::WaitingForDelay(Some(jh), area.clone());
     }
 
+    /// Ripete l'ultima cattura eseguita, con lo stesso delay: se l'utente lo ha richiesto nelle impostazioni di
+    /// salvataggio, l'immagine ottenuta verrà salvata direttamente, senza aprire l'editor.
+    /// Se non è ancora stata eseguita alcuna cattura, scrive un messaggio nello stato di errore globale.
+    fn start_repeat_last_capture(&mut self, frame: &mut eframe::Frame, ctx: &eframe::egui::Context) {
+        let last = self.last_capture.borrow().clone();
+        match last {
+            Some(last) => {
+                self.repeat_save = self.save_settings.borrow().get_repeat_saves_directly();
+                let (delay, mode) = (last.delay, last.mode.clone());
+                self.pending_repeat = Some(last);
+                self.start_wait_delay(delay, mode, frame, ctx);
+            }
+            None => {
+                self.alert
+                    .borrow_mut()
+                    .replace("No capture to repeat".to_string());
+            }
+        }
+    }
+
     /// Se nello stato corrente è memorizzato un JoinHandle, esegue <i>join()</i>, mettendo di fatto in attesa la gui (che intanto non è visibile)
     /// fino a quando lo sleep eseguito dal thread non è terminato.
     /// Dopo il <i>join()</i>, rende di nuovo visibile l'applicazione.
     ///
-    /// Dopo ciò, richiama un metodo diverso a seconda del tipo di screenshot richiesto.
+    /// Dopo ciò, richiama un metodo diverso a seconda del tipo di screenshot richiesto (oppure
+    /// <i>Self::repeat_capture()</i>, se è in corso la ripetizione dell'ultima cattura).
     ///
     /// <h3>Panics:</h3>
     /// Nel caso <i>self.state</i> sia diverso da <i>EnumGuiState::WaitingForDelay</i>.
@@ -231,14 +446,30 @@ impl GlobalGuiState {
         if let EnumGuiState::WaitingForDelay(opt_jh, area) = &mut self.state {
             let jh = opt_jh.take().unwrap();
             match jh.join() {
+                Ok(_) if self.pending_repeat.is_some() => {
+                    let last = self.pending_repeat.take().unwrap();
+                    self.repeat_capture(last, frame);
+                }
                 Ok(_) => match *area {
                     ScreenshotDim::Fullscreen => {
-                        self.switch_to_edit_image(None, ctx, frame);
+                        match (self.pending_recording.take(), self.pending_timelapse.take()) {
+                            (Some(rs), _) => self.start_recording(None, rs, frame),
+                            (None, Some(ts)) => self.start_timelapse(None, ts, frame),
+                            (None, None) => self.switch_to_edit_image(None, ctx, frame),
+                        }
                     }
                     ScreenshotDim::Rectangle => {
                         frame.set_visible(false);
                         self.switch_to_rect_selection(frame);
                     }
+                    ScreenshotDim::Window => {
+                        let settings = self.pending_window.take().unwrap_or_default();
+                        self.switch_to_window_capture(settings, frame);
+                    }
+                    ScreenshotDim::Region => match self.pending_region.take() {
+                        Some(preset) => self.switch_to_region_capture(preset, frame),
+                        None => self.switch_to_main_menu(frame),
+                    },
                 },
                 _ => {
                     self.alert.borrow_mut().replace("Timer error".to_string());
@@ -256,6 +487,7 @@ impl GlobalGuiState {
     ///
     fn switch_to_rect_selection(&mut self, frame: &mut eframe::Frame) {
         frame.set_visible(false);
+        self.start_cursor_capture();
         self.state = EnumGuiState::LoadingRectSelection(
             self.screens_manager.start_thread_fullscreen_screenshot(),
         );
@@ -301,6 +533,7 @@ impl GlobalGuiState {
 
     /// Richiama <i>RectSelection::update</i> e ne gestisce il valore di ritorno nel caso questo sia <i>Some((Rect, RgbaImage))</i>,
     /// passando i due parametri al metodo <i>Self::switch_to_edit_image()</i>.
+    /// Se invece è stata richiesta una registrazione (o una timelapse), la avvia sull'area selezionata.
     ///  
     /// <h3>Panics:</h3>
     /// Nel caso <i>self.state</i> sia diverso da <i>EnumGuiState::RectSelection</i>.
@@ -308,7 +541,11 @@ impl GlobalGuiState {
         if let EnumGuiState::RectSelection(ref mut rs) = self.state {
             ctx.request_repaint(); //per evitare il bug durante la transizione
             if let Some((rect, rgba)) = rs.update(ctx) {
-                self.switch_to_edit_image(Some((rect, rgba)), ctx, frame);
+                match (self.pending_recording.take(), self.pending_timelapse.take()) {
+                    (Some(settings), _) => self.start_recording(Some(rect), settings, frame),
+                    (None, Some(settings)) => self.start_timelapse(Some(rect), settings, frame),
+                    (None, None) => self.switch_to_edit_image(Some((rect, rgba)), ctx, frame),
+                }
             }
         } else {
             unreachable!();
@@ -325,12 +562,40 @@ impl GlobalGuiState {
     ///     avvia il thread che esegue lo screenshot fullscreen, salva l'estremità ricevente del canale di comunicazione con
     ///     il thread all'interno dello stato.
     /// In entrambi i casi, il prossimo stato della macchina a stati sarà <i>EnumGuiState::LoadingEditImage.
+    ///
+    /// Memorizza inoltre i metadati della cattura (schermo, modalità e area ritagliata), che verranno scritti nel file salvato,
+    /// e i parametri della cattura, per poterla ripetere.
     fn switch_to_edit_image(
         &mut self,
         opt_rect_img: Option<(Rect, RgbaImage)>,
         ctx: &eframe::egui::Context,
         frame: &mut eframe::Frame,
     ) {
+        self.image_source = ImageSource::Screenshot;
+        let crop = opt_rect_img.as_ref().map(|(rect, _)| {
+            (
+                rect.left() as u32,
+                rect.top() as u32,
+                rect.width() as u32,
+                rect.height() as u32,
+            )
+        });
+        let mode = if crop.is_some() {
+            ScreenshotDim::Rectangle
+        } else {
+            ScreenshotDim::Fullscreen
+        };
+        self.capture_metadata = self
+            .screens_manager
+            .get_current_screen_infos()
+            .map(|display| CaptureMetadata::new(&display, mode, crop));
+        //l'area selezionata viene proposta per la creazione di un preset di regione
+        if let (Some(crop), Some(md)) = (crop, &self.capture_metadata) {
+            self.region_presets
+                .borrow_mut()
+                .set_last_selection(md.screen_id, crop);
+        }
+        self.remember_capture(None);
         if let Some((rect, img)) = opt_rect_img {
             frame.set_decorations(true);
             frame.set_fullscreen(false);
@@ -341,39 +606,257 @@ impl GlobalGuiState {
         } else {
             frame.set_visible(false);
             ctx.request_repaint();
+            self.start_cursor_capture();
             self.state = EnumGuiState::LoadingEditImage(
                 self.screens_manager.start_thread_fullscreen_screenshot(),
             );
         }
     }
 
-    /// Richiama <i>Receiver::try_recv()</i> sul receiver memorizzato nello stato corrente:
+    /// Se l'utente ha chiesto di includere il puntatore del mouse negli screenshot, lancia il thread che ne legge
+    /// l'immagine e la posizione, da eseguire assieme a quello che cattura lo schermo.
+    fn start_cursor_capture(&mut self) {
+        self.cursor = None;
+        self.cursor_receiver = self
+            .include_cursor
+            .get()
+            .then(window_capture::start_thread_cursor_image);
+    }
+
+    /// Legge, senza bloccare, il puntatore del mouse richiesto da <i>Self::start_cursor_capture()</i> e lo memorizza in
+    /// <i>self.cursor</i>. Ritorna true finché il thread worker non ha ancora inviato il risultato.<br>
+    /// Se non è stato possibile leggere il puntatore, l'errore viene solo scritto su stderr.
+    fn poll_cursor(&mut self) -> bool {
+        if let Some(rx) = &self.cursor_receiver {
+            match rx.try_recv() {
+                Err(TryRecvError::Empty) => return true,
+                Ok(Ok(cursor)) => self.cursor = Some(cursor),
+                Ok(Err(error_message)) => {
+                    let _ = writeln!(std::io::stderr(), "{}", error_message);
+                }
+                Err(TryRecvError::Disconnected) => (),
+            }
+            self.cursor_receiver = None;
+        }
+        false
+    }
+
+    /// Ritorna il puntatore del mouse letto da <i>Self::poll_cursor()</i>, con la posizione riportata in coordinate dello
+    /// screenshot descritto da <i>self.capture_metadata</i>: l'origine dello schermo, in coordinate logiche, viene
+    /// convertita in pixel fisici come il puntatore e il ritaglio.
+    /// Ritorna None se il puntatore non è stato richiesto o non è stato possibile leggerlo: lo screenshot viene
+    /// comunque aperto.
+    fn receive_cursor(&mut self) -> Option<CursorImage> {
+        let cursor = self.cursor.take()?;
+        let md = self.capture_metadata.as_ref()?;
+        let display = self.screens_manager.get_screen_infos(md.screen_id)?;
+        let (crop_x, crop_y) = md.crop.map_or((0, 0), |(x, y, ..)| (x as i32, y as i32));
+        let origin_x = (display.x as f32 * display.scale_factor).round() as i32;
+        let origin_y = (display.y as f32 * display.scale_factor).round() as i32;
+        Some(cursor.relative_to(origin_x + crop_x, origin_y + crop_y))
+    }
+
+    /// Avvia il thread che cattura la finestra indicata da <b>settings</b> (eventualmente attendendo che l'utente la
+    /// scelga con un click) e cambia lo stato in <i>EnumGuiState::LoadingWindow</i>.
+    /// L'applicazione resta invisibile durante la cattura, per non coprire la finestra da scegliere.
+    fn switch_to_window_capture(&mut self, settings: WindowCaptureSettings, frame: &mut eframe::Frame) {
+        frame.set_visible(false);
+        self.image_source = ImageSource::Window;
+        self.capture_metadata = self
+            .screens_manager
+            .get_current_screen_infos()
+            .map(|display| CaptureMetadata::new(&display, ScreenshotDim::Window, None));
+        self.remember_capture(Some(settings));
+        self.cursor_receiver = None;
+        self.cursor = None;
+        self.state = EnumGuiState::LoadingWindow(window_capture::start_thread_window_screenshot(settings));
+    }
+
+    /// Attende, mostrando uno spinner, la cattura avviata da <i>Self::switch_to_window_capture()</i>. Quando arriva,
+    /// sostituisce i metadati della cattura con quelli dello schermo su cui si trova la finestra (vedi
+    /// <i>ScreensManager::window_metadata()</i>) e passa l'immagine allo stato <i>EnumGuiState::LoadingEditImage</i>,
+    /// che gestisce anche gli errori.
+    ///
+    /// <h3>Panics:</h3>
+    /// Nel caso <i>self.state</i> sia diverso da <i>EnumGuiState::LoadingWindow</i>.
+    fn load_window(&mut self, ctx: &eframe::egui::Context) {
+        if let EnumGuiState::LoadingWindow(r) = &mut self.state {
+            let (tx, rx) = channel();
+            match r.try_recv() {
+                Err(TryRecvError::Empty) => {
+                    show_loading(ctx);
+                    return;
+                }
+                Ok(res) => {
+                    let _ = tx.send(res.map(|(img, rect)| {
+                        let rect = (rect.x, rect.y, rect.width, rect.height);
+                        self.capture_metadata = self.screens_manager.window_metadata(rect);
+                        img
+                    }));
+                }
+                //il canale chiuso viene segnalato da load_edit_image()
+                Err(TryRecvError::Disconnected) => (),
+            }
+            self.state = EnumGuiState::LoadingEditImage(rx);
+            ctx.request_repaint();
+        } else {
+            unreachable!();
+        }
+    }
+
+    /// Avvia il thread che cattura la regione di <b>preset</b> sul suo schermo, senza mostrare la selezione dell'area,
+    /// e cambia lo stato in <i>EnumGuiState::LoadingEditImage</i>.
+    fn switch_to_region_capture(&mut self, preset: RegionPreset, frame: &mut eframe::Frame) {
+        frame.set_visible(false);
+        self.image_source = ImageSource::Region;
+        let crop = (preset.x, preset.y, preset.width, preset.height);
+        self.capture_metadata = self
+            .screens_manager
+            .get_screen_infos(preset.screen_id)
+            .map(|display| CaptureMetadata::new(&display, ScreenshotDim::Region, Some(crop)));
+        self.remember_capture(None);
+        self.start_cursor_capture();
+        self.state = EnumGuiState::LoadingEditImage(
+            self.screens_manager
+                .start_thread_screen_screenshot(preset.screen_id, Some(preset.rect())),
+        );
+    }
+
+    /// Cattura di nuovo lo schermo e l'area di <b>last</b>, senza mostrare la selezione dell'area, e cambia lo stato in
+    /// <i>EnumGuiState::LoadingEditImage</i>. Le catture di una finestra vengono ripetute con
+    /// <i>Self::switch_to_window_capture()</i>.
+    fn repeat_capture(&mut self, last: LastCapture, frame: &mut eframe::Frame) {
+        if let Some(settings) = last.window {
+            self.switch_to_window_capture(settings, frame);
+            return;
+        }
+        frame.set_visible(false);
+        self.image_source = ImageSource::Region;
+        self.capture_metadata = self
+            .screens_manager
+            .get_screen_infos(last.screen_id)
+            .map(|display| CaptureMetadata::new(&display, last.mode.clone(), last.area));
+        let area = last.area.map(|(x, y, width, height)| {
+            Rect::from_min_size(
+                pos2(x as f32, y as f32),
+                Vec2::new(width as f32, height as f32),
+            )
+        });
+        self.remember_capture(None);
+        self.start_cursor_capture();
+        self.state = EnumGuiState::LoadingEditImage(
+            self.screens_manager.start_thread_screen_screenshot(last.screen_id, area),
+        );
+    }
+
+    /// Memorizza (anche nel file di configurazione) i parametri della cattura descritta da <i>self.capture_metadata</i>,
+    /// con il delay della cattura in corso e l'eventuale finestra catturata.
+    fn remember_capture(&mut self, window: Option<WindowCaptureSettings>) {
+        let delay = std::mem::take(&mut self.capture_delay);
+        if let Some(md) = &self.capture_metadata {
+            let last = LastCapture {
+                mode: md.mode.clone(),
+                screen_id: md.screen_id,
+                area: md.crop,
+                delay,
+                window,
+            };
+            last.start_thread_serialize();
+            self.last_capture.borrow_mut().replace(last);
+        }
+    }
+
+    /// Avvia il thread che legge l'immagine salvata in <b>path</b> e cambia lo stato in
+    /// <i>EnumGuiState::LoadingEditImage</i>, come avviene dopo uno screenshot.
+    /// Il path viene memorizzato in <i>self.image_source</i>, per offrire all'utente la possibilità di sovrascrivere il file.
+    fn switch_to_open_image(&mut self, path: PathBuf) {
+        self.state = EnumGuiState::LoadingEditImage(image_coding::start_thread_load_image(
+            path.clone(),
+        ));
+        self.image_source = ImageSource::File(path);
+        self.capture_metadata = None;
+    }
+
+    /// Avvia il thread che legge l'immagine contenuta nella clipboard e cambia lo stato in
+    /// <i>EnumGuiState::LoadingEditImage</i>, come avviene dopo uno screenshot.
+    fn switch_to_clipboard_image(&mut self) {
+        self.state =
+            EnumGuiState::LoadingEditImage(image_coding::start_thread_paste_from_clipboard());
+        self.image_source = ImageSource::Clipboard;
+        self.capture_metadata = None;
+    }
+
+    /// Finché il puntatore del mouse richiesto assieme allo screenshot non è stato ricevuto (vedi
+    /// <i>Self::poll_cursor()</i>), mostra uno spinner; poi richiama <i>Receiver::try_recv()</i> sul receiver memorizzato
+    /// nello stato corrente:
     /// - Se la <i>recv()</i> ha successo:
-    ///     1. avvia il thread per copiare nella clipboard l'immagine ricevuta tramite il canale;
-    ///     2. richiama EditImage::new(), a cui passa l'immagine ricevuta tramite il canale;
+    ///     1. se si tratta di uno screenshot, avvia il thread per copiare nella clipboard l'immagine ricevuta;
+    ///     2. richiama EditImage::new(), a cui passa l'immagine ricevuta tramite il canale, l'eventuale path di origine e
+    ///        l'eventuale puntatore del mouse (vedi <i>Self::receive_cursor()</i>);
     ///     3. cambia lo stato corrente in <i>EnumGuiState::EditImage</i>, in cui memorizza una nuova istanza di <i>EditImage</i>.
+    ///
+    ///   Se invece l'immagine è stata ottenuta ripetendo l'ultima cattura e le impostazioni prevedono il salvataggio
+    ///   diretto, avvia la procedura di salvataggio in formato png e torna alla schermata principale.
     /// - Se il canale è vuoto, mostra uno spinner;
+    /// - Se la lettura di un file o della clipboard, o la cattura di una finestra, è fallita, scrive il messaggio ricevuto
+    ///   nello stato di errore globale
+    ///   (ad esempio, se la clipboard non contiene un'immagine);
     /// - Se il canale è stato chiuso inaspettatamente, scrive un messaggio di errore nello stato di errore globale.
     ///
     /// <h3>Panics:</h3>
     /// Nel caso <i>self.state</i> sia diverso da <i>EnumGuiState::LoadingEditImage</i>.
     fn load_edit_image(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
+        if self.poll_cursor() {
+            show_loading(ctx);
+            return;
+        }
         if let EnumGuiState::LoadingEditImage(r) = &mut self.state {
             match r.try_recv() {
                 Ok(Ok(img)) => {
-                    if self.save_settings.borrow().get_copy_on_clipboard() {
-                        self.clipboard = Some(start_thread_copy_to_clipboard(&img));
+                    let cursor = self.receive_cursor();
+                    //immagine con il puntatore, per le copie che non passano dall'editor
+                    let flattened = cursor.as_ref().map(|cursor| {
+                        let mut img = img.clone();
+                        cursor.draw_onto(&mut img);
+                        img
+                    });
+                    if matches!(
+                        self.image_source,
+                        ImageSource::Screenshot | ImageSource::Window | ImageSource::Region
+                    )
+                        && self.save_settings.borrow().get_copy_on_clipboard()
+                    {
+                        self.clipboard = Some(start_thread_copy_to_clipboard(
+                            flattened.as_ref().unwrap_or(&img),
+                        ));
+                    }
+                    if std::mem::take(&mut self.repeat_save) {
+                        let metadata = self.capture_metadata.clone();
+                        let format = self.save_settings.borrow().get_repeat_save_format();
+                        self.manage_save_request(flattened.unwrap_or(img), format, metadata);
+                        self.switch_to_main_menu(frame);
+                        return;
                     }
                     frame.set_decorations(true);
                     frame.set_fullscreen(false);
                     frame.set_maximized(false);
                     frame.set_visible(true);
-                    let em = EditImage::new(img, ctx);
+                    let source =
+                        std::mem::replace(&mut self.image_source, ImageSource::Screenshot);
+                    let source_path = match source {
+                        ImageSource::File(path) => Some(path),
+                        _ => None,
+                    };
+                    let em = EditImage::new(img, ctx, source_path, cursor);
                     self.state = EnumGuiState::EditImage(em);
                 }
                 Err(TryRecvError::Empty) => {
                     show_loading(ctx);
                 }
+                Ok(Err(error_message)) if !matches!(self.image_source, ImageSource::Screenshot) => {
+                    self.alert.borrow_mut().replace(error_message.to_string());
+                    self.switch_to_main_menu(frame);
+                }
                 Err(TryRecvError::Disconnected) | Ok(Err(_)) => {
                     self.alert
                         .borrow_mut()
@@ -392,6 +875,9 @@ impl GlobalGuiState {
     /// - <i>EditImageEvent::Aborted</i>: ritorna alla schermata principale eliminando tutti i progressi;
     /// - <i>EditImageEvent::Nil</i>: non è necessaria alcuna azione.
     ///
+    /// Se è stata richiesta la sovrascrittura del file di origine, il salvataggio avviene direttamente in quel path,
+    /// senza consultare le impostazioni di salvataggio.
+    ///
     /// <h3>Panics:</h3>
     /// Nel caso <i>self.state</i> sia diverso da <i>EnumGuiState::EditImage</i>.
     fn show_edit_image(
@@ -402,9 +888,40 @@ impl GlobalGuiState {
     ) {
         if let EnumGuiState::EditImage(em) = &mut self.state {
             match em.update(ctx, enabled) {
-                FrameEvent::Saved { image, format , clipboard_receiver} => {
+                FrameEvent::Saved {
+                    image,
+                    format,
+                    clipboard_receiver,
+                    overwrite,
+                    cut,
+                } => {
                     self.clipboard = Some(clipboard_receiver);
-                    self.manage_save_request(image, format);
+                    match overwrite {
+                        Some(path) => {
+                            self.state =
+                                EnumGuiState::Saving(image_coding::start_thread_save_image(
+                                    path,
+                                    image,
+                                    format,
+                                    self.save_settings.borrow().get_encoder_options(),
+                                    None,
+                                ));
+                        }
+                        None => {
+                            //i metadati descrivono l'area salvata, compreso il ritaglio fatto nell'editor
+                            let metadata = self.capture_metadata.clone().map(|mut md| {
+                                if let Some(cut) = cut {
+                                    md.apply_cut(cut);
+                                }
+                                md
+                            });
+                            self.manage_save_request(image, format, metadata);
+                        }
+                    }
+                }
+                FrameEvent::CopyAsText { image, export } => {
+                    let options = self.save_settings.borrow().get_encoder_options();
+                    self.start_copy_as_text(export, ExportSource::Image(image, options));
                 }
                 FrameEvent::Aborted => {
                     self.switch_to_main_menu(frame);
@@ -421,9 +938,16 @@ impl GlobalGuiState {
     /// 2. salva in <i>GlobalGuiState</i>il <i>Receiver</i> del canale di comunicazione con il thread.
     /// La presenza di tale <i>Receiver</i> nello stato globale causerà la disabilitazione
     /// dell'altra finestra attualmente mostrata.
-    fn manage_save_request(&mut self, image: RgbaImage, format: ImageFormat) {
+    ///
+    /// I metadati <i>metadata</i> della cattura vengono scritti nel file, se il formato li supporta.
+    fn manage_save_request(
+        &mut self,
+        image: RgbaImage,
+        format: ImageFormat,
+        metadata: Option<CaptureMetadata>,
+    ) {
         let rx = self.save_settings.borrow().compose_output_file_path(format);
-        self.pending_save_request = Some((rx, image));
+        self.pending_save_request = Some((rx, SavePayload::Image(image, format, metadata)));
     }
 
     ///In seguito alla creazione di una richiesta di salvataggio, gestisce l'attesa (ripetendo
@@ -435,13 +959,30 @@ impl GlobalGuiState {
     /// - se è None, elimina la richiesta di salvataggio.
     /// Se il canale si è chiuso, segnala l'errore.
     fn wait_output_file_path(&mut self) {
-        if let Some((rx, _)) = &self.pending_save_request {
+        if let Some((rx, ..)) = &self.pending_save_request {
             match rx.try_recv() {
                 //L'utente non ha annullato il salvataggio e il path di output è disponibile:
                 Ok(Some(pb)) => {
-                    if let Some((_, img)) = self.pending_save_request.take() {
-                        self.state =
-                            EnumGuiState::Saving(image_coding::start_thread_save_image(pb, img));
+                    let options = self.save_settings.borrow().get_encoder_options();
+                    match self.pending_save_request.take() {
+                        Some((_, SavePayload::Image(img, format, metadata))) => {
+                            self.state = EnumGuiState::Saving(
+                                image_coding::start_thread_save_image(
+                                    pb, img, format, options, metadata,
+                                ),
+                            );
+                        }
+                        Some((_, SavePayload::Animation(frames, frame_delay, settings))) => {
+                            self.state =
+                                EnumGuiState::Saving(image_coding::start_thread_save_animation(
+                                    pb,
+                                    frames,
+                                    frame_delay,
+                                    settings.format,
+                                    options,
+                                ));
+                        }
+                        None => (),
                     }
                 }
                 //L'utente ha annullato il salvataggio => viene eliminata la richiesta pending:
@@ -461,23 +1002,197 @@ impl GlobalGuiState {
         }
     }
 
+    //----------------------RECORDING --------------------------------------------------
+    /// Avvia il thread worker che registra lo schermo selezionato (ritagliando i frame su <i>area</i>, se specificata)
+    /// e cambia lo stato in <i>EnumGuiState::Recording</i>.<br>
+    /// La finestra viene mostrata ridotta, senza decorazioni e sempre in primo piano, per permettere all'utente di
+    /// vedere il tempo trascorso e di interrompere la registrazione: la sua area viene mascherata nei frame (vedi
+    /// <i>Self::status_window_rect()</i>), e il thread worker inizia a registrare dopo averla ricevuta.
+    fn start_recording(
+        &mut self,
+        area: Option<Rect>,
+        settings: RecordingSettings,
+        frame: &mut eframe::Frame,
+    ) {
+        frame.set_decorations(false);
+        frame.set_fullscreen(false);
+        frame.set_maximized(false);
+        frame.set_window_size(Vec2::new(250.0, 120.0));
+        frame.set_always_on_top(true);
+        frame.set_visible(true);
+        let stop = Arc::new(AtomicBool::new(false));
+        let excluded = Arc::new(ExcludedArea::default());
+        let rx = self.screens_manager.start_thread_recording(
+            area,
+            settings.fps,
+            Duration::from_secs_f64(settings.duration),
+            stop.clone(),
+            Some(excluded.clone()),
+        );
+        self.state = EnumGuiState::Recording(Recording::new(rx, stop, excluded, settings));
+    }
+
+    /// Rettangolo occupato dalla finestra, in pixel fisici dello screenshot dello schermo selezionato: la posizione,
+    /// riportata da eframe in punti rispetto al primo schermo, viene convertita in pixel fisici e riferita all'origine
+    /// dello schermo (in coordinate logiche, come per il puntatore del mouse). Il rettangolo viene allargato di qualche
+    /// pixel, per coprire eventuali arrotondamenti e ombre.<br>
+    /// Ritorna None se il sistema non comunica la posizione della finestra.
+    fn status_window_rect(&self, frame: &eframe::Frame) -> Option<Rect> {
+        let info = frame.info();
+        let pos = info.window_info.position?;
+        let ppp = info.native_pixels_per_point.unwrap_or(1.0);
+        let display = self.screens_manager.get_current_screen_infos()?;
+        let origin = Vec2::new(display.x as f32, display.y as f32) * display.scale_factor;
+        let min = (pos.to_vec2() * ppp - origin).to_pos2();
+        Some(Rect::from_min_size(min, info.window_info.size * ppp).expand(4.0))
+    }
+
+    /// Comunica al thread worker l'area attuale della finestra di stato, da mascherare nei frame, poi richiama
+    /// <i>Recording::update()</i> e, quando la registrazione è terminata:
+    /// - se sono stati catturati dei frame, torna alla schermata principale e avvia la procedura di salvataggio
+    ///   dell'animazione (vedi <i>Self::manage_save_request()</i>); se la registrazione è stata interrotta dal limite
+    ///   di memoria, lo segnala nello stato di errore globale;
+    /// - altrimenti, scrive l'errore nello stato di errore globale.
+    ///
+    /// <h3>Panics:</h3>
+    /// Nel caso <i>self.state</i> sia diverso da <i>EnumGuiState::Recording</i>.
+    fn show_recording(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
+        let status_rect = self.status_window_rect(frame);
+        if let EnumGuiState::Recording(r) = &mut self.state {
+            if let Some(rect) = status_rect {
+                r.exclude(rect);
+            }
+            let settings = r.get_settings();
+            match r.update(ctx) {
+                Some(_) if r.is_cancelled() => self.switch_to_main_menu(frame),
+                Some(Ok((frames, frame_delay, limit_reached))) if !frames.is_empty() => {
+                    self.switch_to_main_menu(frame);
+                    if limit_reached {
+                        self.alert.borrow_mut().replace(format!(
+                            "Recording stopped: the frames reached the memory limit of {} MiB. The frames recorded so far can still be saved.",
+                            MAX_RECORDING_BYTES >> 20
+                        ));
+                    }
+                    let rx = self
+                        .save_settings
+                        .borrow()
+                        .compose_output_file_path(settings.format.image_format());
+                    self.pending_save_request = Some((
+                        rx,
+                        SavePayload::Animation(frames, frame_delay, settings),
+                    ));
+                }
+                Some(Ok(_)) => {
+                    self.alert
+                        .borrow_mut()
+                        .replace("Error: no frames recorded".to_string());
+                    self.switch_to_main_menu(frame);
+                }
+                Some(Err(e)) => {
+                    self.alert.borrow_mut().replace(e.to_string());
+                    self.switch_to_main_menu(frame);
+                }
+                None => (),
+            }
+        } else {
+            unreachable!();
+        }
+    }
+
+    //----------------------TIMELAPSE --------------------------------------------------
+    const TIMELAPSE_DIR_MISSING: &'static str =
+        "A timelapse saves the shots in the default directory: enable it in the save settings";
+
+    /// Avvia il thread worker che esegue la timelapse sullo schermo selezionato (ritagliando gli scatti su <i>area</i>,
+    /// se specificata) e cambia lo stato in <i>EnumGuiState::Timelapse</i>.<br>
+    /// Gli scatti vengono salvati direttamente, con i nomi e nella cartella di default delle impostazioni di salvataggio
+    /// (vedi <i>SaveSettings::timelapse_saver()</i>): se la cartella di default non è più disponibile, scrive un messaggio
+    /// nello stato di errore globale e torna alla schermata principale.<br>
+    /// La finestra viene mostrata ridotta, con i contatori degli scatti, e nascosta durante ogni scatto (vedi
+    /// <i>ShotGate</i>).
+    fn start_timelapse(
+        &mut self,
+        area: Option<Rect>,
+        settings: TimelapseSettings,
+        frame: &mut eframe::Frame,
+    ) {
+        let fallback_name = if settings.watch.is_some() { "change" } else { "timelapse" };
+        let saver = self
+            .save_settings
+            .borrow()
+            .timelapse_saver(settings.format, fallback_name);
+        let saver = match saver {
+            Some(saver) => saver,
+            None => {
+                self.alert.borrow_mut().replace(Self::TIMELAPSE_DIR_MISSING.to_string());
+                self.switch_to_main_menu(frame);
+                return;
+            }
+        };
+        frame.set_decorations(true);
+        frame.set_fullscreen(false);
+        frame.set_maximized(false);
+        frame.set_window_size(Vec2::new(300.0, 150.0));
+        frame.set_visible(true);
+        let stop = Arc::new(AtomicBool::new(false));
+        let gate = Arc::new(ShotGate::default());
+        let rx = self.screens_manager.start_thread_timelapse(
+            area,
+            settings,
+            stop.clone(),
+            Some(gate.clone()),
+            saver,
+        );
+        self.state = EnumGuiState::Timelapse(Timelapse::new(rx, stop, gate, settings));
+    }
+
+    /// Applica la visibilità della finestra chiesta dal thread worker, poi richiama <i>Timelapse::update()</i> e,
+    /// quando la timelapse è terminata, torna alla schermata principale, scrivendo nello stato di errore globale il
+    /// numero di scatti salvati oppure l'errore che l'ha interrotta.
+    ///
+    /// <h3>Panics:</h3>
+    /// Nel caso <i>self.state</i> sia diverso da <i>EnumGuiState::Timelapse</i>.
+    fn show_timelapse(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
+        if let EnumGuiState::Timelapse(t) = &mut self.state {
+            if let Some(visible) = t.window_visibility() {
+                frame.set_visible(visible);
+            }
+            match t.update(ctx) {
+                Some(Ok(saved)) => {
+                    self.alert
+                        .borrow_mut()
+                        .replace(format!("Timelapse finished: {} shots saved", saved));
+                    self.switch_to_main_menu(frame);
+                }
+                Some(Err(e)) => {
+                    let _ = writeln!(std::io::stderr(), "Error: {}", e);
+                    self.alert
+                        .borrow_mut()
+                        .replace("Error: timelapse interrupted".to_string());
+                    self.switch_to_main_menu(frame);
+                }
+                None => (),
+            }
+        } else {
+            unreachable!();
+        }
+    }
+
     //----------------------SAVING --------------------------------------------------
     /// Esegue busy waiting sul canale di comunicazione con il thread worker iterando la chiamata al metodo <i>Receiver::try_recv()</i>:
     /// - Fino a quando non compare un messaggio nel canale, mostra uno spinner;
     /// - Se il canale viene chiuso inaspettatamente o se nel canale compare un oggetto <i>Err()</i>, scrive un messaggio nello stato di
     ///     errore globale dell'applicazione;
-    /// - Se nel canale compare un oggetto Ok(), mostra un alert con un messaggio di conferma e riporta l'applicazione nella schermata
-    ///     di partenza.
+    /// - Se nel canale compare un oggetto Ok(), mostra la finestra di conferma del salvataggio (vedi <i>SavedAlert</i>) e
+    ///     riporta l'applicazione nella schermata di partenza.
     ///
     /// <h3>Panics:</h3>
     /// Nel caso in cui <i>self.state</i> sia diverso da <i>EnumGuiState::Saving</i>.
     fn show_saving(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
         if let EnumGuiState::Saving(rx) = &mut self.state {
             match rx.try_recv() {
-                Ok(Ok(path)) => {
-                    let mut msg = String::from("Image saved: \n\n");
-                    msg.push_str(&path);
-                    self.alert.borrow_mut().replace(msg);
+                Ok(Ok(saved)) => {
+                    self.saved_alert = Some(SavedAlert::new(saved));
                     self.switch_to_main_menu(frame);
                 }
                 Err(TryRecvError::Empty) => show_loading(ctx),
@@ -496,16 +1211,130 @@ impl GlobalGuiState {
     /// Esegue l'azione relativa alla hotkey <b>hn</b>.
     /// Se la hotkey è stata premuta mentre la finestra dell'applicazione non era in primo piano, la mette in primo
     /// piano con la chiamata a <i>frame.focus()</i>.
+    /// Durante una registrazione (o una timelapse), le uniche hotkeys gestite sono quelle che la interrompono:
+    /// <i>Cancel</i> interrompe la registrazione scartando i frame catturati. Durante la selezione di un'area,
+    /// <i>Cancel</i> torna alla schermata principale.
     fn hotkey_reaction(
         &mut self,
         hn: HotkeyName,
         ctx: &eframe::egui::Context,
         frame: &mut eframe::Frame,
     ) {
+        match &mut self.state {
+            EnumGuiState::Recording(r) => {
+                match hn {
+                    HotkeyName::StopRecording => r.stop(),
+                    HotkeyName::Cancel => r.cancel(),
+                    _ => (),
+                }
+                return;
+            }
+            EnumGuiState::Timelapse(t) => {
+                if hn == HotkeyName::StopRecording || hn == HotkeyName::Cancel {
+                    t.stop();
+                }
+                return;
+            }
+            EnumGuiState::RectSelection(..) if hn == HotkeyName::Cancel => {
+                self.switch_to_main_menu(frame);
+                return;
+            }
+            _ => (),
+        }
         frame.focus();
         match hn {
             HotkeyName::FullscreenScreenshot => self.switch_to_edit_image(None, ctx, frame),
             HotkeyName::RectScreenshot => self.switch_to_rect_selection(frame),
+            HotkeyName::StopRecording | HotkeyName::Cancel => (),
+            HotkeyName::StartRecording => {
+                self.pending_recording = Some(RecordingSettings::default());
+                self.start_wait_delay(0.0, ScreenshotDim::Fullscreen, frame, ctx);
+            }
+            HotkeyName::OpenClipboardImage => self.switch_to_clipboard_image(),
+            HotkeyName::WindowScreenshot => {
+                self.pending_window = Some(WindowCaptureSettings::default());
+                self.start_wait_delay(0.0, ScreenshotDim::Window, frame, ctx);
+            }
+            HotkeyName::RepeatLastCapture => self.start_repeat_last_capture(frame, ctx),
+            HotkeyName::RegionPreset(i) => {
+                let preset = self.region_presets.borrow().get(i).cloned();
+                match preset {
+                    Some(preset) => {
+                        self.pending_region = Some(preset);
+                        self.start_wait_delay(0.0, ScreenshotDim::Region, frame, ctx);
+                    }
+                    None => {
+                        self.alert
+                            .borrow_mut()
+                            .replace(format!("Region preset {} does not exist", i + 1));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Legge le modifiche agli schermi collegati notificate dal watcher: la lista degli schermi è già stata aggiornata,
+    /// per cui è sufficiente avvisare l'utente se lo schermo selezionato è stato scollegato.
+    fn manage_screens_change(&mut self) {
+        if let Some(rx) = &self.screens_receiver {
+            match rx.try_recv() {
+                Ok(change) => {
+                    if change.selection_lost {
+                        self.alert.borrow_mut().replace(
+                            "The selected screen has been disconnected: the primary screen is now selected"
+                                .to_string(),
+                        );
+                    }
+                }
+                Err(TryRecvError::Empty) => (),
+                Err(TryRecvError::Disconnected) => self.screens_receiver = None,
+            }
+        }
+    }
+
+    /// Avvia il thread che copia l'immagine nella clipboard come testo nel formato <b>export</b>.
+    fn start_copy_as_text(&mut self, export: TextExport, source: ExportSource) {
+        self.text_export = Some((export, image_coding::start_thread_copy_as_text(export, source)));
+    }
+
+    /// Esegue busy waiting sul canale di comunicazione con il thread worker che sta copiando l'immagine come testo nella
+    /// clipboard. Se la copia è andata a buon fine e la finestra di conferma del salvataggio è ancora aperta, lo segnala
+    /// nella finestra; altrimenti mostra l'errore.
+    fn manage_text_export(&mut self) {
+        if let Some((export, rx)) = &self.text_export {
+            match rx.try_recv() {
+                Ok(Ok(())) => {
+                    if let Some(sa) = &mut self.saved_alert {
+                        sa.set_copied(*export);
+                    }
+                    self.text_export = None;
+                }
+                Ok(Err(e)) => {
+                    self.alert.borrow_mut().replace(e.to_string());
+                    self.text_export = None;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    self.alert.borrow_mut().replace(
+                        "Error: impossible to copy the text on the clipboard".to_string(),
+                    );
+                    self.text_export = None;
+                }
+                Err(TryRecvError::Empty) => (),
+            }
+        }
+    }
+
+    /// Mostra la finestra di conferma dell'ultimo salvataggio e gestisce i pulsanti premuti.
+    fn show_saved_alert(&mut self, ctx: &eframe::egui::Context) {
+        if let Some(sa) = &mut self.saved_alert {
+            match sa.update(ctx) {
+                SavedAlertEvent::CopyAsText(export) => {
+                    let path = PathBuf::from(sa.path());
+                    self.start_copy_as_text(export, ExportSource::File(path));
+                }
+                SavedAlertEvent::Closed => self.saved_alert = None,
+                SavedAlertEvent::Nil => (),
+            }
         }
     }
 
@@ -537,7 +1366,8 @@ impl GlobalGuiState {
     }
 }
 
-pub fn launch_gui() {
+/// Avvia la gui. Se <b>open_file</b> è specificato, l'immagine contenuta nel file viene aperta direttamente nell'editor.
+pub fn launch_gui(open_file: Option<PathBuf>) {
     let options = NativeOptions {
         min_window_size: Some(Vec2::new(600.0, 300.0)),
         ..Default::default()
@@ -545,12 +1375,18 @@ pub fn launch_gui() {
     eframe::run_native(
         "Simple screenshot App",
         options,
-        Box::new(|_cc| Box::new(GlobalGuiState::new())),
+        Box::new(|_cc| Box::new(GlobalGuiState::new(open_file))),
     )
     .unwrap();
 }
 
 impl eframe::App for GlobalGuiState {
+    /// Prima della chiusura dell'applicazione, termina il servizio che possiede la clipboard, in modo che l'ultima
+    /// immagine copiata rimanga disponibile (se nel sistema è presente un clipboard manager).
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        image_coding::stop_clipboard_service();
+    }
+
     /// Attiva di default l'ascolto della pressione delle hotkeys: potrà essere eventualmente disattivato dai metodi che verranno
     /// richiamati successivamente da questo metodo. Si è scelto questo approccio perché sono poche le casistiche in cui l'ascolto
     /// debba essere disattivato.<br>
@@ -559,11 +1395,14 @@ impl eframe::App for GlobalGuiState {
     /// Solo se attualmente non è mostrato nessun alert e nessun file dialog, controlla se nell'input di questo frame c'è la pressione di una hotkey:
     /// in caso positivo, la gestisce.
     /// Se invece lo stato di errore globale non è vuoto, mostra un alert con il messaggio che descrive tale errore.
+    /// Se è appena terminato un salvataggio, mostra la relativa finestra di conferma.
     /// Se invece è aperto un file dialog, mostra la schermata corrente disabilitata.
     fn update(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
         let main_window_enabled = self.alert.borrow().is_none()
+            && self.saved_alert.is_none()
             && self.pending_save_request.is_none()
-            && self.directory_dialog_receiver.is_none();
+            && self.directory_dialog_receiver.is_none()
+            && self.open_image_dialog_receiver.is_none();
 
         //se non è ancora stato fatto partire il thread che ascolta le hotkey, si crea un canale di comunicazione e si richiama l'apposita funzione del modulo hotkeys.
         //È necessario mettere questa istruzione all'interno di GlobalGuiState::update() per poter avere
@@ -579,8 +1418,20 @@ impl eframe::App for GlobalGuiState {
             );
         }
 
+        //allo stesso modo viene lanciato (o rilanciato) il thread che rileva le modifiche agli schermi collegati
+        if self.screens_receiver.is_none() {
+            self.screens_receiver = Some(
+                self.screens_manager
+                    .start_thread_watch_screens(Arc::new(ctx.clone()), Duration::from_secs(2)),
+            );
+        }
+        self.manage_screens_change();
+
         self.registered_hotkeys.set_listen_enabled(true); //abilito di default l'ascolto delle hotkeys (potrà essere disabilitato dalle funzioni chiamate nei rami del match)
+        //registra (o rilascia) i passi successivi della sequenza di tasti in corso, segnalata dal thread di ascolto
+        self.registered_hotkeys.update_sequence_hotkeys();
         self.manage_clipboard();
+        self.manage_text_export();
 
         match &mut self.state {
             EnumGuiState::MainMenu(..) => {
@@ -595,12 +1446,21 @@ impl eframe::App for GlobalGuiState {
             EnumGuiState::RectSelection(..) => {
                 self.show_rect_selection(ctx, frame);
             }
+            EnumGuiState::LoadingWindow(..) => {
+                self.load_window(ctx);
+            }
             EnumGuiState::LoadingEditImage(..) => {
                 self.load_edit_image(ctx, frame);
             }
             EnumGuiState::EditImage(..) => {
                 self.show_edit_image(ctx, frame, main_window_enabled);
             }
+            EnumGuiState::Recording(..) => {
+                self.show_recording(ctx, frame);
+            }
+            EnumGuiState::Timelapse(..) => {
+                self.show_timelapse(ctx, frame);
+            }
             EnumGuiState::Saving(..) => {
                 self.show_saving(ctx, frame);
             }
@@ -625,6 +1485,13 @@ impl eframe::App for GlobalGuiState {
             if self.alert.borrow().is_some() {
                 error_alert::show_error_alert(ctx, &mut self.alert.borrow_mut())
             }
+            //conferma dell'ultimo salvataggio
+            if self.saved_alert.is_some() {
+                self.show_saved_alert(ctx);
+                if self.text_export.is_some() {
+                    ctx.request_repaint();
+                }
+            }
             //attesa del risultato del file dialog
             if self.pending_save_request.is_some() {
                 self.wait_output_file_path();
@@ -632,6 +1499,9 @@ impl eframe::App for GlobalGuiState {
             } else if self.directory_dialog_receiver.is_some() {
                 self.wait_directory_dialog();
                 ctx.request_repaint();
+            } else if self.open_image_dialog_receiver.is_some() {
+                self.wait_open_image_dialog();
+                ctx.request_repaint();
             }
         }
     }
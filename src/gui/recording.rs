@@ -0,0 +1,103 @@
+use crate::itc::RecordingSettings;
+use crate::screens_manager::{ExcludedArea, RecordingResult};
+use eframe::egui::{self, CentralPanel, Context, Rect};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Stato della parte di gui mostrata durante una registrazione animata dello schermo.<br>
+/// La cattura dei frame è eseguita da un thread worker (vedi <i>ScreensManager::start_thread_recording()</i>),
+/// che si ferma allo scadere della durata massima oppure quando viene impostato il flag <i>stop</i>, condiviso con
+/// questa struct.<br>
+/// Durante la registrazione la finestra di stato rimane visibile, e la sua area viene mascherata nei frame (vedi
+/// <i>ExcludedArea</i>).
+pub struct Recording {
+    receiver: Receiver<RecordingResult>,
+    stop: Arc<AtomicBool>,
+    ///Area della finestra di stato, condivisa con il thread worker.
+    excluded: Arc<ExcludedArea>,
+    ///Impostato da <i>self::cancel()</i>: i frame catturati devono essere scartati.
+    cancelled: bool,
+    start: Instant,
+    settings: RecordingSettings,
+}
+
+impl Recording {
+    pub fn new(
+        receiver: Receiver<RecordingResult>,
+        stop: Arc<AtomicBool>,
+        excluded: Arc<ExcludedArea>,
+        settings: RecordingSettings,
+    ) -> Self {
+        Self {
+            receiver,
+            stop,
+            excluded,
+            cancelled: false,
+            start: Instant::now(),
+            settings,
+        }
+    }
+
+    /// Chiede al thread worker di terminare la registrazione. Il risultato sarà comunque ricevuto tramite <i>self::update()</i>.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Comunica al thread worker il rettangolo occupato dalla finestra di stato, in pixel fisici dello screenshot dello
+    /// schermo registrato, da mascherare nei frame.
+    pub fn exclude(&self, rect: Rect) {
+        self.excluded.set(rect);
+    }
+
+    /// Come <i>self::stop()</i>, ma i frame catturati fino a quel momento non verranno salvati.
+    pub fn cancel(&mut self) {
+        self.cancelled = true;
+        self.stop();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    pub fn get_settings(&self) -> RecordingSettings {
+        self.settings
+    }
+
+    /// Mostra il tempo trascorso dall'inizio della registrazione e un bottone "Stop".<br>
+    /// Esegue <i>try_recv()</i> sul canale con il thread worker:
+    /// - se il canale è ancora vuoto, ritorna <i>None</i> e chiede un nuovo repaint per aggiornare il tempo mostrato;
+    /// - altrimenti ritorna il risultato della registrazione (o un errore, se il canale è stato chiuso inaspettatamente).
+    pub fn update(&mut self, ctx: &Context) -> Option<RecordingResult> {
+        let ret = match self.receiver.try_recv() {
+            Ok(res) => Some(res),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err("Error: recording interrupted")),
+        };
+
+        CentralPanel::default().show(ctx, |ui| {
+            ui.style_mut().spacing.button_padding = egui::vec2(12.0, 3.0);
+            if self.stop.load(Ordering::Relaxed) {
+                ui.heading("Stopping...");
+            } else {
+                ui.heading("● Recording");
+            }
+            ui.label(format!(
+                "{:.1} s / {:.0} s",
+                self.start.elapsed().as_secs_f64().min(self.settings.duration),
+                self.settings.duration
+            ));
+            ui.add_space(10.0);
+            ui.style_mut().visuals.widgets.hovered.weak_bg_fill = egui::Color32::RED;
+            if ui.button("Stop ⏹").clicked() {
+                self.stop();
+            }
+        });
+
+        if ret.is_none() {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+        ret
+    }
+}
@@ -13,7 +13,10 @@ use image::RgbaImage;
 /// - La <i>TextureHandle</i> viene usata per mostrare tale immagine come sfondo;
 /// - La <i>RgbaImage</i> sarà ritagliata per produrre l'output.<br>
 ///
-/// La struct memorizza inoltre, al suo interno, se e da quale punto è stata avviata un'operazione di drag.
+/// La struct memorizza inoltre, al suo interno, se e da quale punto è stata avviata un'operazione di drag.<br>
+/// Se l'immagine non ha le stesse proporzioni della finestra (ad esempio, se comprende tutti gli schermi del desktop virtuale),
+/// viene mostrata ridotta e centrata: il rettangolo selezionato viene comunque convertito in coordinate dell'immagine,
+/// per cui la selezione può attraversare i confini tra gli schermi.
 pub struct RectSelection {
     texture_handle: TextureHandle,
     start_drag_point: Option<Pos2>,
@@ -56,9 +59,10 @@ impl RectSelection {
                 Vec2::new(ctx.screen_rect().width(), ctx.screen_rect().height()),
                 Sense::click_and_drag(),
             );
+            let image_rect = fit_rect(painter.clip_rect(), self.rgba.dimensions());
             painter.image(
                 self.texture_handle.id(),
-                painter.clip_rect(),
+                image_rect,
                 Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
                 Color32::WHITE,
             );
@@ -73,22 +77,20 @@ impl RectSelection {
                     if let (Some(click_pos), Some(hover_pos)) =
                         (self.start_drag_point, ctx.pointer_hover_pos())
                     {
-                        rect_not_to_be_obscured = Rect::from_points(&[click_pos, hover_pos]);
+                        rect_not_to_be_obscured = Rect::from_points(&[
+                            image_rect.clamp(click_pos),
+                            image_rect.clamp(hover_pos),
+                        ]);
                     }
                 } else if response.drag_released() {
                     if let (Some(click_pos), Some(hover_pos)) =
                         (self.start_drag_point, ctx.pointer_hover_pos())
                     {
+                        let dimensions = self.rgba.dimensions();
                         ret = Some((
                             Rect::from_points(&[
-                                pos2(
-                                    click_pos.x * ctx.pixels_per_point(),
-                                    click_pos.y * ctx.pixels_per_point(),
-                                ),
-                                pos2(
-                                    hover_pos.x * ctx.pixels_per_point(),
-                                    hover_pos.y * ctx.pixels_per_point(),
-                                ),
+                                to_image_coords(click_pos, image_rect, dimensions),
+                                to_image_coords(hover_pos, image_rect, dimensions),
                             ]),
                             self.rgba.clone(),
                         ));
@@ -104,3 +106,41 @@ impl RectSelection {
         ret
     }
 }
+
+/// Ritorna il rettangolo più grande, centrato in <b>container</b>, con le stesse proporzioni di un'immagine di dimensioni
+/// <b>size</b>.
+fn fit_rect(container: Rect, size: (u32, u32)) -> Rect {
+    let (w, h) = (size.0.max(1) as f32, size.1.max(1) as f32);
+    let scale = (container.width() / w).min(container.height() / h);
+    Rect::from_center_size(container.center(), Vec2::new(w * scale, h * scale))
+}
+
+/// Converte un punto della finestra in coordinate (in pixel) dell'immagine mostrata in <b>image_rect</b>.
+/// I punti esterni all'immagine vengono riportati sul bordo più vicino.
+fn to_image_coords(p: Pos2, image_rect: Rect, size: (u32, u32)) -> Pos2 {
+    let p = image_rect.clamp(p) - image_rect.min;
+    pos2(
+        p.x * size.0 as f32 / image_rect.width(),
+        p.y * size.1 as f32 / image_rect.height(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_rect_test() {
+        let window = Rect::from_min_size(Pos2::ZERO, Vec2::new(100.0, 50.0));
+        //stesse proporzioni della finestra, con densità 2: il risultato coincide con la conversione per pixels_per_point
+        let full = fit_rect(window, (200, 100));
+        assert_eq!(full, window);
+        assert_eq!(to_image_coords(pos2(10.0, 20.0), full, (200, 100)), pos2(20.0, 40.0));
+
+        //desktop virtuale più largo della finestra: l'immagine viene centrata verticalmente
+        let wide = fit_rect(window, (400, 100));
+        assert_eq!(wide, Rect::from_min_max(pos2(0.0, 12.5), pos2(100.0, 37.5)));
+        assert_eq!(to_image_coords(pos2(50.0, 0.0), wide, (400, 100)), pos2(200.0, 0.0));
+        assert_eq!(to_image_coords(pos2(100.0, 50.0), wide, (400, 100)), pos2(400.0, 100.0));
+    }
+}
@@ -2,12 +2,14 @@
 
 use eframe::egui::{self, ScrollArea};
 use serde::{Serialize, Deserialize};
-use crate::itc::SettingsEvent;
+use crate::config::{self, ConfigFile};
+use crate::itc::{CaptureMetadata, SettingsEvent};
 use chrono::Local;
 use std::cell::RefCell;
 use super::file_dialog;
 use std::rc::Rc;
-use crate::image_coding::ImageFormat;
+use crate::image_coding::{self, EncoderOptions, ImageFormat, PngCompression, PngFilter, PngOptimization};
+use image::RgbaImage;
 use std::path::PathBuf;
 use std::sync::mpsc::{channel, Receiver};
 
@@ -74,26 +76,44 @@ struct Memory
     default_dir: DefaultDir,
     default_name: DefaultName,
     copy_on_clipboard: bool, 
+    /// Assente nei file di configurazione salvati dalle versioni precedenti: in tal caso si usano i valori di default.
+    #[serde(default)]
+    encoder_options: EncoderOptions,
+    /// Se true, le catture ripetute con "Repeat last capture" vengono salvate senza passare dall'editor.
+    #[serde(default)]
+    repeat_saves_directly: bool,
+    /// Formato con cui vengono salvate le catture ripetute quando <i>repeat_saves_directly</i> è true.
+    #[serde(default)]
+    repeat_save_format: ImageFormat,
+}
+
+impl ConfigFile for Memory
+{
+    const FILE_NAME: &'static str = "save.json";
+    const LEGACY_FILE_NAME: &'static str = ".config_save.json";
 }
 
 impl SaveSettings
 {
-    const CONFIG_FILE_NAME: &'static str = ".config_save.json";
+    const TIMESTAMP_FMT: &'static str = "%Y-%m-%d_%H%M%S";
 
     ///Controlla se esiste il file contenente l'oggetto serializzato: in caso positivo, lo 
     /// deserializza; altrimenti, crea un oggetto nuovo con valori di default.
+    /// Se il file esiste ma non è valido, scrive un messaggio nello stato di errore globale.
     pub fn new(alert: Rc<RefCell<Option<String>>>) -> Self
     {
-        if let Ok(f) = std::fs::File::open(Self::CONFIG_FILE_NAME)
+        match config::load::<Memory>()
         {
-                if let Ok(mem) = serde_json::from_reader(f)
-                {
-                    return Self{mem, alert}
-                }
+            Ok(Some(mem)) => return Self{mem, alert},
+            Ok(None) => (),
+            Err(e) => { alert.borrow_mut().replace(format!("Save settings: {}", e)); }
         }
         Self {mem: Memory {default_dir: DefaultDir { enabled: false, path: "".to_string() }, 
                 default_name: DefaultName { enabled: false, name: "".to_string(), mode: DefaultNameMode::Timestamp,},
-                copy_on_clipboard: true}, 
+                copy_on_clipboard: true,
+                encoder_options: EncoderOptions::default(),
+                repeat_saves_directly: false,
+                repeat_save_format: ImageFormat::default()},
                 alert
             }
     }
@@ -123,27 +143,14 @@ impl SaveSettings
         std::thread::spawn(move||{
             match (dd_opt, dn_opt) {
                 (Some(dp), Some(dn)) => {
-                    let mut pb = PathBuf::from(dp);
-                    let ext: &str = format.into();
-                    pb.push("temp"); //per fare si che quando si setta il nome del file non si sovrascriva il nome della parent dir
-                    let file = match mode
-                    {
-                        DefaultNameMode::Counter =>
-                        {
-                            Self::get_incremental_filename(&dn,format, &pb)
-                        }
-                        _ => dn
-                    };
-                    pb.set_file_name(file);
-                    pb.set_extension(ext);
-                    let _ = tx.send(Some(pb));
+                    let _ = tx.send(Some(Self::default_file_path(dp, dn, mode, format)));
                     return;
                 }
     
                 (None, Some(dn)) => {
                     let dir_opt = file_dialog::show_directory_dialog(None);
                     if let Some(mut pb) = dir_opt {
-                        let ext: &str = format.into();
+                        let ext = format.extension();
                         let file = match mode
                     {
                         DefaultNameMode::Counter =>
@@ -162,7 +169,7 @@ impl SaveSettings
                 (Some(dp), None) => {
                     let dir_opt = file_dialog::show_save_dialog(format, Some(dp));
                     if let Some(mut pb) = dir_opt {
-                        let ext: &str = format.into();
+                        let ext = format.extension();
                         pb.set_extension(ext);
                         let _ = tx.send(Some(pb));
                         return;
@@ -172,7 +179,7 @@ impl SaveSettings
                 (None, None) => {
                     let dir_opt = file_dialog::show_save_dialog(format, None);
                     if let Some(mut pb) = dir_opt {
-                        let ext: &str = format.into();
+                        let ext = format.extension();
                         pb.set_extension(ext);
                         let _ = tx.send(Some(pb));
                         return;
@@ -185,11 +192,79 @@ impl SaveSettings
         rx
     }
 
-    /// Mostra, all'interno di una ScrollArea orizzontale, una schermata divisa in quattro sezioni:
+    /// Versione non interattiva di <i>compose_output_file_path()</i>, pensata per l'uso da riga di comando.<br>
+    /// Ritorna <i>Some(PathBuf)</i> solo se sono abilitati sia la directory che il nome di default, cioè nell'unico
+    /// caso in cui la gui non mostrerebbe nessun file dialog; altrimenti ritorna <i>None</i>.
+    pub fn compose_default_output_file_path(&self, format: ImageFormat) -> Option<PathBuf>
+    {
+        match (self.get_default_dir(), self.get_default_name())
+        {
+            (Some(dp), Some(dn)) => Some(Self::default_file_path(dp, dn, self.mem.default_name.mode, format)),
+            _ => None
+        }
+    }
+
+    /// Ritorna la funzione con cui una timelapse salva ciascuno scatto, con i relativi metadati, nella cartella di default,
+    /// nel formato <i>format</i> e con i parametri dei codificatori correnti, ritornando il path del file scritto.<br>
+    /// Il nome dei file è il nome di default (oppure <i>fallback_name</i>, se disabilitato), seguito dal timestamp se
+    /// richiesto dalle impostazioni e altrimenti da un numero incrementale, così che gli scatti non si sovrascrivano a
+    /// vicenda.<br>
+    /// Ritorna <i>None</i> se il salvataggio in una cartella di default è disabilitato: durante una timelapse non è
+    /// possibile mostrare un file dialog per ogni scatto.
+    pub fn timelapse_saver(&self, format: ImageFormat, fallback_name: &str)
+        -> Option<impl FnMut(RgbaImage, CaptureMetadata) -> Result<PathBuf, String> + Send + 'static>
+    {
+        let dir = self.get_default_dir()?;
+        let (name, mode) = match (self.mem.default_name.enabled, self.mem.default_name.mode)
+        {
+            (true, DefaultNameMode::Timestamp) => (self.mem.default_name.name.clone(), DefaultNameMode::Timestamp),
+            (true, _) => (self.mem.default_name.name.clone(), DefaultNameMode::Counter),
+            (false, _) => (fallback_name.to_string(), DefaultNameMode::Counter)
+        };
+        let options = self.get_encoder_options();
+        Some(move |img: RgbaImage, metadata: CaptureMetadata|
+        {
+            let name = match mode
+            {
+                DefaultNameMode::Timestamp => format!("{}{}", name, Local::now().format(Self::TIMESTAMP_FMT)),
+                _ => name.clone()
+            };
+            let path = Self::default_file_path(dir.clone(), name, mode, format);
+            match image_coding::start_thread_save_image(path.clone(), img, format, options, Some(metadata)).recv()
+            {
+                Ok(Ok(_)) => Ok(path),
+                Ok(Err(e)) => Err(format!("unable to save {}: {}", path.display(), e)),
+                Err(_) => Err(format!("unable to save {}", path.display()))
+            }
+        })
+    }
+
+    /// Compone il path di salvataggio a partire dalla directory e dal nome di default, aggiungendo il numero
+    /// incrementale se richiesto da <i>mode</i> e l'estensione corrispondente a <i>format</i>.
+    fn default_file_path(dir: String, name: String, mode: DefaultNameMode, format: ImageFormat) -> PathBuf
+    {
+        let mut pb = PathBuf::from(dir);
+        let ext = format.extension();
+        pb.push("temp"); //per fare si che quando si setta il nome del file non si sovrascriva il nome della parent dir
+        let file = match mode
+        {
+            DefaultNameMode::Counter =>
+            {
+                Self::get_incremental_filename(&name, format, &pb)
+            }
+            _ => name
+        };
+        pb.set_file_name(file);
+        pb.set_extension(ext);
+        pb
+    }
+
+    /// Mostra, all'interno di una ScrollArea orizzontale, una schermata divisa in cinque sezioni:
     /// 1. form relativo alla directory di default;
     /// 2. form relativo al nome di default;
-    /// 3. form relativo alla copia negli appunti;
-    /// 4. bottoni per salvataggio o annullamento.
+    /// 3. form relativo alla copia negli appunti e al salvataggio diretto delle catture ripetute;
+    /// 4. form relativo ai parametri dei codificatori dei singoli formati;
+    /// 5. bottoni per salvataggio o annullamento.
     /// 
     /// <b>Sezione 1:</b> contiene un input text per specificare il path a mano e un bottone per aprire un directory dialog.<br>
     /// 
@@ -198,9 +273,13 @@ impl SaveSettings
     /// - combobox per aggiungere opzionalmente un numero incrementale o il timestamp.
     /// 
     /// <b>Sezione 3:</b> checkbox per attivare/disattivare la copia automatica dell'immagine negli appunti.<br>
-    /// <i>NOTA: la copia viene fatta prima della modifica dell'immagine, negli appunti ci sarà solo l'immagine non modificata</i>
+    /// <i>NOTA: la copia viene fatta prima della modifica dell'immagine, negli appunti ci sarà solo l'immagine non modificata</i><br>
+    /// Checkbox per salvare direttamente (in formato PNG) il risultato di "Repeat last capture", senza aprire l'editor.
+    /// 
+    /// <b>Sezione 4:</b> qualità JPEG e colore di sfondo su cui appiattire la trasparenza, livello di compressione
+    /// e filtro PNG, ottimizzazione delle dimensioni dei PNG, velocità di codifica GIF (vedi <i>self::encoder_options_ui()</i>).
     /// 
-    /// <b>Sezione 4:</b> 
+    /// <b>Sezione 5:</b> 
     /// - bottone "Save": se premuto, il metodo ritorna <i>SettingsEvent::Saved</i>;
     /// - bottone "Abort": se premuto, il metodo ritorna <i>SettingsEvent::Aborted</i>;
     /// - etichetta che mostra un punto interrogativo: mostra un tooltip con istruzioni utili per questa schermata.
@@ -257,6 +336,25 @@ impl SaveSettings
             ui.add_space(10.0);
             ui.checkbox(&mut self.mem.copy_on_clipboard, "Copy on clipboard")
             .on_hover_text("When you acquire a screenshot, the acquired image is automatically copied in you clipboard.\nNote that modifications to the image performed after the acquire phase are not included.");
+            ui.horizontal(|ui|
+            {
+                ui.checkbox(&mut self.mem.repeat_saves_directly, "Save repeated captures without editing")
+                .on_hover_text("Images captured with \"Repeat last capture\" are saved in the chosen format without opening the editor.");
+                ui.add_enabled_ui(self.mem.repeat_saves_directly, |ui|
+                {
+                    egui::ComboBox::from_id_source("repeat_save_format")
+                    .selected_text(<ImageFormat as Into<&'static str>>::into(self.mem.repeat_save_format))
+                    .show_ui(ui, |ui|{
+                        for f in ImageFormat::available_formats()
+                        {
+                            ui.selectable_value(&mut self.mem.repeat_save_format, f, <ImageFormat as Into<&'static str>>::into(f));
+                        }
+                    });
+                });
+            });
+            ui.separator();
+
+            self.encoder_options_ui(ui);
             ui.separator();
 
 
@@ -287,18 +385,72 @@ impl SaveSettings
 
     }
 
-    pub fn start_thread_serialize(&self)
+    /// Form per la modifica dei parametri dei codificatori, organizzato in una griglia con una riga per parametro.
+    fn encoder_options_ui(&mut self, ui: &mut egui::Ui)
     {
-        let mem = self.mem.clone();
-        std::thread::spawn(move||
+        let opt = &mut self.mem.encoder_options;
+        ui.label("Encoder options");
+        egui::Grid::new("encoder_options_grid")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui|
         {
-            if let Ok(f) = std::fs::File::create(Self::CONFIG_FILE_NAME)
-            {
-                let _ = serde_json::to_writer(f, &mem);
-            } 
+            ui.label("JPEG quality:");
+            ui.add(egui::Slider::new(&mut opt.jpeg_quality, 1..=100));
+            ui.end_row();
+
+            ui.label("JPEG background:");
+            ui.color_edit_button_srgb(&mut opt.jpeg_background)
+            .on_hover_text("JPEG does not support transparency: transparent pixels are blended with this colour.");
+            ui.end_row();
+
+            ui.label("PNG compression:");
+            egui::ComboBox::from_id_source("png_compression")
+            .selected_text(<PngCompression as Into<&'static str>>::into(opt.png_compression))
+            .show_ui(ui, |ui|{
+                for c in PngCompression::available_compressions()
+                {
+                    ui.selectable_value(&mut opt.png_compression, c, <PngCompression as Into<&'static str>>::into(c));
+                }
+            });
+            ui.end_row();
+
+            ui.label("PNG filter:");
+            egui::ComboBox::from_id_source("png_filter")
+            .selected_text(<PngFilter as Into<&'static str>>::into(opt.png_filter))
+            .show_ui(ui, |ui|{
+                for f in PngFilter::available_filters()
+                {
+                    ui.selectable_value(&mut opt.png_filter, f, <PngFilter as Into<&'static str>>::into(f));
+                }
+            });
+            ui.end_row();
+
+            ui.label("PNG optimisation:");
+            egui::ComboBox::from_id_source("png_optimization")
+            .selected_text(<PngOptimization as Into<&'static str>>::into(opt.png_optimization))
+            .show_ui(ui, |ui|{
+                for o in PngOptimization::available_optimizations()
+                {
+                    ui.selectable_value(&mut opt.png_optimization, o, <PngOptimization as Into<&'static str>>::into(o));
+                }
+            })
+            .response
+            .on_hover_text("Lossless tries every combination of compression level and filter. Palette reduces the image to at most 256 colours with dithering: smaller files, lower quality.");
+            ui.end_row();
+
+            ui.label("GIF speed:");
+            ui.add(egui::Slider::new(&mut opt.gif_speed, 1..=30))
+            .on_hover_text("Lower values produce better colours, but saving takes longer.");
+            ui.end_row();
         });
     }
 
+    pub fn start_thread_serialize(&self)
+    {
+        config::start_thread_save(self.mem.clone());
+    }
+
 
     /// Ritorna:
     /// - <i>None</i>, se il salvataggio in una cartella di default è disabilitato (<i>self.mem.default_dir.enabled == false</i>), 
@@ -336,8 +488,7 @@ impl SaveSettings
 
             DefaultNameMode::Timestamp =>
             {
-                const TIMESTAMP_FMT: &str = "%Y-%m-%d_%H%M%S";
-                let str = format!("{}{}", self.mem.default_name.name, Local::now().format(TIMESTAMP_FMT));
+                let str = format!("{}{}", self.mem.default_name.name, Local::now().format(Self::TIMESTAMP_FMT));
                 Some(str)
             }
         }
@@ -358,7 +509,7 @@ impl SaveSettings
         let mut counter: usize = 0;
         let mut ret = String::from(filename);
         ret.push_str(&counter.to_string());
-        let ext: &str = format.into();
+        let ext = format.extension();
         loop 
         {
             pb.set_file_name(ret.clone());
@@ -377,6 +528,21 @@ impl SaveSettings
     {
         self.mem.copy_on_clipboard
     }
+
+    pub fn get_encoder_options(&self) -> EncoderOptions
+    {
+        self.mem.encoder_options
+    }
+
+    pub fn get_repeat_saves_directly(&self) -> bool
+    {
+        self.mem.repeat_saves_directly
+    }
+
+    pub fn get_repeat_save_format(&self) -> ImageFormat
+    {
+        self.mem.repeat_save_format
+    }
 }
 
 
@@ -415,6 +581,17 @@ mod tests
         std::fs::remove_dir("./dd").unwrap();
     }
 
+    #[test]
+    fn legacy_config_test()
+    {
+        let legacy = r#"{"default_dir":{"enabled":false,"path":""},"default_name":{"enabled":true,"mode":"Counter","name":"dn"},"copy_on_clipboard":false}"#;
+        let mem: Memory = serde_json::from_str(legacy).unwrap();
+        assert!(!mem.copy_on_clipboard);
+        assert_eq!(mem.encoder_options, EncoderOptions::default());
+        assert!(!mem.repeat_saves_directly);
+        assert_eq!(mem.repeat_save_format, ImageFormat::Png);
+    }
+
     #[test]
     fn get_default_name_test()
     {
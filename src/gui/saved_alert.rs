@@ -0,0 +1,83 @@
+use crate::image_coding::{SavedFile, TextExport};
+use eframe::egui::{Context, Pos2, Window};
+
+/// Evento generato dalla finestra di conferma del salvataggio.
+pub enum SavedAlertEvent {
+    /// E' stato premuto uno dei pulsanti "Copy as": il file salvato va copiato nella clipboard come testo.
+    CopyAsText(TextExport),
+    Closed,
+    Nil,
+}
+
+/// Finestra mostrata al termine di un salvataggio: oltre al path del file (ed eventualmente allo spazio risparmiato
+/// dall'ottimizzazione PNG), offre la possibilità di copiarlo nella clipboard come data URI, link Markdown o tag HTML.
+pub struct SavedAlert {
+    saved: SavedFile,
+    /// Ultimo formato testuale copiato con successo nella clipboard.
+    copied: Option<TextExport>,
+}
+
+impl SavedAlert {
+    pub fn new(saved: SavedFile) -> Self {
+        Self {
+            saved,
+            copied: None,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.saved.path
+    }
+
+    /// Segnala che la copia nel formato <i>export</i> è andata a buon fine.
+    pub fn set_copied(&mut self, export: TextExport) {
+        self.copied = Some(export);
+    }
+
+    fn message(&self) -> String {
+        let mut msg = format!("Image saved: \n\n{}", self.saved.path);
+        if let Some(bytes_saved) = self.saved.bytes_saved {
+            let original = self.saved.size + bytes_saved;
+            msg.push_str(&format!(
+                "\n\nPNG optimisation saved {:.1} KiB ({}%)",
+                bytes_saved as f64 / 1024.0,
+                (bytes_saved * 100).checked_div(original).unwrap_or(0)
+            ));
+        }
+        msg
+    }
+
+    /// Mostra la finestra e ritorna l'eventuale pulsante premuto.
+    pub fn update(&mut self, ctx: &Context) -> SavedAlertEvent {
+        let mut ret = SavedAlertEvent::Nil;
+        Window::new("Alert")
+            .default_pos(Pos2::new(100.0, 100.0))
+            .show(ctx, |ui| {
+                ui.heading(self.message());
+
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    ui.label("Copy as:");
+                    for export in TextExport::available_exports() {
+                        if ui.button(<TextExport as Into<&str>>::into(export)).clicked() {
+                            ret = SavedAlertEvent::CopyAsText(export);
+                        }
+                    }
+                });
+                if let Some(export) = self.copied {
+                    ui.label(format!(
+                        "✔ Copied to the clipboard as {}",
+                        <TextExport as Into<&str>>::into(export)
+                    ));
+                }
+
+                ui.add_space(10.0);
+
+                if ui.button("Close").clicked() {
+                    ret = SavedAlertEvent::Closed;
+                }
+            });
+        ret
+    }
+}
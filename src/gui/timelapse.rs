@@ -0,0 +1,108 @@
+use crate::itc::TimelapseSettings;
+use crate::screens_manager::{ShotGate, TimelapseEvent};
+use eframe::egui::{self, CentralPanel, Context};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Stato della parte di gui mostrata durante una timelapse (o durante la sorveglianza dei cambiamenti dello schermo,
+/// vedi <i>TimelapseSettings::watch</i>).<br>
+/// Gli scatti vengono catturati e salvati da un thread worker (vedi <i>ScreensManager::start_thread_timelapse()</i>),
+/// che notifica ogni scatto con un <i>TimelapseEvent</i> e si ferma al raggiungimento del numero massimo di scatti
+/// oppure quando viene impostato il flag <i>stop</i>, condiviso con questa struct.<br>
+/// Durante ogni scatto la finestra viene nascosta, su richiesta del thread worker (vedi <i>ShotGate</i>).
+pub struct Timelapse {
+    receiver: Receiver<TimelapseEvent>,
+    stop: Arc<AtomicBool>,
+    gate: Arc<ShotGate>,
+    settings: TimelapseSettings,
+    saved: u32,
+    skipped: u32,
+    last_saved: Option<PathBuf>,
+}
+
+impl Timelapse {
+    pub fn new(
+        receiver: Receiver<TimelapseEvent>,
+        stop: Arc<AtomicBool>,
+        gate: Arc<ShotGate>,
+        settings: TimelapseSettings,
+    ) -> Self {
+        Self {
+            receiver,
+            stop,
+            gate,
+            settings,
+            saved: 0,
+            skipped: 0,
+            last_saved: None,
+        }
+    }
+
+    /// Visibilità che la finestra deve assumere, se il thread worker ne ha chiesto il cambiamento (vedi
+    /// <i>ShotGate::window_visibility()</i>).
+    pub fn window_visibility(&self) -> Option<bool> {
+        self.gate.window_visibility()
+    }
+
+    /// Chiede al thread worker di terminare la timelapse. La fine sarà comunque notificata tramite <i>self::update()</i>.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Mostra il numero di scatti salvati e scartati, il path dell'ultimo file scritto e un bottone "Stop".<br>
+    /// Legge con <i>try_recv()</i> tutti gli eventi presenti sul canale con il thread worker:
+    /// - se la timelapse è ancora in corso, ritorna <i>None</i> e chiede un nuovo repaint per aggiornare i contatori;
+    /// - se è terminata, ritorna il numero di scatti salvati;
+    /// - se è stata interrotta da un errore (o il canale è stato chiuso inaspettatamente), ritorna il messaggio di errore.
+    pub fn update(&mut self, ctx: &Context) -> Option<Result<u32, String>> {
+        let mut ret = None;
+        while ret.is_none() {
+            match self.receiver.try_recv() {
+                Ok(TimelapseEvent::Saved(path)) => {
+                    self.saved += 1;
+                    self.last_saved = Some(path);
+                }
+                Ok(TimelapseEvent::Skipped) => self.skipped += 1,
+                Ok(TimelapseEvent::Finished) => ret = Some(Ok(self.saved)),
+                Ok(TimelapseEvent::Failed(e)) => ret = Some(Err(e)),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    ret = Some(Err("timelapse interrupted".to_string()))
+                }
+            }
+        }
+
+        CentralPanel::default().show(ctx, |ui| {
+            ui.style_mut().spacing.button_padding = egui::vec2(12.0, 3.0);
+            if self.stop.load(Ordering::Relaxed) {
+                ui.heading("Stopping...");
+            } else if self.settings.watch.is_some() {
+                ui.heading(format!("◉ Watching for changes, every {:.0} s", self.settings.interval));
+            } else {
+                ui.heading(format!("◷ Timelapse, every {:.0} s", self.settings.interval));
+            }
+            ui.label(format!("Shots: {} / {}", self.saved, self.settings.max_shots));
+            if self.settings.watch.is_some() {
+                ui.label(format!("Unchanged: {}", self.skipped));
+            } else if self.settings.skip_identical {
+                ui.label(format!("Skipped (identical): {}", self.skipped));
+            }
+            if let Some(path) = &self.last_saved {
+                ui.small(path.display().to_string());
+            }
+            ui.add_space(10.0);
+            ui.style_mut().visuals.widgets.hovered.weak_bg_fill = egui::Color32::RED;
+            if ui.button("Stop ⏹").clicked() {
+                self.stop();
+            }
+        });
+
+        if ret.is_none() {
+            ctx.request_repaint_after(Duration::from_millis(250));
+        }
+        ret
+    }
+}
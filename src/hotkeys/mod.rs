@@ -1,72 +1,299 @@
+use crate::config::{self, ConfigFile};
+use crate::region_presets::MAX_REGION_PRESETS;
 use eframe::egui::Context;
 use global_hotkey::hotkey::HotKey;
 use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager};
-use std::cmp::Ordering;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
 use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
 
-///Il numero di varianti della enum HotkeyName. Il modulo Hotkeys è predisposto per scalare ad un maggiore
-///numero di hotkeys.
-pub const N_HOTK: usize = 2;
+#[cfg(target_os = "linux")]
+mod x11;
 
-/// Può esserci una sola combinazione di tasti associata ad ogni variante di questa enum. Infatti, ad ogni variante di HotkeyName è associato un comando che può essere dato in input al programma.
-///
-/// <b>Attenzione:</b> se si dovessero aggiungere varianti a questa enum, è necessario aggiornare la costante <i>N_HOTK</i>.
-#[derive(Clone, Copy)]
+/// Azioni a cui è possibile associare delle combinazioni di tasti: ad ogni azione ne possono essere associate più
+/// di una, alternative tra loro (vedi <i>Binding</i>).<br>
+/// Le varianti sono descritte (id, nome e combinazione di default) in un unico punto, la funzione <i>actions()</i>:
+/// per aggiungere un'azione è sufficiente aggiungere la variante e la sua descrizione, oltre a gestirla in
+/// <i>GlobalGuiState::hotkey_reaction()</i>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum HotkeyName {
     FullscreenScreenshot,
     RectScreenshot,
+    WindowScreenshot,
+    RepeatLastCapture,
+    OpenClipboardImage,
+    ///Avvia una registrazione dello schermo selezionato, con i parametri di default.
+    StartRecording,
+    StopRecording,
+    ///Annulla la selezione dell'area o la registrazione in corso.
+    Cancel,
+    ///Cattura del preset di regione nella posizione indicata (minore di <i>MAX_REGION_PRESETS</i>), che non cambia
+    ///quando gli altri preset vengono eliminati.
+    RegionPreset(usize),
 }
 
-impl PartialEq for HotkeyName {
-    fn eq(&self, other: &Self) -> bool {
-        <HotkeyName as Into<usize>>::into(*self) == <HotkeyName as Into<usize>>::into(*other)
+impl HotkeyName {
+    ///Descrizione dell'azione, presa da <i>actions()</i>.
+    ///
+    /// <h3>Panics:</h3>
+    /// Nel caso l'azione non sia descritta in <i>actions()</i> (ad esempio, un preset di regione con indice non valido).
+    pub fn action(self) -> &'static HotkeyAction {
+        actions()
+            .iter()
+            .find(|a| a.name == self)
+            .expect("HotkeyName without a HotkeyAction")
+    }
+
+    ///Azione con l'id <i>id</i>, se esiste.
+    pub fn from_id(id: &str) -> Option<Self> {
+        actions().iter().find(|a| a.id == id).map(|a| a.name)
+    }
+
+    ///Azione corrispondente alla riga <i>i</i> del vecchio file di configurazione, in cui le combinazioni di tasti erano
+    ///scritte una per riga, nell'ordine in cui le varianti erano dichiarate.
+    fn from_legacy_index(i: usize) -> Option<Self> {
+        const LEGACY_ORDER: [HotkeyName; 6] = [
+            HotkeyName::FullscreenScreenshot,
+            HotkeyName::RectScreenshot,
+            HotkeyName::StopRecording,
+            HotkeyName::OpenClipboardImage,
+            HotkeyName::WindowScreenshot,
+            HotkeyName::RepeatLastCapture,
+        ];
+        match LEGACY_ORDER.get(i) {
+            Some(name) => Some(*name),
+            None if i - LEGACY_ORDER.len() < MAX_REGION_PRESETS => {
+                Some(Self::RegionPreset(i - LEGACY_ORDER.len()))
+            }
+            None => None,
+        }
     }
 }
 
-impl Into<usize> for HotkeyName {
-    /// Converte ad intero assegnando un indice incrementale ad ogni variante della enum HotkeyName.
-    fn into(self) -> usize {
-        match self {
-            Self::FullscreenScreenshot => 0,
-            Self::RectScreenshot => 1,
+/// Descrizione di un'azione a cui è possibile associare una hotkey.
+#[derive(Debug)]
+pub struct HotkeyAction {
+    pub name: HotkeyName,
+    ///Identificativo stabile, con cui l'azione viene memorizzata nel file di configurazione.
+    pub id: String,
+    ///Nome mostrato all'utente.
+    pub label: String,
+    ///Combinazione di tasti registrata al primo avvio, quando non esiste ancora un file di configurazione.
+    pub default_binding: Option<&'static str>,
+}
+
+impl HotkeyAction {
+    fn new(
+        name: HotkeyName,
+        id: impl Into<String>,
+        label: impl Into<String>,
+        default_binding: Option<&'static str>,
+    ) -> Self {
+        Self {
+            name,
+            id: id.into(),
+            label: label.into(),
+            default_binding,
+        }
+    }
+}
+
+/// Elenco di tutte le azioni a cui è possibile associare una hotkey, nell'ordine in cui sono mostrate nelle
+/// impostazioni. L'elenco viene costruito una sola volta.
+pub fn actions() -> &'static [HotkeyAction] {
+    static ACTIONS: OnceLock<Vec<HotkeyAction>> = OnceLock::new();
+    ACTIONS.get_or_init(|| {
+        use HotkeyName::*;
+        let mut actions = vec![
+            HotkeyAction::new(FullscreenScreenshot, "fullscreen_screenshot", "Fullscreen screenshot", Some("Alt+Shift+F")),
+            HotkeyAction::new(RectScreenshot, "rect_screenshot", "Rect screenshot", Some("Alt+Shift+R")),
+            HotkeyAction::new(WindowScreenshot, "window_screenshot", "Window screenshot", Some("Alt+Shift+W")),
+            HotkeyAction::new(RepeatLastCapture, "repeat_last_capture", "Repeat last capture", Some("Alt+Shift+L")),
+            HotkeyAction::new(OpenClipboardImage, "open_clipboard_image", "Open clipboard image", None),
+            HotkeyAction::new(StartRecording, "start_recording", "Start recording", None),
+            HotkeyAction::new(StopRecording, "stop_recording", "Stop recording", Some("Alt+Shift+S")),
+            HotkeyAction::new(Cancel, "cancel", "Cancel", None),
+        ];
+        actions.extend((0..MAX_REGION_PRESETS).map(|i| {
+            HotkeyAction::new(
+                RegionPreset(i),
+                format!("region_preset_{}", i + 1),
+                format!("Region preset {}", i + 1),
+                None,
+            )
+        }));
+        actions
+    })
+}
+
+/// Numero massimo di passi di una sequenza di tasti.
+pub const MAX_SEQUENCE_STEPS: usize = 2;
+
+/// Tempo entro cui deve essere premuto il passo successivo di una sequenza, dopo il quale la sequenza viene annullata.
+const SEQUENCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Combinazione di tasti associata ad un'azione: una singola hotkey oppure una sequenza di hotkeys da premere una dopo
+/// l'altra (al massimo <i>MAX_SEQUENCE_STEPS</i>), scritta separando i passi con una virgola: ad esempio
+/// "Ctrl+Shift+S, R".<br>
+/// Solo il primo passo viene registrato in modo permanente: i successivi sono registrati dopo la sua pressione e
+/// fino allo scadere di <i>SEQUENCE_TIMEOUT</i> (vedi <i>RegisteredHotkeys::update_sequence_hotkeys()</i>), per cui
+/// possono essere anche tasti senza modificatori.
+#[derive(Debug, Clone, PartialEq)]
+struct Binding {
+    steps: Vec<HotKey>,
+    ///Combinazione in forma di stringa, con i passi separati da ", ".
+    text: String,
+}
+
+impl FromStr for Binding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let texts: Vec<&str> = s.split(',').map(str::trim).collect();
+        if texts.len() > MAX_SEQUENCE_STEPS {
+            return Err(());
         }
+        let steps = texts
+            .iter()
+            .map(|t| HotKey::from_str(t))
+            .collect::<Result<Vec<HotKey>, _>>()
+            .map_err(|_| ())?;
+        Ok(Self {
+            steps,
+            text: texts.join(", "),
+        })
+    }
+}
+
+impl Binding {
+    fn first(&self) -> HotKey {
+        self.steps[0]
+    }
+
+    ///Due combinazioni sono in conflitto se sono uguali oppure se una è l'inizio dell'altra: alla pressione dei tasti
+    ///in comune non sarebbe possibile decidere quale azione eseguire. Due sequenze con lo stesso primo passo e
+    ///secondi passi diversi non sono in conflitto.
+    fn conflicts_with(&self, other: &Binding) -> bool {
+        self.steps.iter().zip(&other.steps).all(|(a, b)| a == b)
     }
 }
 
-impl Into<String> for HotkeyName {
-    fn into(self) -> String {
-        match self {
-            Self::FullscreenScreenshot => String::from("Fullscreen screenshot"),
-            Self::RectScreenshot => String::from("Rect screenshot"),
+/// Contenuto del file di configurazione delle hotkeys (vedi il modulo <i>config</i>).<br>
+/// Le combinazioni di tasti sono indicate con l'id stabile dell'azione (<i>HotkeyAction::id</i>), per cui aggiungere o
+/// riordinare le azioni non modifica le associazioni già salvate.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct HotkeysConfig {
+    bindings: BTreeMap<String, Vec<String>>,
+}
+
+impl ConfigFile for HotkeysConfig {
+    const FILE_NAME: &'static str = "hotkeys.json";
+    const LEGACY_FILE_NAME: &'static str = ".config_hotkeys.json";
+    const VERSION: u32 = 2;
+
+    ///Fino alla versione 1 ad ogni azione era associata una sola combinazione, scritta come stringa.
+    fn migrate(version: u32, mut doc: serde_json::Value) -> Result<Self, String> {
+        if version < 2 {
+            if let Some(bindings) = doc["bindings"].as_object_mut() {
+                for binding in bindings.values_mut() {
+                    *binding = serde_json::Value::Array(vec![binding.take()]);
+                }
+            }
         }
+        serde_json::from_value(doc).map_err(|e| e.to_string())
     }
 }
 
-impl From<usize> for HotkeyName {
-    /// A partire da un intero, lo converte nella variante che compare in quella posizione nella definizione della enum.
-    fn from(us: usize) -> Self {
-        match us {
-            0 => Self::FullscreenScreenshot,
-            1 => Self::RectScreenshot,
-            _ => unreachable!("Invalid value in HotkeyName::from::<usize>()"),
+impl HotkeysConfig {
+    fn new(bindings: Vec<(HotkeyName, String)>) -> Self {
+        let mut ret = Self {
+            bindings: BTreeMap::new(),
+        };
+        for (name, binding) in bindings {
+            ret.bindings
+                .entry(name.action().id.clone())
+                .or_default()
+                .push(binding);
         }
+        ret
     }
 }
 
+/// Associazioni valide lette da un file di configurazione, e descrizione delle voci scartate.
+type ParsedBindings = (Vec<(HotkeyName, String)>, Vec<String>);
+
+/// Controlla le associazioni lette dal file di configurazione (vedi <i>HotkeysConfig</i>).
+fn parse_config(config: HotkeysConfig) -> ParsedBindings {
+    validate_bindings(config.bindings.into_iter().flat_map(|(id, bindings)| {
+        bindings
+            .into_iter()
+            .map(move |binding| (HotkeyName::from_id(&id), format!("\"{}\"", id), binding))
+    }))
+}
+
+/// Legge il file di configurazione delle prime versioni, con una combinazione di tasti per riga.
+/// Le righe nella forma "id=combinazione" indicano l'azione con il suo id; nelle altre, l'azione è indicata dalla
+/// posizione della riga (vedi <i>HotkeyName::from_legacy_index()</i>). Le righe vuote indicano azioni senza hotkey.
+fn parse_legacy_config(text: &str) -> ParsedBindings {
+    validate_bindings(text.lines().enumerate().map(|(i, line)| {
+        match line.split_once('=') {
+            Some((id, binding)) => (
+                HotkeyName::from_id(id.trim()),
+                format!("\"{}\"", id.trim()),
+                binding.to_string(),
+            ),
+            None => (
+                HotkeyName::from_legacy_index(i),
+                format!("line {}", i + 1),
+                line.to_string(),
+            ),
+        }
+    }))
+}
+
+/// Controlla le voci lette da un file di configurazione, nella forma (azione, descrizione della voce, combinazione).
+/// Una voce viene scartata se l'azione non esiste, se la combinazione non è valida oppure se è in conflitto con una già
+/// letta (vedi <i>Binding::conflicts_with()</i>); le voci con combinazione vuota vengono ignorate.
+fn validate_bindings(
+    entries: impl Iterator<Item = (Option<HotkeyName>, String, String)>,
+) -> ParsedBindings {
+    let (mut bindings, mut errors): ParsedBindings = (vec![], vec![]);
+    let mut used: Vec<Binding> = vec![];
+    for (name, key, binding) in entries {
+        let binding = binding.trim().to_string();
+        if binding.is_empty() {
+            continue;
+        }
+        match (name, Binding::from_str(&binding)) {
+            (None, _) => errors.push(format!("unknown action {}", key)),
+            (Some(_), Err(_)) => errors.push(format!("invalid keys \"{}\" for {}", binding, key)),
+            (Some(_), Ok(b)) if used.iter().any(|u| u.conflicts_with(&b)) => {
+                errors.push(format!("keys \"{}\" for {} are already used", binding, key))
+            }
+            (Some(name), Ok(b)) => {
+                bindings.push((name, b.text.clone()));
+                used.push(b);
+            }
+        }
+    }
+    (bindings, errors)
+}
+
 /// Struttura dati che si occupa di gestire le hotkeys registrate al livello dell'intera applicazione.<br>
-/// Memorizza al suo interno:
+/// Memorizza al suo interno, per ogni azione descritta in <i>actions()</i>, l'elenco delle combinazioni di tasti
+/// associate (vedi <i>Binding</i>):
 /// - copia di backup: campo privato, modificabile solo con la chiamata al metodo <i>update_changes()</i>;
-/// - <i>vec</i>: copia "di brutta" del precedente campo. Usata per salvare le modifiche temporanee prima del loro effettivo salvataggio.<br>
+/// - <i>draft</i>: copia "di brutta" del precedente campo. Usata per salvare le modifiche temporanee prima del loro effettivo salvataggio.<br>
 ///
 /// Questa ridondanza ha l'obiettivo di mantenere stabili le impostazioni originali fino a quando le modifiche non
-/// vengono confermate. Infatti, solo <i>vec</i> viene modificato tramite le chiamate a <i>request_register()</i> e
+/// vengono confermate. Infatti, solo <i>draft</i> viene modificato tramite le chiamate a <i>request_register()</i> e
 /// <i>request_unregister()</i>.<br>
-/// Prima dell'utilizzo di <i>vec</i> per introdurre nuove modifiche deve essere eseguito il metodo <i>prepare_for_updates()</i>,
-/// che copia il contenuto di <i>backup</i> in <i>vec</i>.<br>
+/// Prima dell'utilizzo di <i>draft</i> per introdurre nuove modifiche deve essere eseguito il metodo <i>prepare_for_updates()</i>,
+/// che copia il contenuto di <i>backup</i> in <i>draft</i>.<br>
 ///
 /// Esiste la possibilità di disabilitare l'ascolto delle hotkeys, tramite il campo <i>listen_enabled</i> e il relativo metodo
 /// setter.
@@ -75,132 +302,163 @@ impl From<usize> for HotkeyName {
 /// - mutabilità interna: il campo deve poter essere modificato, permettendo all'intera struttura di essere posseduta da Arc;
 /// - la struttura deve essere thread-safe, quindi il tipo Cell non sarebbe bastato.<br>
 ///
-/// Si è deciso di incapsulare ogni cella dei vettori (<i>backup, vec</i>) in un RwLock e non incapsulare ciascun vettore in un unico
-/// RwLock per permettere maggiore parallelismo nel loro accesso.
+/// Si è deciso di incapsulare ogni valore delle mappe (<i>backup, draft</i>) in un RwLock e non incapsulare ciascuna mappa in un unico
+/// RwLock per permettere maggiore parallelismo nel loro accesso: le chiavi sono tutte le azioni, inserite alla creazione.
 pub struct RegisteredHotkeys {
-    ///Memorizzazione stabile delle hotkey registrate. Questo Vec viene modificato solo quando una modifica viene salvata.
-    ///Si fa riferimento al contenuto di questo <i>Vec</i> per sapere quali comandi devono essere eseguiti in seguito alla
+    ///Memorizzazione stabile delle hotkey registrate. Questa mappa viene modificata solo quando una modifica viene salvata.
+    ///Si fa riferimento al suo contenuto per sapere quali comandi devono essere eseguiti in seguito alla
     ///pressione delle hotkeys durante il funzionamento normale del programma.
-    backup: Vec<RwLock<Option<(HotKey, String)>>>,
-    ///Copia di "brutta" del vettore di Hotkeys, modificato direttamente durante il settaggio delle impostazioni.
-    vec: Vec<RwLock<Option<String>>>,
+    backup: HashMap<HotkeyName, RwLock<Vec<Binding>>>,
+    ///Copia di "brutta" delle hotkeys, modificata direttamente durante il settaggio delle impostazioni.
+    draft: HashMap<HotkeyName, RwLock<Vec<String>>>,
     ///Mette a disposizione i metodi per attivare/disattivare l'effettivo ascolto delle hotkeys.
     ghm: GlobalHotKeyManager,
     ///Per disattivare temporaneamente le Hotkeys senza dover richiamare <i>unregister()</i>.
     listen_enabled: RwLock<bool>,
+    ///Primo passo della sequenza in corso, impostato dal thread di ascolto (vedi <i>start_thread_listen_hotkeys()</i>)
+    ///quando viene premuto e azzerato quando la sequenza termina o scade.
+    pending_sequence: RwLock<Option<HotKey>>,
+    ///Hotkeys registrate temporaneamente per i passi successivi della sequenza in corso.
+    sequence_hotkeys: RwLock<Vec<HotKey>>,
 }
 
 impl RegisteredHotkeys {
-    const CONFIG_FILE_NAME: &'static str = ".config_hotkeys";
+    ///File di configurazione delle prime versioni, in formato testo, letto solo se non esiste ancora quello in json.
+    const LEGACY_TEXT_FILE_NAME: &'static str = ".config_hotkeys";
 
-    /// Controlla se esiste il file in cui sono state salvate permanentemente le impostazioni: se esiste,
-    /// lo legge e usa le informazioni per riempire la nuova struct.
-    /// Altrimenti, assegna alla nuova struct valori di default:
-    /// Crea i due <i>Vec</i> di <i>RwLock</i> inizialmente vuoti.
+    /// Legge le impostazioni salvate permanentemente nel file di configurazione; se non esiste, le migra dal file
+    /// in formato testo delle prime versioni (riscrivendole subito nel nuovo formato). Se non esiste nessuno dei due
+    /// (primo avvio), registra le combinazioni di default delle azioni che ne hanno una.
     ///Imposta <i>listen_enabled</i> a true di default.
     ///
-    ///Ritorna la struttura già incapsulata in un <i>Arc</i>.
-    pub fn new() -> Arc<Self> {
-        let mut vec = vec![];
-        let mut backup = vec![];
-        for _ in 0..N_HOTK {
-            vec.push(RwLock::new(None));
-            backup.push(RwLock::new(None));
-        }
+    ///Ritorna la struttura già incapsulata in un <i>Arc</i>, insieme all'esito della lettura: le voci non valide del
+    ///file di configurazione e le hotkeys che non è stato possibile registrare vengono ignorate e descritte
+    ///nel messaggio di errore.
+    pub fn new() -> (Arc<Self>, Result<(), String>) {
         let ret = Arc::new(Self {
-            vec,
-            backup,
+            draft: actions().iter().map(|a| (a.name, RwLock::new(vec![]))).collect(),
+            backup: actions().iter().map(|a| (a.name, RwLock::new(vec![]))).collect(),
             ghm: GlobalHotKeyManager::new().unwrap(),
             listen_enabled: RwLock::new(true),
+            pending_sequence: RwLock::new(None),
+            sequence_hotkeys: RwLock::new(vec![]),
         });
 
-        if let Ok(f) = File::open(Self::CONFIG_FILE_NAME) {
-            ret.deserialize(f);
+        let (bindings, mut errors) = match config::load::<HotkeysConfig>() {
+            Ok(Some(config)) => parse_config(config),
+            Err(e) => (vec![], vec![e]),
+            Ok(None) => match config::read_legacy(Self::LEGACY_TEXT_FILE_NAME) {
+                Some(text) => {
+                    let parsed = parse_legacy_config(&text);
+                    match config::save(&HotkeysConfig::new(parsed.0.clone())) {
+                        Ok(()) => config::remove_legacy(Self::LEGACY_TEXT_FILE_NAME),
+                        Err(e) => eprintln!("Error: {}", e),
+                    }
+                    parsed
+                }
+                None => (
+                    actions()
+                        .iter()
+                        .filter_map(|a| a.default_binding.map(|b| (a.name, b.to_string())))
+                        .collect(),
+                    vec![],
+                ),
+            },
+        };
+        for (name, binding) in bindings {
+            ret.draft[&name].write().unwrap().push(binding);
+        }
+        if let Err(e) = ret.update_changes() {
+            errors.push(e);
         }
-        ret
-    }
 
-    pub fn deserialize(self: &Arc<Self>, f: File) {
-        let mut buf = BufReader::new(f);
-        let mut line = String::new();
-        let mut i = 0;
-        while let Ok(n) = buf.read_line(&mut line) {
-            if n == 0 {
-                break;
-            }
-            let _ = line.pop();
-            if n > 1 {
-                self.vec
-                    .get(i)
-                    .unwrap()
-                    .write()
-                    .unwrap()
-                    .replace(line.clone());
-            }
-            i += 1;
-            line = String::new();
+        if errors.is_empty() {
+            (ret, Ok(()))
+        } else {
+            let e = format!("Hotkeys configuration:\n{}", errors.join("\n"));
+            (ret, Err(e))
         }
-        let _ = self.update_changes();
     }
 
     pub fn start_thread_serialize(self: &Arc<Self>) {
-        let arc_clone = self.clone();
-        std::thread::spawn(move || {
-            if let Ok(mut f) = File::create(Self::CONFIG_FILE_NAME) {
-                for rl in arc_clone.backup.iter() {
-                    let g = rl.read().unwrap().clone();
-                    if let Some((_, s)) = g {
-                        let mut s_clone = s.clone();
-                        s_clone.push('\n');
-                        let _ = f.write(s_clone.as_bytes());
-                    } else {
-                        let s = String::from("\n");
-                        let _ = f.write(s.as_bytes());
-                    }
-                }
-            }
-        });
+        let bindings: Vec<(HotkeyName, String)> = self
+            .bindings()
+            .into_iter()
+            .map(|(name, b)| (name, b.text))
+            .collect();
+        config::start_thread_save(HotkeysConfig::new(bindings));
     }
 
-    ///Copia il contenuto di <i>self::vec</i> dentro a <i>self::backup</i>,
-    ///andando a richiamare <i>self:: register()/unregister()<i> in base alle differenze tra le celle
-    ///dei due vettori associate alla stessa <i>HotkeyName</i>.
-    ///In particolare, se per una determinata <i>HotkeyName</i> era già stata memorizzata una combinazione di
-    ///tasti (CT), si ha l'accortezza di richiamare i metodi per la registrazione solo se effettivamente la CT è
-    ///cambiata: questo può essere rilevato convertendo le CT in stringhe ed eseguendo il metodo <i>cmp()</i>.
+    ///Tutte le combinazioni registrate (in <i>self::backup</i>), con l'azione associata, nell'ordine di <i>actions()</i>.
+    fn bindings(&self) -> Vec<(HotkeyName, Binding)> {
+        actions()
+            .iter()
+            .flat_map(|a| {
+                let backup = self.backup[&a.name].read().unwrap();
+                backup.iter().map(|b| (a.name, b.clone())).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    ///Copia il contenuto di <i>self::draft</i> dentro a <i>self::backup</i>, andando a richiamare
+    ///<i>self::register()/unregister()</i> in base alle differenze tra i primi passi delle combinazioni delle due mappe:
+    ///una hotkey condivisa da più sequenze viene registrata una sola volta, e le hotkeys che non sono cambiate non vengono
+    ///registrate di nuovo. Prima della copia viene annullata l'eventuale sequenza in corso.
     ///
-    ///Ad ogni operazione di registrazione, controlla se si sono verificati errori.
+    ///Ad ogni operazione di registrazione, controlla se si sono verificati errori: un errore non interrompe la
+    ///registrazione delle altre hotkeys (le combinazioni che iniziano con una hotkey non registrata vengono
+    ///scartate), ma viene ritornato il primo errore incontrato.
     pub fn update_changes(self: &Arc<Self>) -> Result<(), String> {
         let mut ret = Ok(());
-        for i in 0..N_HOTK {
-            let temp1;
-            let temp2;
-            {
-                temp1 = self.vec.get(i).unwrap().read().unwrap().clone();
-                temp2 = self.backup.get(i).unwrap().read().unwrap().clone();
+        *self.pending_sequence.write().unwrap() = None;
+        self.update_sequence_hotkeys();
+
+        let old = first_steps(&self.bindings());
+        let mut new: Vec<(HotkeyName, Binding)> = actions()
+            .iter()
+            .flat_map(|a| {
+                let draft = self.draft[&a.name].read().unwrap();
+                draft
+                    .iter()
+                    .filter_map(|s| Binding::from_str(s).ok())
+                    .map(|b| (a.name, b))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let new_first = first_steps(&new);
+
+        for (h, name) in old.iter().filter(|(h, _)| !new_first.iter().any(|(n, _)| n == h)) {
+            let res = self.unregister(*h, *name);
+            if ret.is_ok() {
+                ret = res;
             }
-            match (temp1, temp2) {
-                (None, None) => (),
-                (None, Some(..)) => ret = self.unregister(HotkeyName::from(i)),
-                (Some(s), None) => ret = self.register(s.to_string(), HotkeyName::from(i)),
-                (Some(s1), Some((_, s2))) => {
-                    if s1.cmp(&s2) != Ordering::Equal {
-                        ret = self.register(s1.to_string(), HotkeyName::from(i))
-                    }
-                }
+        }
+        for (h, name) in new_first.iter().filter(|(h, _)| !old.iter().any(|(o, _)| o == h)) {
+            let res = self.register(*h, *name);
+            if res.is_err() {
+                new.retain(|(_, b)| b.first() != *h);
             }
+            if ret.is_ok() {
+                ret = res;
+            }
+        }
 
-            ret.as_ref()?; //ritorna nel caso ret.is_err() == true
+        for a in actions() {
+            *self.backup[&a.name].write().unwrap() = new
+                .iter()
+                .filter(|(name, _)| *name == a.name)
+                .map(|(_, b)| b.clone())
+                .collect();
         }
 
         ret
     }
 
     ///Metodo da richiamare <b>sempre</b> prima di iniziare una sessione di modifica.
-    ///Copia il contenuto di <i>self::backup</i> in <i>self::vec</i> in modo che quest'ultimo possa essere
+    ///Copia il contenuto di <i>self::backup</i> in <i>self::draft</i> in modo che quest'ultimo possa essere
     ///modificato a partire da dati consistenti.
     /// Per non bloccare il main thread e rendere l'operazione veloce a prescindere dal numero di hotkeys,
-    /// un thread padre lancia un figlio per ogni entry dei vettori, i thread figli eseguono la copia in
+    /// un thread padre lancia un figlio per ogni azione, i thread figli eseguono la copia in
     /// parallelo.
     ///
     ///<b>Ritorna:</b> un <i>Receiver</i> su cui è possibile mettersi in ascolto per attendere che l'operazione di copia
@@ -211,41 +469,17 @@ impl RegisteredHotkeys {
 
         std::thread::spawn(move || {
             let mut jh = vec![];
-            for i in 0..N_HOTK {
+            for a in actions() {
                 let self_clonex2 = self_clone.clone();
+                let name = a.name;
                 jh.push(std::thread::spawn(move || {
-                    let temp1;
-                    let temp2;
-                    {
-                        temp1 = self_clonex2.vec.get(i).unwrap().read().unwrap().clone();
-                        temp2 = self_clonex2.backup.get(i).unwrap().read().unwrap().clone();
-                    }
-                    match (temp1, temp2) {
-                        (None, None) => (),
-                        (None, Some((_, s))) => {
-                            self_clonex2
-                                .vec
-                                .get(i)
-                                .unwrap()
-                                .write()
-                                .unwrap()
-                                .replace(s.clone());
-                        }
-                        (Some(_), None) => {
-                            self_clonex2.vec.get(i).unwrap().write().unwrap().take();
-                        }
-                        (Some(s1), Some((_, s2))) => {
-                            if s1.cmp(&s2) != Ordering::Equal {
-                                self_clonex2
-                                    .vec
-                                    .get(i)
-                                    .unwrap()
-                                    .write()
-                                    .unwrap()
-                                    .replace(s2.clone());
-                            }
-                        }
-                    }
+                    let temp: Vec<String> = self_clonex2.backup[&name]
+                        .read()
+                        .unwrap()
+                        .iter()
+                        .map(|b| b.text.clone())
+                        .collect();
+                    *self_clonex2.draft[&name].write().unwrap() = temp;
                 }))
             }
             for j in jh {
@@ -256,22 +490,34 @@ impl RegisteredHotkeys {
         rx
     }
 
-    ///Esegue un ciclo su tutte le hotkeys memorizzate nella bozza (<i>self::vec</i>)
-    /// e le confronta con quella passata come parametro.
-    fn check_if_already_registered(self: &Arc<Self>, hotkey: &String) -> bool {
-        for opt in self.vec.iter() {
-            if let Some(s) = &*opt.read().unwrap() {
-                if s == hotkey {
-                    return true;
-                }
-            }
-        }
+    ///Esegue un ciclo su tutte le combinazioni memorizzate nella bozza (<i>self::draft</i>)
+    /// e controlla se qualcuna è in conflitto con quella passata come parametro (vedi <i>Binding::conflicts_with()</i>).
+    fn check_if_already_registered(self: &Arc<Self>, binding: &Binding) -> bool {
+        self.draft.values().any(|list| {
+            list.read()
+                .unwrap()
+                .iter()
+                .filter_map(|s| Binding::from_str(s).ok())
+                .any(|b| b.conflicts_with(binding))
+        })
+    }
 
-        false
+    ///Controlla se uno dei passi di <i>binding</i> è già catturato da un'altra applicazione, che ne impedirebbe
+    ///la registrazione. Le hotkeys già registrate da questa applicazione (i primi passi delle combinazioni in
+    ///<i>self::backup</i>) non vengono controllate.<br>
+    ///Il controllo è possibile solo su X11: sulle altre piattaforme l'eventuale conflitto viene segnalato al salvataggio.
+    fn check_if_grabbed_elsewhere(self: &Arc<Self>, binding: &Binding) -> bool {
+        let own = first_steps(&self.bindings());
+        binding
+            .steps
+            .iter()
+            .filter(|h| !own.iter().any(|(o, _)| o == *h))
+            .any(grabbed_by_other_client)
     }
 
-    ///Memorizza l'associazione tra la hotkey <i>name</i> e la combinazione di tasti scritta sotto forma di stringa <i>h_str</i>.
-    ///Per controllare la correttezza sintattica della stringa utilizza <i>Hotkey::from_str().is_ok()</i>.
+    ///Aggiunge alle combinazioni della hotkey <i>name</i> quella scritta sotto forma di stringa <i>h_str</i>: una hotkey
+    ///oppure una sequenza con i passi separati da una virgola (vedi <i>Binding</i>).
+    ///Per controllare la correttezza sintattica della stringa utilizza <i>Binding::from_str()</i>.
     ///
     ///<b>ATTENZIONE:</b> con questo metodo, si sta solo creando una <b>richiesta</b> di registrazione, che si tradurrà nella registrazione della hotkey
     ///solo quando verrà richiamato <i>self::update_changes()</i>.
@@ -288,105 +534,111 @@ impl RegisteredHotkeys {
         let self_clone = self.clone();
 
         std::thread::spawn(move || {
-            let mut ret = Ok(());
-
-            //controllo che la stessa combinazione di tasti non sia già associata ad un altro comando:
-            if self_clone.check_if_already_registered(&h_str) {
-                ret = Err("Hotkey already registered");
-            } else if HotKey::from_str(&h_str).is_ok() {
-                self_clone
-                    .vec
-                    .get(<HotkeyName as Into<usize>>::into(name))
-                    .unwrap()
-                    .write()
-                    .unwrap()
-                    .replace(h_str);
-            }
+            let ret = match Binding::from_str(&h_str) {
+                Err(()) => Err("Invalid hotkey"),
+                //controllo che la stessa combinazione di tasti (o il suo inizio) non sia già associata ad un altro comando:
+                Ok(b) if self_clone.check_if_already_registered(&b) => {
+                    Err("Hotkey already registered, or in conflict with the first step of a sequence")
+                }
+                Ok(b) if self_clone.check_if_grabbed_elsewhere(&b) => {
+                    Err("This combination is already used by another application (for example the desktop environment): choose a different one")
+                }
+                Ok(b) => {
+                    self_clone.draft[&name].write().unwrap().push(b.text);
+                    Ok(())
+                }
+            };
 
             let _ = tx.send(ret);
         });
     }
 
-    ///Esegue la registrazione della hotkey presso il <i>GlobalHotkeyManager</i>.
-    ///Se la registrazione ha avuto successo, aggiorna in <i>self::backup<i> l'informazione relativa alla
-    /// <i>HotkeyName</i> passata come parametro. Altrimenti, ritorna una stringa di errore. <br/>
+    ///Esegue la registrazione della hotkey <i>h</i>, associata (eventualmente come primo passo di una sequenza)
+    ///all'azione <i>name</i>, presso il <i>GlobalHotkeyManager</i>.
+    ///Se la registrazione non ha avuto successo, ritorna una stringa di errore. <br/>
     /// NON è possibile fare eseguire da un thread separato perché non compatibile con i requisiti del crate GlobalHotkey.
-    fn register(self: &Arc<Self>, h_str: String, name: HotkeyName) -> Result<(), String> {
-        if let Ok(h) = HotKey::from_str(&h_str) {
-            return match self.ghm.register(h) {
-                Ok(()) => {
-                    self.backup
-                        .get(<HotkeyName as Into<usize>>::into(name))
-                        .unwrap()
-                        .write()
-                        .unwrap()
-                        .replace((h, h_str));
-                    Ok(())
-                }
-                Err(e) => Err(format!(
-                    "Unable to register the hotkey related to command {}.\nError: {}",
-                    <HotkeyName as Into<String>>::into(name),
-                    e
-                )),
-            };
-        }
-
-        Err(format!(
-            "Unable to register the hotkey related to command {}",
-            <HotkeyName as Into<String>>::into(name)
-        ))
+    fn register(self: &Arc<Self>, h: HotKey, name: HotkeyName) -> Result<(), String> {
+        self.ghm.register(h).map_err(|e| {
+            format!(
+                "Unable to register the hotkey related to command {}.\nError: {}",
+                name.action().label,
+                e
+            )
+        })
     }
 
-    ///Cancella l'associazione tra la hotkey <i>name</i> e la combinazione di tasti memorizzata nella corrispondente entry di <i>self::vec</i>.
+    ///Rimuove la combinazione <i>binding</i> da quelle associate alla hotkey <i>name</i> in <i>self::draft</i>.
     ///
-    ///<b>ATTENZIONE:</b> con questo metodo, si sta solo modificando la copia temporanea <i>self::vec</i>.
+    ///<b>ATTENZIONE:</b> con questo metodo, si sta solo modificando la copia temporanea <i>self::draft</i>.
     ///Le modifiche possono essere rese definitive richiamando <i>self::update_changes()</i>.
-
-    pub fn request_unregister(self: &Arc<Self>, name: HotkeyName) {
-        let _ = self
-            .vec
-            .get(<HotkeyName as Into<usize>>::into(name))
-            .unwrap()
-            .write()
-            .unwrap()
-            .take();
+    pub fn request_unregister(self: &Arc<Self>, name: HotkeyName, binding: &str) {
+        self.draft[&name].write().unwrap().retain(|b| b != binding);
     }
 
-    ///Legge, da <i>self::backup</i>, qual'è la combinazione di tasti associata alla hotkey <i>name</i>.
-    ///Se trova una combinazione valida, chiede l'annullamento della registrazione presso il <i>GlobalHotkeyManager</i>
-    ///e ritorna l'esito di tale operazione.
+    ///Chiede l'annullamento della registrazione della hotkey <i>h</i>, associata all'azione <i>name</i>, presso il
+    ///<i>GlobalHotkeyManager</i> e ritorna l'esito di tale operazione.
     ///
     /// NON è possibile fare eseguire da un thread diverso dal main thread a causa dei requisiti del crate GlobalHotkeys.
-    fn unregister(self: &Arc<Self>, name: HotkeyName) -> Result<(), String> {
-        let temp = self
-            .backup
-            .get(<HotkeyName as Into<usize>>::into(name))
-            .unwrap()
-            .write()
-            .unwrap()
-            .take();
-        if let Some((h, _)) = temp {
-            if self.ghm.unregister(h).is_ok() {
-                return Ok(());
+    fn unregister(self: &Arc<Self>, h: HotKey, name: HotkeyName) -> Result<(), String> {
+        self.ghm.unregister(h).map_err(|_| {
+            format!(
+                "Unable to unregister the hotkey related to command {}",
+                name.action().label
+            )
+        })
+    }
+
+    ///Registra, presso il <i>GlobalHotkeyManager</i>, i secondi passi delle sequenze che iniziano con quello premuto
+    ///(<i>self::pending_sequence</i>), e annulla la registrazione di quelli della sequenza precedente, ormai terminata
+    ///o scaduta. I passi già registrati in modo permanente, come prime hotkeys di altre combinazioni, vengono saltati.
+    ///Gli errori non interrompono la sequenza: sono solo stampati su stderr.
+    ///
+    ///Deve essere richiamato dalla gui ad ogni aggiornamento: il thread di ascolto chiede un repaint ogni volta
+    ///che la sequenza in corso cambia.<br>
+    ///NON è possibile fare eseguire da un thread diverso dal main thread a causa dei requisiti del crate GlobalHotkeys.
+    pub fn update_sequence_hotkeys(self: &Arc<Self>) {
+        let pending = *self.pending_sequence.read().unwrap();
+        let mut wanted: Vec<HotKey> = vec![];
+        if let Some(first) = pending {
+            let bindings = self.bindings();
+            let own = first_steps(&bindings);
+            for (_, b) in bindings.iter().filter(|(_, b)| b.steps.len() > 1 && b.first() == first) {
+                if !wanted.contains(&b.steps[1]) && !own.iter().any(|(o, _)| *o == b.steps[1]) {
+                    wanted.push(b.steps[1]);
+                }
+            }
+        }
+
+        let mut registered = self.sequence_hotkeys.write().unwrap();
+        if *registered == wanted {
+            return;
+        }
+        registered.retain(|h| {
+            if wanted.contains(h) {
+                return true;
+            }
+            if let Err(e) = self.ghm.unregister(*h) {
+                eprintln!("Error: unable to unregister a sequence step: {}", e);
+            }
+            false
+        });
+        for h in wanted {
+            if registered.contains(&h) {
+                continue;
+            }
+            match self.ghm.register(h) {
+                Ok(()) => registered.push(h),
+                Err(e) => eprintln!("Error: unable to register a sequence step: {}", e),
             }
         }
-        Err(format!(
-            "Unable to unregister the hotkey related to command {}",
-            <HotkeyName as Into<String>>::into(name)
-        ))
     }
 
-    ///Ritorna la combinazione di tasti associata alla hotkey <i>name</i> espressa come stringa di tasti separati dal carattere '+'.
+    ///Ritorna le combinazioni di tasti associate alla hotkey <i>name</i>, ciascuna espressa come stringa di tasti separati
+    ///dal carattere '+' (e, per le sequenze, di passi separati da ", ").
     ///Siccome il metodo è pensato per poter essere usato durante la modifica delle <i>RegisteredHotkeys</i> da parte
-    ///di una schermata di impostazioni, quello che è ritornato è il contenuto di <i>self::vec</i> e non di <i>self::backup</i>.
-    pub fn get_hotkey_string(self: &Arc<Self>, name: HotkeyName) -> Option<String> {
-        self.vec
-            .get(<HotkeyName as Into<usize>>::into(name))
-            .unwrap()
-            .read()
-            .unwrap()
-            .as_deref()
-            .map(|hk_str| hk_str.to_string())
+    ///di una schermata di impostazioni, quello che è ritornato è il contenuto di <i>self::draft</i> e non di <i>self::backup</i>.
+    pub fn get_hotkey_strings(self: &Arc<Self>, name: HotkeyName) -> Vec<String> {
+        self.draft[&name].read().unwrap().clone()
     }
 
     pub fn set_listen_enabled(&self, val: bool) {
@@ -394,32 +646,272 @@ impl RegisteredHotkeys {
     }
 }
 
+/// Primi passi (senza ripetizioni) delle combinazioni <i>bindings</i>, ciascuno con la prima azione che lo usa.
+fn first_steps(bindings: &[(HotkeyName, Binding)]) -> Vec<(HotKey, HotkeyName)> {
+    let mut ret: Vec<(HotKey, HotkeyName)> = vec![];
+    for (name, b) in bindings {
+        if !ret.iter().any(|(h, _)| *h == b.first()) {
+            ret.push((b.first(), *name));
+        }
+    }
+    ret
+}
+
+/// Stabilisce l'effetto della pressione della hotkey con id <i>id</i>, sapendo che <i>pending</i> è il primo passo della
+/// sequenza in corso. Ritorna l'azione da eseguire, se c'è, e il primo passo della sequenza che rimane in corso:
+/// - se completa una sequenza in corso, viene eseguita l'azione della sequenza;
+/// - altrimenti l'eventuale sequenza in corso viene annullata e la hotkey viene considerata da sola: può essere
+///   associata direttamente ad un'azione oppure essere il primo passo di una o più sequenze.
+fn resolve(
+    bindings: &[(HotkeyName, Binding)],
+    pending: Option<HotKey>,
+    id: u32,
+) -> (Option<HotkeyName>, Option<HotKey>) {
+    if let Some(first) = pending {
+        let completed = bindings
+            .iter()
+            .find(|(_, b)| b.steps.len() == 2 && b.first() == first && b.steps[1].id() == id);
+        if let Some((name, _)) = completed {
+            return (Some(*name), None);
+        }
+    }
+    if let Some((name, _)) = bindings
+        .iter()
+        .find(|(_, b)| b.steps.len() == 1 && b.first().id() == id)
+    {
+        return (Some(*name), None);
+    }
+    let started = bindings
+        .iter()
+        .find(|(_, b)| b.steps.len() > 1 && b.first().id() == id)
+        .map(|(_, b)| b.first());
+    (None, started)
+}
+
+#[cfg(target_os = "linux")]
+fn grabbed_by_other_client(hotkey: &HotKey) -> bool {
+    x11::is_grabbed(hotkey).unwrap_or_else(|e| {
+        eprintln!("Error: unable to check the hotkey: {}", e);
+        false
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn grabbed_by_other_client(_hotkey: &HotKey) -> bool {
+    false
+}
+
+/// Lancia un thread worker che registra la prossima combinazione di tasti premuta, leggendola direttamente dal
+/// server X11 (vedi il modulo <i>hotkeys::x11</i>): a differenza degli eventi di egui, in questo modo possono essere
+/// usati tutti i tasti supportati da <i>HotKey::from_str()</i>, come Stamp o i tasti del volume.<br>
+/// Sul canale ritornato viene inviata la combinazione, nella forma accettata da <i>RegisteredHotkeys::request_register()</i>,
+/// oppure None se la registrazione è stata annullata (con Esc o impostando il flag <i>stop</i>), oppure il messaggio
+/// di errore da mostrare all'utente.<br>
+/// Se <i>plain_keys</i> è true (passi di una sequenza successivi al primo) sono accettati anche lettere, cifre e simboli
+/// senza modificatori.
+///
+/// Ritorna None sulle piattaforme diverse da Linux, dove la combinazione deve essere letta dagli eventi di egui.
+pub fn start_thread_record_hotkey(
+    stop: Arc<AtomicBool>,
+    plain_keys: bool,
+) -> Option<Receiver<Result<Option<String>, String>>> {
+    #[cfg(target_os = "linux")]
+    {
+        let (tx, rx) = channel();
+        std::thread::spawn(move || {
+            let res = x11::record_hotkey(&stop, plain_keys).map_err(|e| e.to_string());
+            let _ = tx.send(res);
+        });
+        Some(rx)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (stop, plain_keys);
+        None
+    }
+}
+
 /// Funzione che lancia un thread worker che rimane (con chiamata bloccante recv()) in ascolto di eventi di pressione di
 /// hotkeys. Riceve come parametro il <i>Context</i> della gui per poter svegliare la gui, in qualsiasi stato essa sia,
 /// dopo il verificarsi di un evento. In particolare, questo è utile nel momento in cui l'applicazione ha smesso
 /// di eseguire il metodo <i>App::update()</i> (vedi impl <i>GlobalGuiState</i>) perché la finestra non è al momento visibile.
 ///
 /// Quando la chiamata a <i>GlobalHotkeyEvent::receiver.recv()</i> ritorna un evento <i>GlobalHotkeyEvent<i>, esso viene
-/// convertito in <i>HotkeyName<i> utilizzando la struttura <i>RegisteredHotkeys</i> e inviato sul canale con il thread gui.
-/// Successivamente, si assicura che la gui possa leggere dal canale, svegliandola con il metodo <i>Context::request_repaint()</i>.
+/// convertito in <i>HotkeyName<i> utilizzando la struttura <i>RegisteredHotkeys</i> (vedi <i>resolve()</i>) e inviato
+/// sul canale con il thread gui.
+/// Il thread tiene traccia della sequenza in corso: dopo la pressione del primo passo di una sequenza, lo memorizza in
+/// <i>RegisteredHotkeys::pending_sequence</i> e attende il passo successivo al massimo per <i>SEQUENCE_TIMEOUT</i>.
+/// Successivamente, si assicura che la gui possa leggere dal canale e aggiornare le hotkeys registrate per la sequenza,
+/// svegliandola con il metodo <i>Context::request_repaint()</i>.
 pub fn start_thread_listen_hotkeys(
     arc_ctx: Arc<Context>,
     arc_registered_hotkeys: Arc<RegisteredHotkeys>,
     main_thr_channel: Sender<HotkeyName>,
 ) {
-    std::thread::spawn(move || loop {
-        if let Ok(event) = GlobalHotKeyEvent::receiver().recv() {
-            for (i, opt) in arc_registered_hotkeys.backup.iter().enumerate() {
-                match opt.read().unwrap().clone() {
-                    None => (),
-                    Some((h, _)) => {
-                        if h.id() == event.id {
-                            main_thr_channel.send(HotkeyName::from(i)).unwrap();
-                            arc_ctx.request_repaint();
-                        }
+    std::thread::spawn(move || {
+        let receiver = GlobalHotKeyEvent::receiver();
+        let mut deadline: Option<Instant> = None;
+        loop {
+            let res = match deadline {
+                Some(d) => receiver.recv_timeout(d.saturating_duration_since(Instant::now())),
+                None => receiver.recv().map_err(Into::into),
+            };
+            let pending = match res {
+                Ok(event) => {
+                    let pending = *arc_registered_hotkeys.pending_sequence.read().unwrap();
+                    let (name, pending) =
+                        resolve(&arc_registered_hotkeys.bindings(), pending, event.id);
+                    if let Some(name) = name {
+                        main_thr_channel.send(name).unwrap();
                     }
+                    pending
                 }
-            }
+                //il passo successivo non è stato premuto in tempo
+                Err(e) if e.is_timeout() => None,
+                Err(_) => return,
+            };
+            deadline = pending.map(|_| Instant::now() + SEQUENCE_TIMEOUT);
+            *arc_registered_hotkeys.pending_sequence.write().unwrap() = pending;
+            arc_ctx.request_repaint();
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn actions_test() {
+        let ids: HashSet<&str> = actions().iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids.len(), actions().len());
+        for a in actions() {
+            assert_eq!(HotkeyName::from_id(&a.id), Some(a.name));
+            assert_eq!(a.name.action().id, a.id);
+        }
+        assert_eq!(HotkeyName::from_id("region_preset_2"), Some(HotkeyName::RegionPreset(1)));
+        assert_eq!(HotkeyName::from_id("unknown"), None);
+
+        //le combinazioni di default sono valide e distinte
+        let defaults: Vec<u32> = actions()
+            .iter()
+            .filter_map(|a| a.default_binding)
+            .map(|b| HotKey::from_str(b).unwrap().id())
+            .collect();
+        assert_eq!(defaults.iter().collect::<HashSet<_>>().len(), defaults.len());
+    }
+
+    #[test]
+    fn legacy_index_test() {
+        assert_eq!(HotkeyName::from_legacy_index(0), Some(HotkeyName::FullscreenScreenshot));
+        assert_eq!(HotkeyName::from_legacy_index(2), Some(HotkeyName::StopRecording));
+        assert_eq!(HotkeyName::from_legacy_index(6), Some(HotkeyName::RegionPreset(0)));
+        assert_eq!(HotkeyName::from_legacy_index(6 + MAX_REGION_PRESETS), None);
+    }
+
+    #[test]
+    fn config_round_trip_test() {
+        let bindings = vec![
+            (HotkeyName::FullscreenScreenshot, "Alt+Shift+F".to_string()),
+            (HotkeyName::Cancel, "Control+KeyQ".to_string()),
+            (HotkeyName::RegionPreset(2), "Alt+KeyY".to_string()),
+        ];
+        let json = config::to_json(&HotkeysConfig::new(bindings.clone())).unwrap();
+        let (mut parsed, errors) = parse_config(config::from_json(&json).unwrap());
+        parsed.sort_by_key(|(name, _)| name.action().id.clone());
+        let mut expected = bindings;
+        expected.sort_by_key(|(name, _)| name.action().id.clone());
+        assert_eq!(parsed, expected);
+        assert!(errors.is_empty());
+
+        //voci non valide: scartate e descritte negli errori
+        let (parsed, errors) = parse_config(config::from_json(
+            r#"{"version":1,"bindings":{"cancel":"Alt+Q","fullscreen_screenshot":"Alt+Q","rect_screenshot":"nope","unknown":"Alt+W","window_screenshot":""}}"#,
+        ).unwrap());
+        assert_eq!(parsed, vec![(HotkeyName::Cancel, "Alt+Q".to_string())]);
+        assert_eq!(errors.len(), 3);
+
+        //più combinazioni per azione: le sequenze possono condividere il primo passo, ma non iniziare con una hotkey
+        let (parsed, errors) = parse_config(config::from_json(
+            r#"{"version":2,"bindings":{"cancel":["Alt+Q","Ctrl+Shift+S,Q"],"rect_screenshot":["Ctrl+Shift+S, R","Alt+Q, W"],"fullscreen_screenshot":["Ctrl+Shift+S, F","A, B, C"]}}"#,
+        ).unwrap());
+        assert_eq!(
+            parsed,
+            vec![
+                (HotkeyName::Cancel, "Alt+Q".to_string()),
+                (HotkeyName::Cancel, "Ctrl+Shift+S, Q".to_string()),
+                (HotkeyName::FullscreenScreenshot, "Ctrl+Shift+S, F".to_string()),
+                (HotkeyName::RectScreenshot, "Ctrl+Shift+S, R".to_string()),
+            ]
+        );
+        assert_eq!(errors.len(), 2);
+
+        assert!(config::from_json::<HotkeysConfig>(r#"{"version":3,"bindings":{}}"#).is_err());
+    }
+
+    #[test]
+    fn binding_test() {
+        let b = Binding::from_str(" Ctrl+Shift+S ,r").unwrap();
+        assert_eq!(b.text, "Ctrl+Shift+S, r");
+        assert_eq!(b.steps, vec![HotKey::from_str("Control+Shift+KeyS").unwrap(), HotKey::from_str("KeyR").unwrap()]);
+        assert!(Binding::from_str("Alt+A, B, C").is_err());
+        assert!(Binding::from_str("Alt+A, ").is_err());
+        assert!(Binding::from_str("").is_err());
+
+        let b = |s: &str| Binding::from_str(s).unwrap();
+        assert!(b("Alt+A").conflicts_with(&b("Alt+KeyA")));
+        assert!(b("Alt+A").conflicts_with(&b("Alt+A, B")));
+        assert!(b("Alt+A, B").conflicts_with(&b("Alt+A")));
+        assert!(!b("Alt+A, B").conflicts_with(&b("Alt+A, C")));
+        assert!(!b("Alt+A, B").conflicts_with(&b("Alt+B")));
+    }
+
+    #[test]
+    fn resolve_test() {
+        let b = |s: &str| Binding::from_str(s).unwrap();
+        let h = |s: &str| HotKey::from_str(s).unwrap();
+        let bindings = vec![
+            (HotkeyName::FullscreenScreenshot, b("Alt+Shift+F")),
+            (HotkeyName::FullscreenScreenshot, b("Ctrl+Shift+S, F")),
+            (HotkeyName::RectScreenshot, b("Ctrl+Shift+S, R")),
+            (HotkeyName::Cancel, b("Ctrl+Shift+S, Alt+Shift+F")),
+        ];
+        let prefix = h("Ctrl+Shift+S");
+
+        assert_eq!(resolve(&bindings, None, h("Alt+Shift+F").id()), (Some(HotkeyName::FullscreenScreenshot), None));
+        assert_eq!(resolve(&bindings, None, prefix.id()), (None, Some(prefix)));
+        assert_eq!(resolve(&bindings, Some(prefix), h("R").id()), (Some(HotkeyName::RectScreenshot), None));
+        assert_eq!(resolve(&bindings, Some(prefix), h("F").id()), (Some(HotkeyName::FullscreenScreenshot), None));
+        //il completamento della sequenza ha la precedenza sulla hotkey singola
+        assert_eq!(resolve(&bindings, Some(prefix), h("Alt+Shift+F").id()), (Some(HotkeyName::Cancel), None));
+        //un secondo passo senza sequenza in corso, o un tasto che non completa la sequenza, la annulla
+        assert_eq!(resolve(&bindings, None, h("R").id()), (None, None));
+        assert_eq!(resolve(&bindings, Some(prefix), h("Q").id()), (None, None));
+        assert_eq!(resolve(&bindings, Some(prefix), prefix.id()), (None, Some(prefix)));
+    }
+
+    #[test]
+    fn legacy_config_test() {
+        //una combinazione per riga, nell'ordine delle vecchie varianti
+        let (parsed, errors) = parse_legacy_config("Alt+Shift+F\n\nAlt+Shift+S\n\n\n\nAlt+KeyP\n");
+        assert_eq!(
+            parsed,
+            vec![
+                (HotkeyName::FullscreenScreenshot, "Alt+Shift+F".to_string()),
+                (HotkeyName::StopRecording, "Alt+Shift+S".to_string()),
+                (HotkeyName::RegionPreset(0), "Alt+KeyP".to_string()),
+            ]
+        );
+        assert!(errors.is_empty());
+
+        //righe "id=combinazione", scritte dalla versione precedente
+        let (parsed, errors) = parse_legacy_config("cancel=Control+KeyQ\nmissing=Alt+KeyM\nrect_screenshot=Alt+");
+        assert_eq!(parsed, vec![(HotkeyName::Cancel, "Control+KeyQ".to_string())]);
+        assert_eq!(errors.len(), 2);
+
+        //la migrazione produce un file equivalente nel nuovo formato
+        let json = config::to_json(&HotkeysConfig::new(parsed.clone())).unwrap();
+        assert_eq!(parse_config(config::from_json(&json).unwrap()).0, parsed);
+    }
+}
@@ -0,0 +1,319 @@
+/* Registrazione delle combinazioni di tasti direttamente dal server X11 (x11rb), invece che dagli eventi di egui.
+Durante la registrazione la tastiera viene catturata (grab) sulla finestra root: in questo modo vengono ricevuti anche
+i tasti che non arriverebbero alla finestra dell'applicazione (Stamp, tasti multimediali, combinazioni intercettate
+dal window manager).
+I tasti vengono riconosciuti tramite il loro keysym e convertiti nei nomi accettati da <i>HotKey::from_str()</i>:
+sono considerati solo i tasti che global-hotkey è in grado di registrare su X11.
+
+Il modulo controlla anche se una combinazione è già catturata da un'altra applicazione, provando a registrarla con
+XGrabKey: il server risponde con un errore BadAccess se un altro client la possiede già.
+ */
+
+use global_hotkey::hotkey::HotKey;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{ConnectionExt, GrabMode, GrabStatus, Keycode, Keysym, Window};
+use x11rb::protocol::{ErrorKind, Event};
+use x11rb::rust_connection::RustConnection;
+use x11rb::CURRENT_TIME;
+
+type X11Result<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+///Maschere dei modificatori nel campo <i>state</i> degli eventi, con il nome accettato da <i>HotKey::from_str()</i>.
+const MODIFIERS: [(u16, &str); 4] = [(0x4, "Ctrl"), (0x8, "Alt"), (0x1, "Shift"), (0x40, "Super")];
+
+const XK_ESCAPE: Keysym = 0xff1b;
+
+///Nome del tasto con keysym <i>keysym</i>, se può essere usato in una hotkey.
+fn key_name(keysym: Keysym) -> Option<&'static str> {
+    const LETTERS: [&str; 26] = [
+        "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R",
+        "S", "T", "U", "V", "W", "X", "Y", "Z",
+    ];
+    const DIGITS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+    const NUMPAD: [&str; 10] = [
+        "Numpad0", "Numpad1", "Numpad2", "Numpad3", "Numpad4", "Numpad5", "Numpad6", "Numpad7",
+        "Numpad8", "Numpad9",
+    ];
+    const FUNCTION: [&str; 12] = [
+        "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12",
+    ];
+
+    Some(match keysym {
+        0x41..=0x5a => LETTERS[(keysym - 0x41) as usize],
+        0x61..=0x7a => LETTERS[(keysym - 0x61) as usize],
+        0x30..=0x39 => DIGITS[(keysym - 0x30) as usize],
+        0xffb0..=0xffb9 => NUMPAD[(keysym - 0xffb0) as usize],
+        0xffbe..=0xffc9 => FUNCTION[(keysym - 0xffbe) as usize],
+        0x20 => "Space",
+        0x2c => "Comma",
+        0x2d => "Minus",
+        0x2e => "Period",
+        0x2f => "Slash",
+        0x3b => "Semicolon",
+        0x3d => "Equal",
+        0x5b => "BracketLeft",
+        0x5c => "Backslash",
+        0x5d => "BracketRight",
+        0xff08 => "Backspace",
+        0xff09 => "Tab",
+        0xff0d => "Enter",
+        0xff14 => "ScrollLock",
+        0xff1b => "Escape",
+        0xff50 => "Home",
+        0xff51 => "ArrowLeft",
+        0xff52 => "ArrowUp",
+        0xff53 => "ArrowRight",
+        0xff54 => "ArrowDown",
+        0xff55 => "PageUp",
+        0xff56 => "PageDown",
+        0xff57 => "End",
+        0xff61 => "PrintScreen",
+        0xff63 => "Insert",
+        0xffaa => "NumpadMultiply",
+        0xffab => "NumpadAdd",
+        0xffad => "NumpadSubtract",
+        0xffae => "NumpadDecimal",
+        0xffaf => "NumpadDivide",
+        0xffff => "Delete",
+        0x1008ff11 => "AudioVolumeDown",
+        0x1008ff12 => "AudioVolumeMute",
+        0x1008ff13 => "AudioVolumeUp",
+        _ => return None,
+    })
+}
+
+///Keysym dei tasti modificatori (Shift, Control, Meta, Alt, Super, Hyper e AltGr): la loro pressione non termina la
+///registrazione.
+fn is_modifier(keysym: Keysym) -> bool {
+    matches!(keysym, 0xffe1..=0xffee | 0xfe03)
+}
+
+///Compone la stringa della hotkey, accettata da <i>HotKey::from_str()</i>, a partire dai modificatori premuti
+///(<i>state</i>) e dal nome del tasto.
+fn binding(state: u16, name: &str) -> String {
+    let mut parts: Vec<&str> = MODIFIERS
+        .iter()
+        .filter(|(mask, _)| state & mask != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    parts.push(name);
+    parts.join("+")
+}
+
+///I tasti che producono un carattere (lettere, cifre e punteggiatura) devono essere accompagnati da almeno un
+///modificatore, per non impedire la normale scrittura.
+fn needs_modifier(keysym: Keysym) -> bool {
+    keysym < 0x100
+}
+
+///Mappa della tastiera: per ogni keycode, i keysym che può produrre.
+struct Keyboard {
+    min_keycode: Keycode,
+    per_keycode: usize,
+    keysyms: Vec<Keysym>,
+}
+
+impl Keyboard {
+    fn read(conn: &RustConnection) -> X11Result<Self> {
+        let setup = conn.setup();
+        let (min, max) = (setup.min_keycode, setup.max_keycode);
+        let reply = conn.get_keyboard_mapping(min, max - min + 1)?.reply()?;
+        Ok(Self {
+            min_keycode: min,
+            per_keycode: reply.keysyms_per_keycode.max(1) as usize,
+            keysyms: reply.keysyms,
+        })
+    }
+
+    ///Primo keysym del tasto <i>keycode</i> che può essere usato in una hotkey; se non ce ne sono, il primo keysym.
+    ///Ad esempio, per i tasti del tastierino numerico il primo keysym è quello con BlocNum disattivato (KP_Home),
+    ///mentre la hotkey usa quello della cifra (KP_7).
+    fn keysym(&self, keycode: Keycode) -> Keysym {
+        let start = (keycode.saturating_sub(self.min_keycode)) as usize * self.per_keycode;
+        let row = self
+            .keysyms
+            .get(start..start + self.per_keycode)
+            .unwrap_or(&[]);
+        row.iter()
+            .copied()
+            .find(|k| key_name(*k).is_some())
+            .or_else(|| row.first().copied())
+            .unwrap_or(0)
+    }
+
+    ///Modificatori e keycode con cui può essere premuta la combinazione <i>hotkey</i>, se esiste un tasto
+    ///corrispondente su questa tastiera.<br>
+    ///Vengono confrontate con <i>hotkey</i> tutte le combinazioni di modificatori e tasti, così da riconoscere i nomi
+    ///alternativi accettati da <i>HotKey::from_str()</i> (ad esempio "KeyA" e "A").
+    fn find(&self, hotkey: &HotKey) -> Option<(u16, Keycode)> {
+        let count = self.keysyms.len() / self.per_keycode;
+        let all_mods = (0..1u16 << MODIFIERS.len()).map(|bits| {
+            MODIFIERS
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| bits & (1 << i) != 0)
+                .fold(0, |acc, (_, (mask, _))| acc | mask)
+        });
+        (0..count)
+            .map(|i| self.min_keycode.saturating_add(i as u8))
+            .filter_map(|k| key_name(self.keysym(k)).map(|name| (k, name)))
+            .flat_map(|(k, name)| all_mods.clone().map(move |mods| (mods, k, name)))
+            .find(|(mods, _, name)| HotKey::from_str(&binding(*mods, name)).ok() == Some(*hotkey))
+            .map(|(mods, k, _)| (mods, k))
+    }
+}
+
+///Cattura la tastiera e attende la pressione di una combinazione valida.<br>
+///Ritorna:
+///- <i>Ok(Some(combinazione))</i>, quando viene premuto un tasto utilizzabile insieme agli eventuali modificatori;
+///- <i>Ok(None)</i>, se viene premuto Esc senza modificatori oppure se viene impostato il flag <i>stop</i>;
+///- un errore, se la tastiera è già catturata da un'altra applicazione o se il tasto premuto non è supportato.
+///
+///Se <i>plain_keys</i> è true, anche lettere, cifre e simboli possono essere premuti senza modificatori.
+pub fn record_hotkey(stop: &AtomicBool, plain_keys: bool) -> X11Result<Option<String>> {
+    let (conn, screen) = RustConnection::connect(None)?;
+    let root = conn.setup().roots[screen].root;
+    let keyboard = Keyboard::read(&conn)?;
+
+    let grab = conn
+        .grab_keyboard(false, root, CURRENT_TIME, GrabMode::ASYNC, GrabMode::ASYNC)?
+        .reply()?;
+    if grab.status != GrabStatus::SUCCESS {
+        return Err("the keyboard is grabbed by another application".into());
+    }
+
+    let ret = wait_combination(&conn, &keyboard, stop, plain_keys);
+    conn.ungrab_keyboard(CURRENT_TIME)?;
+    conn.flush()?;
+    ret
+}
+
+fn wait_combination(
+    conn: &RustConnection,
+    keyboard: &Keyboard,
+    stop: &AtomicBool,
+    plain_keys: bool,
+) -> X11Result<Option<String>> {
+    //gli eventi sono letti senza bloccare, per poter interrompere la registrazione dalla gui
+    while !stop.load(Ordering::Relaxed) {
+        match conn.poll_for_event()? {
+            Some(Event::KeyPress(e)) => {
+                let keysym = keyboard.keysym(e.detail);
+                let mods = e.state & MODIFIERS.iter().fold(0, |acc, (mask, _)| acc | mask);
+                if is_modifier(keysym) {
+                    continue;
+                }
+                if keysym == XK_ESCAPE && mods == 0 {
+                    return Ok(None);
+                }
+                return match key_name(keysym) {
+                    Some(_) if mods == 0 && !plain_keys && needs_modifier(keysym) => {
+                        Err("letters, digits and symbols need at least one modifier".into())
+                    }
+                    Some(name) => Ok(Some(binding(mods, name))),
+                    None => Err("this key cannot be used in a hotkey".into()),
+                };
+            }
+            Some(_) => (),
+            None => std::thread::sleep(Duration::from_millis(20)),
+        }
+    }
+    Ok(None)
+}
+
+///Controlla se la combinazione <i>hotkey</i> è già catturata da un'altra applicazione: la registra con XGrabKey e,
+///se ci riesce, la rilascia subito.
+///Ritorna false se la combinazione contiene un tasto che non esiste sulla tastiera.
+pub fn is_grabbed(hotkey: &HotKey) -> X11Result<bool> {
+    let (conn, screen) = RustConnection::connect(None)?;
+    let root: Window = conn.setup().roots[screen].root;
+    let (mods, keycode) = match Keyboard::read(&conn)?.find(hotkey) {
+        Some(found) => found,
+        None => return Ok(false),
+    };
+
+    let grab = conn
+        .grab_key(false, root, mods, keycode, GrabMode::ASYNC, GrabMode::ASYNC)?
+        .check();
+    match grab {
+        Ok(()) => {
+            conn.ungrab_key(keycode, root, mods)?.check()?;
+            Ok(false)
+        }
+        Err(ReplyError::X11Error(e)) if e.error_kind == ErrorKind::Access => Ok(true),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_name_test() {
+        assert_eq!(key_name(0x61), Some("A"));
+        assert_eq!(key_name(0x5a), Some("Z"));
+        assert_eq!(key_name(0xff61), Some("PrintScreen"));
+        assert_eq!(key_name(0xffb7), Some("Numpad7"));
+        assert_eq!(key_name(0xffc9), Some("F12"));
+        //Pausa non è supportato da global-hotkey
+        assert_eq!(key_name(0xff13), None);
+        assert!(is_modifier(0xffe3) && is_modifier(0xfe03) && !is_modifier(0xff61));
+
+        //tutti i nomi sono accettati da HotKey::from_str()
+        let keysyms = (0x20..0x100)
+            .chain(0xff00..0x10000)
+            .chain(0x1008ff00..0x1008ff20);
+        for name in keysyms.filter_map(key_name) {
+            assert!(HotKey::from_str(name).is_ok(), "{}", name);
+            assert!(
+                HotKey::from_str(&format!("Ctrl+{}", name)).is_ok(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn binding_test() {
+        assert_eq!(binding(0x4 | 0x1, "A"), "Ctrl+Shift+A");
+        assert_eq!(binding(0, "PrintScreen"), "PrintScreen");
+        assert_eq!(
+            binding(0x8 | 0x40, "AudioVolumeUp"),
+            "Alt+Super+AudioVolumeUp"
+        );
+        //una lettera senza modificatori non è una hotkey valida, a differenza di Stamp
+        assert!(needs_modifier(0x61) && !needs_modifier(0xff61));
+
+        let h = HotKey::from_str(&binding(0x4 | 0x8, "PrintScreen")).unwrap();
+        assert_eq!(h, HotKey::from_str("Control+Alt+PrintScreen").unwrap());
+    }
+
+    #[test]
+    fn find_test() {
+        //keycode 10: "a" e "A"; keycode 11: tastierino numerico, con e senza BlocNum; keycode 12: Stamp
+        let keyboard = Keyboard {
+            min_keycode: 10,
+            per_keycode: 2,
+            keysyms: vec![0x61, 0x41, 0xff95, 0xffb7, 0xff61, 0],
+        };
+        assert_eq!(keyboard.keysym(11), 0xffb7);
+        let find = |s: &str| keyboard.find(&HotKey::from_str(s).unwrap());
+        assert_eq!(find("Ctrl+KeyA"), Some((0x4, 10)));
+        assert_eq!(find("shift+super+a"), Some((0x1 | 0x40, 10)));
+        assert_eq!(find("Alt+Num7"), Some((0x8, 11)));
+        assert_eq!(find("PrintScreen"), Some((0, 12)));
+        assert_eq!(find("Ctrl+KeyB"), None);
+    }
+
+    #[test]
+    #[ignore = "requires a display server: run with `xvfb-run cargo test -- --ignored`"]
+    fn is_grabbed_test() {
+        assert!(!is_grabbed(&HotKey::from_str("Ctrl+Alt+Shift+F11").unwrap()).unwrap());
+    }
+}
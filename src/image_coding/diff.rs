@@ -0,0 +1,138 @@
+/* Confronto tra due screenshot, usato per salvare una cattura solo quando il contenuto dello schermo cambia.
+Le immagini vengono suddivise in blocchi quadrati, di cui si calcola il colore medio: un blocco è considerato cambiato se
+almeno un canale del suo colore medio differisce di più della soglia. Il sottocampionamento rende il confronto veloce
+anche su schermi ad alta risoluzione e ignora le differenze minime (ad esempio, il rumore della compressione o
+l'antialiasing del testo).
+ */
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+///Parametri del confronto tra due immagini.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct DiffSettings {
+    ///Lato, in pixel, dei blocchi in cui vengono suddivise le immagini.
+    pub block_size: u32,
+    ///Differenza massima (0-255) tra i canali del colore medio di due blocchi perché siano considerati uguali.
+    pub threshold: u8,
+}
+
+impl Default for DiffSettings {
+    fn default() -> Self {
+        Self {
+            block_size: 8,
+            threshold: 16,
+        }
+    }
+}
+
+///Risultato del confronto tra due immagini.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ImageDiff {
+    ///Numero di blocchi cambiati.
+    pub changed_blocks: u32,
+    ///Rettangolo (x, y, larghezza, altezza) che contiene tutti i blocchi cambiati, in pixel dell'immagine; None se le
+    ///immagini sono uguali.
+    pub changed_area: Option<(u32, u32, u32, u32)>,
+}
+
+///Confronta <i>before</i> e <i>after</i> secondo <i>settings</i>.<br>
+///Se le due immagini hanno dimensioni diverse, l'intera <i>after</i> è considerata cambiata.
+pub fn compare_images(before: &RgbaImage, after: &RgbaImage, settings: &DiffSettings) -> ImageDiff {
+    let (width, height) = after.dimensions();
+    let block = settings.block_size.max(1);
+    let (cols, rows) = (width.div_ceil(block), height.div_ceil(block));
+    if before.dimensions() != after.dimensions() {
+        return ImageDiff {
+            changed_blocks: cols * rows,
+            changed_area: (width > 0 && height > 0).then_some((0, 0, width, height)),
+        };
+    }
+
+    let (mut changed_blocks, mut bounds) = (0, None);
+    for row in 0..rows {
+        for col in 0..cols {
+            let (a, b) = (
+                block_mean(before, col * block, row * block, block),
+                block_mean(after, col * block, row * block, block),
+            );
+            let changed = a
+                .iter()
+                .zip(b.iter())
+                .any(|(a, b)| a.abs_diff(*b) > settings.threshold as u32);
+            if changed {
+                changed_blocks += 1;
+                let (c0, r0, c1, r1) = bounds.unwrap_or((col, row, col, row));
+                bounds = Some((c0.min(col), r0.min(row), c1.max(col), r1.max(row)));
+            }
+        }
+    }
+
+    ImageDiff {
+        changed_blocks,
+        changed_area: bounds.map(|(c0, r0, c1, r1)| {
+            let (x, y) = (c0 * block, r0 * block);
+            (
+                x,
+                y,
+                ((c1 + 1) * block).min(width) - x,
+                ((r1 + 1) * block).min(height) - y,
+            )
+        }),
+    }
+}
+
+///Colore medio del blocco di lato <i>size</i> con l'angolo in alto a sinistra in (<i>x</i>, <i>y</i>); i blocchi sul
+///bordo destro e inferiore possono essere più piccoli.
+fn block_mean(img: &RgbaImage, x: u32, y: u32, size: u32) -> [u32; 4] {
+    let (x1, y1) = ((x + size).min(img.width()), (y + size).min(img.height()));
+    let mut sum = [0u64; 4];
+    for py in y..y1 {
+        for px in x..x1 {
+            for (s, c) in sum.iter_mut().zip(img.get_pixel(px, py).0) {
+                *s += c as u64;
+            }
+        }
+    }
+    let count = ((x1 - x) * (y1 - y)).max(1) as u64;
+    sum.map(|s| (s / count) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn compare_images_test() {
+        let settings = DiffSettings {
+            block_size: 10,
+            threshold: 16,
+        };
+        let before = RgbaImage::from_pixel(95, 40, Rgba([100, 100, 100, 255]));
+        let diff = compare_images(&before, &before.clone(), &settings);
+        assert_eq!(diff.changed_blocks, 0);
+        assert_eq!(diff.changed_area, None);
+
+        //differenza sotto la soglia: ignorata
+        let mut after = before.clone();
+        after.put_pixel(5, 5, Rgba([110, 110, 110, 255]));
+        assert_eq!(compare_images(&before, &after, &settings).changed_area, None);
+
+        //due blocchi cambiati, di cui uno sul bordo destro (largo 5 pixel)
+        for (x, y) in (12..17).flat_map(|x| (2..7).map(move |y| (x, y))) {
+            after.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+        }
+        for (x, y) in (90..95).flat_map(|x| (25..30).map(move |y| (x, y))) {
+            after.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+        }
+        let diff = compare_images(&before, &after, &settings);
+        assert_eq!(diff.changed_blocks, 2);
+        assert_eq!(diff.changed_area, Some((10, 0, 85, 30)));
+
+        //dimensioni diverse: tutta l'immagine è cambiata
+        let diff = compare_images(&before, &RgbaImage::new(20, 10), &settings);
+        assert_eq!(diff.changed_blocks, 2);
+        assert_eq!(diff.changed_area, Some((0, 0, 20, 10)));
+    }
+}
@@ -0,0 +1,407 @@
+/* Scrittura e lettura dei metadati di cattura (vedi <i>CaptureMetadata</i>) nei file salvati.
+Nei file PNG i metadati sono scritti in chunk tEXt (per le chiavi standard "Software" e "Creation Time") e iTXt (per le
+altre), assieme ad un chunk pHYs con la risoluzione ricavata dal fattore di scala dello schermo.
+Nei file JPEG sono scritti in un segmento APP1 (EXIF) e in un segmento COM, che contiene tutti i campi nella forma
+"chiave: valore"; la risoluzione è scritta nell'header JFIF.
+Gli altri formati vengono salvati senza metadati.
+ */
+
+use super::EncoderOptions;
+use crate::itc::{CaptureMetadata, ScreenshotDim};
+use chrono::DateTime;
+use image::codecs::jpeg::{JpegEncoder, PixelDensity};
+use image::{ImageEncoder, ImageError, ImageResult, RgbImage, RgbaImage};
+use std::io::Write;
+use std::path::Path;
+
+const SOFTWARE: &str = "Software";
+const CREATION_TIME: &str = "Creation Time";
+const SCREEN_ID: &str = "Screen Id";
+const SCREEN_RESOLUTION: &str = "Screen Resolution";
+const SCALE_FACTOR: &str = "Scale Factor";
+const CAPTURE_MODE: &str = "Capture Mode";
+const CROP_RECTANGLE: &str = "Crop Rectangle";
+const CHANGED_RECTANGLE: &str = "Changed Rectangle";
+
+const APP_NAME: &str = env!("CARGO_PKG_NAME");
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const METERS_PER_INCH: f64 = 0.0254;
+
+///Converte un errore del codificatore PNG nel tipo di errore usato dal resto del modulo.
+pub(super) fn png_to_image_error(e: png::EncodingError) -> ImageError {
+    ImageError::IoError(std::io::Error::other(e))
+}
+
+///Codifica l'immagine in formato PNG con i parametri indicati in <i>options</i> e, se presenti, i metadati di cattura.
+pub(super) fn write_png<W: Write>(
+    w: W,
+    img: &RgbaImage,
+    options: &EncoderOptions,
+    metadata: Option<&CaptureMetadata>,
+) -> ImageResult<()> {
+    let mut encoder = png::Encoder::new(w, img.width(), img.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(options.png_compression.into());
+    let (filter, adaptive_filter) = options.png_filter.into();
+    encoder.set_filter(filter);
+    encoder.set_adaptive_filter(adaptive_filter);
+    if let Some(md) = metadata {
+        add_png_metadata(&mut encoder, md)?;
+    }
+    let mut writer = encoder.write_header().map_err(png_to_image_error)?;
+    writer.write_image_data(img).map_err(png_to_image_error)?;
+    writer.finish().map_err(png_to_image_error)
+}
+
+///Aggiunge all'encoder PNG la densità (chunk pHYs) e i campi testuali dei metadati di cattura.
+pub(super) fn add_png_metadata<W: Write>(
+    encoder: &mut png::Encoder<W>,
+    md: &CaptureMetadata,
+) -> ImageResult<()> {
+    let ppm = (md.dpi() as f64 / METERS_PER_INCH).round() as u32;
+    encoder.set_pixel_dims(Some(png::PixelDimensions {
+        xppu: ppm,
+        yppu: ppm,
+        unit: png::Unit::Meter,
+    }));
+    for (key, value) in to_fields(md) {
+        if key == SOFTWARE || key == CREATION_TIME {
+            encoder.add_text_chunk(key.to_string(), value)
+        } else {
+            encoder.add_itxt_chunk(key.to_string(), value)
+        }
+        .map_err(png_to_image_error)?;
+    }
+    Ok(())
+}
+
+///Codifica l'immagine in formato JPEG con la qualità indicata e, se presenti, i metadati di cattura.<br>
+///Il codificatore non permette di aggiungere segmenti: l'immagine viene quindi codificata in memoria e i segmenti
+///EXIF e COM vengono inseriti subito dopo l'header JFIF.
+pub(super) fn write_jpeg<W: Write>(
+    mut w: W,
+    rgb: &RgbImage,
+    quality: u8,
+    metadata: Option<&CaptureMetadata>,
+) -> ImageResult<()> {
+    let mut buf = Vec::new();
+    let mut encoder = JpegEncoder::new_with_quality(&mut buf, quality);
+    if let Some(md) = metadata {
+        encoder.set_pixel_density(PixelDensity::dpi(md.dpi().min(u16::MAX as u32) as u16));
+    }
+    encoder.write_image(rgb, rgb.width(), rgb.height(), image::ColorType::Rgb8)?;
+    if let Some(md) = metadata {
+        let mut segments = jpeg_segment(0xE1, &exif_payload(md));
+        segments.extend(jpeg_segment(0xFE, comment_text(md).as_bytes()));
+        let pos = jfif_header_end(&buf);
+        buf.splice(pos..pos, segments);
+    }
+    w.write_all(&buf)?;
+    Ok(())
+}
+
+///Legge i metadati di cattura da un file PNG o JPEG salvato dall'applicazione.
+pub fn read_metadata(path: &Path) -> Result<CaptureMetadata, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let fields = if data.starts_with(&PNG_SIGNATURE) {
+        let decoder = png::Decoder::new(data.as_slice());
+        let reader = decoder.read_info().map_err(|e| e.to_string())?;
+        let info = reader.info();
+        let mut fields: Vec<(String, String)> = info
+            .uncompressed_latin1_text
+            .iter()
+            .map(|c| (c.keyword.clone(), c.text.clone()))
+            .collect();
+        for c in &info.utf8_text {
+            fields.push((c.keyword.clone(), c.get_text().map_err(|e| e.to_string())?));
+        }
+        fields
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        jpeg_segments(&data)
+            .into_iter()
+            .filter(|(marker, _)| *marker == 0xFE)
+            .flat_map(|(_, payload)| {
+                String::from_utf8_lossy(payload)
+                    .lines()
+                    .filter_map(|line| line.split_once(": "))
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    } else {
+        return Err("unsupported file format".to_string());
+    };
+    from_fields(&fields)
+}
+
+///Legge la risoluzione di stampa (in DPI) da un file PNG (chunk pHYs) o JPEG (header JFIF).
+///Ritorna None se il file non la specifica.
+pub fn read_dpi(path: &Path) -> Option<u32> {
+    let data = std::fs::read(path).ok()?;
+    if data.starts_with(&PNG_SIGNATURE) {
+        let reader = png::Decoder::new(data.as_slice()).read_info().ok()?;
+        match reader.info().pixel_dims? {
+            png::PixelDimensions {
+                xppu,
+                unit: png::Unit::Meter,
+                ..
+            } => Some((xppu as f64 * METERS_PER_INCH).round() as u32),
+            _ => None,
+        }
+    } else {
+        let segments = jpeg_segments(&data);
+        let (_, jfif) = segments.first().filter(|(marker, _)| *marker == 0xE0)?;
+        if jfif.len() < 12 || !jfif.starts_with(b"JFIF\0") {
+            return None;
+        }
+        let density = u16::from_be_bytes([jfif[8], jfif[9]]) as f64;
+        match jfif[7] {
+            1 => Some(density as u32),
+            2 => Some((density * 2.54).round() as u32),
+            _ => None,
+        }
+    }
+}
+
+///Coppie chiave-valore scritte nei file.
+fn to_fields(md: &CaptureMetadata) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        (SOFTWARE, format!("{} {}", APP_NAME, md.app_version)),
+        (CREATION_TIME, md.timestamp.to_rfc3339()),
+        (SCREEN_ID, md.screen_id.to_string()),
+        (
+            SCREEN_RESOLUTION,
+            format!("{}x{}", md.screen_size.0, md.screen_size.1),
+        ),
+        (SCALE_FACTOR, md.scale_factor.to_string()),
+        (CAPTURE_MODE, <&str>::from(&md.mode).to_string()),
+    ];
+    if let Some((x, y, w, h)) = md.crop {
+        fields.push((CROP_RECTANGLE, format!("{},{},{},{}", x, y, w, h)));
+    }
+    if let Some((x, y, w, h)) = md.changed_area {
+        fields.push((CHANGED_RECTANGLE, format!("{},{},{},{}", x, y, w, h)));
+    }
+    fields
+}
+
+///Ricostruisce i metadati a partire dalle coppie chiave-valore lette da un file.
+///Tutti i campi sono obbligatori, tranne il rettangolo di ritaglio e quello cambiato.
+fn from_fields(fields: &[(String, String)]) -> Result<CaptureMetadata, String> {
+    let get = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .ok_or_else(|| format!("missing field '{}'", key))
+    };
+    let invalid = |key: &str| format!("invalid field '{}'", key);
+
+    let app_version = get(SOFTWARE)?
+        .strip_prefix(APP_NAME)
+        .map(|v| v.trim().to_string())
+        .ok_or_else(|| invalid(SOFTWARE))?;
+    let timestamp =
+        DateTime::parse_from_rfc3339(get(CREATION_TIME)?).map_err(|_| invalid(CREATION_TIME))?;
+    let screen_id = get(SCREEN_ID)?.parse().map_err(|_| invalid(SCREEN_ID))?;
+    let screen_size = get(SCREEN_RESOLUTION)?
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .ok_or_else(|| invalid(SCREEN_RESOLUTION))?;
+    let scale_factor = get(SCALE_FACTOR)?
+        .parse()
+        .map_err(|_| invalid(SCALE_FACTOR))?;
+    let mode = ScreenshotDim::try_from(get(CAPTURE_MODE)?)?;
+    let rectangle = |key: &str| match get(key) {
+        Ok(v) => {
+            let values = v
+                .split(',')
+                .map(|n| n.parse::<u32>())
+                .collect::<Result<Vec<u32>, _>>()
+                .map_err(|_| invalid(key))?;
+            match values[..] {
+                [x, y, w, h] => Ok(Some((x, y, w, h))),
+                _ => Err(invalid(key)),
+            }
+        }
+        Err(_) => Ok(None),
+    };
+    let crop = rectangle(CROP_RECTANGLE)?;
+    let changed_area = rectangle(CHANGED_RECTANGLE)?;
+
+    Ok(CaptureMetadata {
+        timestamp,
+        screen_id,
+        screen_size,
+        scale_factor,
+        mode,
+        crop,
+        changed_area,
+        app_version,
+    })
+}
+
+///Testo del segmento COM: un campo per riga, nella forma "chiave: valore".
+fn comment_text(md: &CaptureMetadata) -> String {
+    to_fields(md)
+        .into_iter()
+        .map(|(k, v)| format!("{}: {}", k, v))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+///Contenuto del segmento APP1: una struttura TIFF (big endian) con la sola IFD0, che contiene descrizione, risoluzione,
+///software e data di cattura.
+fn exif_payload(md: &CaptureMetadata) -> Vec<u8> {
+    const ASCII: u16 = 2;
+    const SHORT: u16 = 3;
+    const RATIONAL: u16 = 5;
+    let ascii = |s: String| {
+        let mut v = s.into_bytes();
+        v.push(0);
+        (v.len() as u32, v)
+    };
+    let rational = |n: u32| (1, [n.to_be_bytes(), 1u32.to_be_bytes()].concat());
+    let dpi = md.dpi();
+
+    //(tag, tipo, numero di valori, valori), in ordine crescente di tag
+    let entries: Vec<(u16, u16, (u32, Vec<u8>))> = vec![
+        (0x010E, ASCII, ascii(comment_text(md))),
+        (0x011A, RATIONAL, rational(dpi)),
+        (0x011B, RATIONAL, rational(dpi)),
+        (0x0128, SHORT, (1, 2u16.to_be_bytes().to_vec())), //unità: pollici
+        (0x0131, ASCII, ascii(format!("{} {}", APP_NAME, md.app_version))),
+        (
+            0x0132,
+            ASCII,
+            ascii(md.timestamp.format("%Y:%m:%d %H:%M:%S").to_string()),
+        ),
+    ];
+
+    let mut tiff = b"MM\x00\x2A".to_vec();
+    tiff.extend(8u32.to_be_bytes());
+    tiff.extend((entries.len() as u16).to_be_bytes());
+    let mut data_offset = 8 + 2 + entries.len() * 12 + 4;
+    let mut data = vec![];
+    for (tag, kind, (count, mut value)) in entries {
+        tiff.extend(tag.to_be_bytes());
+        tiff.extend(kind.to_be_bytes());
+        tiff.extend(count.to_be_bytes());
+        if value.len() <= 4 {
+            value.resize(4, 0);
+            tiff.extend(value);
+        } else {
+            tiff.extend((data_offset as u32).to_be_bytes());
+            data_offset += value.len();
+            data.extend(value);
+            if data_offset % 2 == 1 {
+                data.push(0);
+                data_offset += 1;
+            }
+        }
+    }
+    tiff.extend(0u32.to_be_bytes()); //nessuna IFD successiva
+    tiff.extend(data);
+    [b"Exif\x00\x00".to_vec(), tiff].concat()
+}
+
+///Segmento JPEG con il marker indicato: la lunghezza include i due byte della lunghezza stessa.
+fn jpeg_segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+    let mut ret = vec![0xFF, marker];
+    ret.extend(((payload.len() + 2) as u16).to_be_bytes());
+    ret.extend(payload);
+    ret
+}
+
+///Segmenti (marker, contenuto) che precedono i dati compressi dell'immagine.
+fn jpeg_segments(data: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut ret = vec![];
+    let mut pos = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xFF {
+        let marker = data[pos + 1];
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        if len < 2 || pos + 2 + len > data.len() {
+            break;
+        }
+        ret.push((marker, &data[pos + 4..pos + 2 + len]));
+        pos += 2 + len;
+    }
+    ret
+}
+
+///Posizione immediatamente successiva al segmento APP0 (JFIF), se presente, altrimenti al marker SOI.
+fn jfif_header_end(data: &[u8]) -> usize {
+    if data.len() >= 6 && data[2..4] == [0xFF, 0xE0] {
+        4 + u16::from_be_bytes([data[4], data[5]]) as usize
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+    use std::path::PathBuf;
+
+    fn metadata(crop: Option<(u32, u32, u32, u32)>) -> CaptureMetadata {
+        CaptureMetadata {
+            timestamp: Local::now().fixed_offset(),
+            screen_id: 42,
+            screen_size: (1920, 1080),
+            scale_factor: 1.25,
+            mode: if crop.is_some() {
+                ScreenshotDim::Rectangle
+            } else {
+                ScreenshotDim::Fullscreen
+            },
+            crop,
+            changed_area: crop.map(|(_, _, w, h)| (1, 2, w - 1, h - 2)),
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    #[test]
+    fn png_round_trip_test() {
+        let img = RgbaImage::from_pixel(4, 3, image::Rgba([1, 2, 3, 255]));
+        let md = metadata(Some((10, 20, 4, 3)));
+        let path = PathBuf::from("./test_metadata.png");
+        let file = std::fs::File::create(&path).unwrap();
+        write_png(file, &img, &EncoderOptions::default(), Some(&md)).unwrap();
+
+        assert_eq!(read_metadata(&path), Ok(md.clone()));
+        assert_eq!(read_dpi(&path), Some(md.dpi()));
+        assert_eq!(image::open(&path).unwrap().to_rgba8(), img);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn jpeg_round_trip_test() {
+        let img = RgbImage::from_pixel(4, 3, image::Rgb([1, 2, 3]));
+        let md = metadata(None);
+        let path = PathBuf::from("./test_metadata.jpeg");
+        let file = std::fs::File::create(&path).unwrap();
+        write_jpeg(file, &img, 90, Some(&md)).unwrap();
+
+        assert_eq!(read_metadata(&path), Ok(md.clone()));
+        assert_eq!(read_dpi(&path), Some(md.dpi()));
+        assert!(image::open(&path).is_ok());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn missing_metadata_test() {
+        let img = RgbaImage::from_pixel(2, 2, image::Rgba([1, 2, 3, 255]));
+        let path = PathBuf::from("./test_no_metadata.png");
+        let file = std::fs::File::create(&path).unwrap();
+        write_png(file, &img, &EncoderOptions::default(), None).unwrap();
+
+        assert!(read_metadata(&path).is_err());
+        assert_eq!(read_dpi(&path), None);
+        let _ = std::fs::remove_file(path);
+    }
+}
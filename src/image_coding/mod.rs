@@ -1,19 +1,39 @@
-/* Modulo dedicato all'elaborazione di immagini: scrittura in memoria secondaria, copia nella clipboard e ritaglio.
+/* Modulo dedicato all'elaborazione di immagini: scrittura in memoria secondaria, copia nella clipboard, ritaglio e
+confronto (vedi il modulo diff).
 Siccome sono operazioni onerose, per ogni funzionalità sono messi a disposizione metodi per lanciare un thread worker. */
 
+mod diff;
+mod metadata;
+mod optimize;
+mod text_export;
+
 use arboard::{Clipboard, ImageData};
 use eframe::emath::Rect;
-use image::{ImageError, RgbaImage};
+use crate::itc::CaptureMetadata;
+use image::codecs::webp::{WebPEncoder, WebPQuality};
+use image::{ImageError, RgbImage, RgbaImage};
+pub use diff::{compare_images, DiffSettings};
+pub use metadata::{read_dpi, read_metadata};
+pub use text_export::{start_thread_copy_as_text, ExportSource, TextExport};
+use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::sync::mpsc::{channel, Receiver};
+use std::io::BufWriter;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
 use std::vec;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
 pub enum ImageFormat {
+    #[default]
     Png,
     Jpeg,
     Gif,
+    WebP,
+    WebPLossy,
+    Bmp,
+    Tiff,
+    Qoi,
 }
 
 impl Into<&str> for ImageFormat {
@@ -22,19 +42,30 @@ impl Into<&str> for ImageFormat {
             Self::Png => "Png",
             Self::Jpeg => "Jpeg",
             Self::Gif => "Gif",
+            Self::WebP => "WebP",
+            Self::WebPLossy => "WebP (lossy)",
+            Self::Bmp => "Bmp",
+            Self::Tiff => "Tiff",
+            Self::Qoi => "Qoi",
         }
     }
 }
 
-impl From<&str> for ImageFormat {
-    fn from(s: &str) -> Self {
-        match s {
-            "Png" | "PNG" | "png" => Self::Png,
-            "Jpeg" | "JPEG" | "jpeg" => Self::Jpeg,
-            "Gif" | "gif" | "GIF" => Self::Gif,
-            &_ => {
-                unreachable!("Non recognized extension");
-            }
+impl TryFrom<&str> for ImageFormat {
+    type Error = String;
+
+    ///Riconosce l'estensione di un file, senza distinzione tra maiuscole e minuscole.
+    ///Per l'estensione "webp" ritorna la variante lossless.
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(Self::Png),
+            "jpeg" | "jpg" => Ok(Self::Jpeg),
+            "gif" => Ok(Self::Gif),
+            "webp" => Ok(Self::WebP),
+            "bmp" => Ok(Self::Bmp),
+            "tiff" | "tif" => Ok(Self::Tiff),
+            "qoi" => Ok(Self::Qoi),
+            _ => Err(format!("Non recognized extension: {}", s)),
         }
     }
 }
@@ -42,29 +73,367 @@ impl From<&str> for ImageFormat {
 impl ImageFormat {
     ///Utility per ottenere l'elenco dei formati contenuti nella enum sotto forma di stringhe.
     pub fn available_formats() -> Vec<ImageFormat> {
-        vec![ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Gif]
+        vec![
+            ImageFormat::Png,
+            ImageFormat::Jpeg,
+            ImageFormat::Gif,
+            ImageFormat::WebP,
+            ImageFormat::WebPLossy,
+            ImageFormat::Bmp,
+            ImageFormat::Tiff,
+            ImageFormat::Qoi,
+        ]
+    }
+
+    ///Estensione dei file salvati nel formato. Si distingue dal nome ritornato da <i>Into<&str></i> perché
+    ///le due varianti WebP condividono la stessa estensione.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::WebP | Self::WebPLossy => "WebP",
+            _ => self.into(),
+        }
+    }
+
+    ///Tipo MIME del formato, usato per comporre i data URI.
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Gif => "image/gif",
+            Self::WebP | Self::WebPLossy => "image/webp",
+            Self::Bmp => "image/bmp",
+            Self::Tiff => "image/tiff",
+            Self::Qoi => "image/qoi",
+        }
     }
 }
 
-///Crea un canale e muove il suo  <i>Sender</i> ad un nuovo thread, il quale si occupa di eseguire <i>copy_to_clipboard()</i>
-///ed inviare il risultato sul canale.
+///Formati disponibili per il salvataggio delle registrazioni animate.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl From<AnimationFormat> for &'static str {
+    fn from(value: AnimationFormat) -> Self {
+        match value {
+            AnimationFormat::Gif => "Gif",
+            AnimationFormat::Apng => "Apng",
+        }
+    }
+}
+
+impl AnimationFormat {
+    pub fn available_formats() -> Vec<AnimationFormat> {
+        vec![AnimationFormat::Gif, AnimationFormat::Apng]
+    }
+
+    ///Formato di immagine statica con la stessa estensione, usato per comporre il path di salvataggio.
+    pub fn image_format(self) -> ImageFormat {
+        match self {
+            AnimationFormat::Gif => ImageFormat::Gif,
+            AnimationFormat::Apng => ImageFormat::Png,
+        }
+    }
+}
+
+///Livello di compressione dei file PNG.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum PngCompression {
+    Default,
+    Fast,
+    Best,
+}
+
+impl From<PngCompression> for &'static str {
+    fn from(value: PngCompression) -> Self {
+        match value {
+            PngCompression::Default => "Default",
+            PngCompression::Fast => "Fast",
+            PngCompression::Best => "Best",
+        }
+    }
+}
+
+impl PngCompression {
+    pub fn available_compressions() -> Vec<PngCompression> {
+        vec![PngCompression::Default, PngCompression::Fast, PngCompression::Best]
+    }
+}
+
+impl From<PngCompression> for png::Compression {
+    fn from(value: PngCompression) -> Self {
+        match value {
+            PngCompression::Default => png::Compression::Default,
+            PngCompression::Fast => png::Compression::Fast,
+            PngCompression::Best => png::Compression::Best,
+        }
+    }
+}
+
+///Filtro applicato alle righe dell'immagine prima della compressione dei file PNG.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum PngFilter {
+    NoFilter,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    Adaptive,
+}
+
+impl From<PngFilter> for &'static str {
+    fn from(value: PngFilter) -> Self {
+        match value {
+            PngFilter::NoFilter => "None",
+            PngFilter::Sub => "Sub",
+            PngFilter::Up => "Up",
+            PngFilter::Avg => "Average",
+            PngFilter::Paeth => "Paeth",
+            PngFilter::Adaptive => "Adaptive",
+        }
+    }
+}
+
+///Il filtro adattivo sceglie il filtro migliore per ogni riga, partendo da Sub.
+impl From<PngFilter> for (png::FilterType, png::AdaptiveFilterType) {
+    fn from(value: PngFilter) -> Self {
+        let filter = match value {
+            PngFilter::NoFilter => png::FilterType::NoFilter,
+            PngFilter::Sub | PngFilter::Adaptive => png::FilterType::Sub,
+            PngFilter::Up => png::FilterType::Up,
+            PngFilter::Avg => png::FilterType::Avg,
+            PngFilter::Paeth => png::FilterType::Paeth,
+        };
+        if value == PngFilter::Adaptive {
+            (filter, png::AdaptiveFilterType::Adaptive)
+        } else {
+            (filter, png::AdaptiveFilterType::NonAdaptive)
+        }
+    }
+}
+
+impl PngFilter {
+    pub fn available_filters() -> Vec<PngFilter> {
+        vec![
+            PngFilter::NoFilter,
+            PngFilter::Sub,
+            PngFilter::Up,
+            PngFilter::Avg,
+            PngFilter::Paeth,
+            PngFilter::Adaptive,
+        ]
+    }
+}
+
+///Ottimizzazione delle dimensioni dei file PNG, applicata dopo la codifica (vedi modulo <i>optimize</i>).
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum PngOptimization {
+    #[default]
+    Off,
+    ///Prova tutte le combinazioni di livello di compressione e filtro e tiene la codifica più piccola; i pixel restano
+    ///invariati.
+    Lossless,
+    ///Riduce l'immagine ad una palette di al più 256 colori, con dithering: comporta una perdita di qualità.
+    Palette,
+}
+
+impl From<PngOptimization> for &'static str {
+    fn from(value: PngOptimization) -> Self {
+        match value {
+            PngOptimization::Off => "Off",
+            PngOptimization::Lossless => "Lossless",
+            PngOptimization::Palette => "Palette (≤256 colours)",
+        }
+    }
+}
+
+impl PngOptimization {
+    pub fn available_optimizations() -> Vec<PngOptimization> {
+        vec![
+            PngOptimization::Off,
+            PngOptimization::Lossless,
+            PngOptimization::Palette,
+        ]
+    }
+}
+
+///Parametri dei codificatori dei singoli formati, usati da <i>save_image()</i>.
+///Sono memorizzati nelle impostazioni di salvataggio.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct EncoderOptions {
+    ///Da 1 (qualità minima) a 100.
+    pub jpeg_quality: u8,
+    ///Colore su cui vengono appiattiti i pixel trasparenti, dato che il formato JPEG non supporta il canale alpha.
+    pub jpeg_background: [u8; 3],
+    pub png_compression: PngCompression,
+    pub png_filter: PngFilter,
+    ///Assente nelle impostazioni salvate dalle versioni precedenti: in tal caso è disattivata.
+    #[serde(default)]
+    pub png_optimization: PngOptimization,
+    ///Da 1 (più lento, qualità migliore) a 30.
+    pub gif_speed: i32,
+}
+
+impl Default for EncoderOptions {
+    fn default() -> Self {
+        Self {
+            jpeg_quality: 90,
+            jpeg_background: [255, 255, 255],
+            png_compression: PngCompression::Default,
+            png_filter: PngFilter::Adaptive,
+            png_optimization: PngOptimization::Off,
+            gif_speed: 30,
+        }
+    }
+}
+
+///Contenuto da copiare nella clipboard.
+enum ClipboardContent {
+    Image(RgbaImage),
+    Text(String),
+}
+
+///Richieste gestite dal thread che possiede la clipboard.
+enum ClipboardRequest {
+    ///Copia di un'immagine o di un testo, con il <i>Sender</i> su cui inviare l'esito.
+    Copy(ClipboardContent, Sender<Result<(), arboard::Error>>),
+    ///Rilascio della clipboard: la conferma viene inviata dopo che il contenuto è stato ceduto al clipboard manager.
+    Stop(Sender<()>),
+}
+
+///Canale verso il thread che possiede la clipboard, creato alla prima richiesta di copia.
+static CLIPBOARD_SERVICE: Mutex<Option<Sender<ClipboardRequest>>> = Mutex::new(None);
+
+///Invia una richiesta di copia al thread che possiede la clipboard (avviandolo, se non è ancora in esecuzione)
+///e ritorna il <i>Receiver</i> su cui verrà inviato l'esito.<br>
+///Su X11 il contenuto della clipboard è servito dal processo che la possiede, e smette di essere disponibile appena
+///l'oggetto <i>Clipboard</i> viene distrutto: per questo la copia non avviene in un thread di breve durata, ma in un
+///thread che rimane in esecuzione per tutta la durata dell'applicazione e continua a servire l'ultima immagine copiata.
 pub fn start_thread_copy_to_clipboard(img: &RgbaImage) -> Receiver<Result<(), arboard::Error>> {
+    send_clipboard_request(ClipboardContent::Image(img.clone()))
+}
+
+///Come <i>start_thread_copy_to_clipboard()</i>, ma copia nella clipboard un testo.
+pub fn start_thread_copy_text_to_clipboard(text: String) -> Receiver<Result<(), arboard::Error>> {
+    send_clipboard_request(ClipboardContent::Text(text))
+}
+
+fn send_clipboard_request(content: ClipboardContent) -> Receiver<Result<(), arboard::Error>> {
+    let (tx, rx) = channel();
+    let mut service = CLIPBOARD_SERVICE.lock().unwrap_or_else(|e| e.into_inner());
+    let mut request = ClipboardRequest::Copy(content, tx);
+    if let Some(sender) = service.as_ref() {
+        match sender.send(request) {
+            Ok(()) => return rx,
+            //il thread è terminato: viene riavviato e la richiesta viene inoltrata al nuovo thread
+            Err(e) => request = e.0,
+        }
+    }
+    let sender = start_thread_clipboard_service();
+    if let Err(e) = sender.send(request) {
+        if let ClipboardRequest::Copy(_, tx) = e.0 {
+            let _ = tx.send(Err(arboard::Error::Unknown {
+                description: "clipboard service not available".to_string(),
+            }));
+        }
+    }
+    service.replace(sender);
+    rx
+}
+
+///Termina il thread che possiede la clipboard, attendendo che l'ultima immagine copiata sia stata ceduta all'eventuale
+///clipboard manager del sistema. Deve essere chiamata prima della terminazione del processo, altrimenti su X11
+///il contenuto della clipboard andrebbe perso.
+pub fn stop_clipboard_service() {
+    let sender = CLIPBOARD_SERVICE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take();
+    if let Some(sender) = sender {
+        let (tx, rx) = channel();
+        if sender.send(ClipboardRequest::Stop(tx)).is_ok() {
+            let _ = rx.recv();
+        }
+    }
+}
+
+///Lancia il thread che possiede la clipboard e serve le richieste ricevute sul canale, fino alla ricezione di
+///<i>ClipboardRequest::Stop</i> o alla chiusura del canale.<br>
+///L'oggetto <i>Clipboard</i> viene creato alla prima richiesta e mantenuto tra una richiesta e l'altra; se un'operazione
+///fallisce viene ricreato alla richiesta successiva.
+fn start_thread_clipboard_service() -> Sender<ClipboardRequest> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let mut clipboard: Option<Clipboard> = None;
+        while let Ok(request) = rx.recv() {
+            match request {
+                ClipboardRequest::Copy(content, result_tx) => {
+                    let _ = result_tx.send(copy_to_clipboard(&mut clipboard, &content));
+                }
+                ClipboardRequest::Stop(ack_tx) => {
+                    drop(clipboard.take());
+                    let _ = ack_tx.send(());
+                    break;
+                }
+            }
+        }
+    });
+    tx
+}
+
+fn copy_to_clipboard(
+    clipboard: &mut Option<Clipboard>,
+    content: &ClipboardContent,
+) -> Result<(), arboard::Error> {
+    if clipboard.is_none() {
+        //inizializzazione della clipboard per copiare negli appunti
+        clipboard.replace(Clipboard::new()?);
+    }
+    //settare l'immagine o il testo come elemento copiato negli appunti
+    let ret = match content {
+        ClipboardContent::Image(img) => clipboard.as_mut().unwrap().set_image(ImageData {
+            width: img.width() as usize,
+            height: img.height() as usize,
+            bytes: std::borrow::Cow::Borrowed(img),
+        }),
+        ClipboardContent::Text(text) => clipboard.as_mut().unwrap().set_text(text.as_str()),
+    };
+    if ret.is_err() {
+        clipboard.take();
+    }
+    ret
+}
+
+///Crea un canale e muove il suo  <i>Sender</i> ad un nuovo thread, il quale si occupa di eseguire <i>paste_from_clipboard()</i>
+///ed inviare il risultato sul canale.
+pub fn start_thread_paste_from_clipboard() -> Receiver<Result<RgbaImage, &'static str>> {
     let (tx, rx) = channel();
-    let i = img.clone();
     std::thread::spawn(move || {
-        let _ = tx.send(copy_to_clipboard(&i));
+        let _ = tx.send(paste_from_clipboard());
     });
     rx
 }
 
-fn copy_to_clipboard(img: &RgbaImage) -> Result<(), arboard::Error> {
-    let mut ctx2 = Clipboard::new().unwrap(); //inizializzazione della clipboard per copiare negli appunti
-    let img_data = ImageData {
-        width: img.width() as usize,
-        height: img.height() as usize,
-        bytes: std::borrow::Cow::Borrowed(img),
-    };
-    ctx2.set_image(img_data) //settare l'immagine come elemento copiato negli appunti
+///Legge l'immagine attualmente contenuta nella clipboard. Ritorna un errore se la clipboard non contiene un'immagine.
+fn paste_from_clipboard() -> Result<RgbaImage, &'static str> {
+    let mut clipboard = Clipboard::new().map_err(|_| "Error: unable to access the clipboard")?;
+    match clipboard.get_image() {
+        Ok(img_data) => image_data_to_rgba(img_data),
+        Err(arboard::Error::ContentNotAvailable) => Err("The clipboard does not contain an image"),
+        Err(_) => Err("Error: unable to read the image from the clipboard"),
+    }
+}
+
+///Converte l'immagine letta dalla clipboard (pixel RGBA, 8 bit per canale) in <i>RgbaImage</i>.
+fn image_data_to_rgba(img_data: ImageData) -> Result<RgbaImage, &'static str> {
+    RgbaImage::from_raw(
+        img_data.width as u32,
+        img_data.height as u32,
+        img_data.bytes.into_owned(),
+    )
+    .ok_or("Error: invalid image in the clipboard")
 }
 
 ///Crea un canale e muove il suo  <i>Sender</i> ad un nuovo thread, il quale si occupa di eseguire <i>crop_image()</i>
@@ -94,38 +463,138 @@ fn crop_image(rect: Rect, img: RgbaImage) -> RgbaImage {
     .to_image()
 }
 
+///Crea un canale e muove il suo  <i>Sender</i> ad un nuovo thread, il quale si occupa di eseguire <i>load_image()</i>
+///ed inviare il risultato sul canale.
+pub fn start_thread_load_image(
+    path: std::path::PathBuf,
+) -> Receiver<Result<RgbaImage, &'static str>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(load_image(&path));
+    });
+    rx
+}
+
+///Legge un'immagine salvata in memoria secondaria, riconoscendone il formato dal contenuto del file, e la converte in RGBA.
+fn load_image(path: &std::path::Path) -> Result<RgbaImage, &'static str> {
+    let reader = image::io::Reader::open(path)
+        .map_err(|_| "Error: unable to open the file")?
+        .with_guessed_format()
+        .map_err(|_| "Error: unable to read the file")?;
+    match reader.decode() {
+        Ok(img) => Ok(img.to_rgba8()),
+        Err(ImageError::Unsupported(_)) => Err("Error: unsupported image format"),
+        Err(_) => Err("Error: unable to decode the image"),
+    }
+}
+
+///Esito di un salvataggio andato a buon fine.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SavedFile {
+    pub path: String,
+    ///Dimensione del file scritto, in byte.
+    pub size: u64,
+    ///Byte risparmiati dall'ottimizzazione PNG rispetto alla codifica standard; None se non è stata applicata.
+    pub bytes_saved: Option<u64>,
+}
+
+impl SavedFile {
+    fn new(path: &std::path::Path, bytes_saved: Option<u64>) -> Self {
+        Self {
+            path: path.as_os_str().to_str().unwrap().to_string(),
+            size: std::fs::metadata(path).map(|m| m.len()).unwrap_or_default(),
+            bytes_saved,
+        }
+    }
+}
+
 ///Crea un canale e muove il suo  <i>Sender</i> ad un nuovo thread, il quale si occupa di eseguire <i>save_image()</i>
 ///ed inviare il risultato sul canale.
 pub fn start_thread_save_image(
     path: std::path::PathBuf,
     img: RgbaImage,
-) -> Receiver<Result<String, ImageError>> {
+    format: ImageFormat,
+    options: EncoderOptions,
+    metadata: Option<CaptureMetadata>,
+) -> Receiver<Result<SavedFile, ImageError>> {
     let (tx, rx) = channel();
-    let path_str = path.as_os_str().to_str().unwrap().to_string();
     std::thread::spawn(move || {
-        let _ = tx.send(save_image(path, img).map_or_else(|res| Err(res), |()| Ok(path_str)));
+        let _ = tx.send(
+            save_image(path.clone(), img, format, options, metadata.as_ref())
+                .map(|bytes_saved| SavedFile::new(&path, bytes_saved)),
+        );
     });
     rx
 }
 
-///Controlla che l'estensione del file di output sia tra i formati supportati.
-///Se il formato è GIF, esegue codice specifico per accelerare il salvataggio.
-fn save_image(file_output: std::path::PathBuf, img: RgbaImage) -> image::ImageResult<()> {
+///Controlla che l'estensione del file di output sia quella del formato <i>format</i>, poi codifica l'immagine
+///nel formato <i>format</i>, con i parametri indicati in <i>options</i>.
+///Il formato è passato esplicitamente perché non sempre è ricavabile dall'estensione (WebP lossless e lossy).
+///Se presenti, i metadati di cattura vengono scritti nei file PNG e JPEG (vedi il modulo <i>metadata</i>).<br>
+///Ritorna i byte risparmiati dall'ottimizzazione PNG, se applicata (vedi il modulo <i>optimize</i>).
+fn save_image(
+    file_output: std::path::PathBuf,
+    img: RgbaImage,
+    format: ImageFormat,
+    options: EncoderOptions,
+    metadata: Option<&CaptureMetadata>,
+) -> image::ImageResult<Option<u64>> {
     if let Some(ext) = file_output.extension() {
-        if ImageFormat::available_formats().contains(&ImageFormat::from(ext.to_str().unwrap())) {
-            return match ext.to_str().unwrap().into() {
-                ImageFormat::Gif => {
-                    let file = File::create(file_output).unwrap();
-                    let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(file, 30);
-                    encoder.encode(&img, img.width(), img.height(), image::ColorType::Rgba8)
-                }
-                _ => img.save(file_output),
-            };
+        let ext = ext.to_str().unwrap_or_default();
+        //le varianti di WebP condividono l'estensione
+        let check = ImageFormat::try_from(ext).and_then(|f| {
+            if f.extension() == format.extension() {
+                Ok(())
+            } else {
+                let name: &str = format.into();
+                Err(format!("The extension .{} does not match the format {}", ext, name))
+            }
+        });
+        if let Err(e) = check {
+            return Err(ImageError::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                e,
+            )));
+        }
+        let mut bytes_saved = None;
+        return match format {
+            ImageFormat::Gif => {
+                let file = File::create(file_output)?;
+                let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(
+                    file,
+                    options.gif_speed.clamp(1, 30),
+                );
+                encoder.encode(&img, img.width(), img.height(), image::ColorType::Rgba8)
+            }
+            ImageFormat::Jpeg => {
+                let rgb = flatten_alpha(&img, options.jpeg_background);
+                let file = BufWriter::new(File::create(file_output)?);
+                metadata::write_jpeg(file, &rgb, options.jpeg_quality.clamp(1, 100), metadata)
+            }
+            ImageFormat::Png => {
+                let (bytes, saved) = optimize::encode_png(&img, &options, metadata)?;
+                bytes_saved = saved;
+                std::fs::write(file_output, bytes).map_err(ImageError::IoError)
+            }
+            ImageFormat::WebP | ImageFormat::WebPLossy => {
+                let quality = if format == ImageFormat::WebP {
+                    WebPQuality::lossless()
+                } else {
+                    WebPQuality::default()
+                };
+                let file = File::create(file_output)?;
+                WebPEncoder::new_with_quality(file, quality).encode(
+                    &img,
+                    img.width(),
+                    img.height(),
+                    image::ColorType::Rgba8,
+                )
+            }
+            ImageFormat::Bmp => img.save_with_format(file_output, image::ImageFormat::Bmp),
+            ImageFormat::Tiff => img.save_with_format(file_output, image::ImageFormat::Tiff),
+            ImageFormat::Qoi => img.save_with_format(file_output, image::ImageFormat::Qoi),
         }
-        return Err(ImageError::IoError(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Invalid file extension",
-        )));
+        .map(|()| bytes_saved);
     }
 
     Err(ImageError::IoError(std::io::Error::new(
@@ -134,6 +603,91 @@ fn save_image(file_output: std::path::PathBuf, img: RgbaImage) -> image::ImageRe
     )))
 }
 
+///Crea un canale e muove il suo  <i>Sender</i> ad un nuovo thread, il quale si occupa di eseguire <i>save_animation()</i>
+///ed inviare il risultato sul canale.
+pub fn start_thread_save_animation(
+    path: std::path::PathBuf,
+    frames: Vec<RgbaImage>,
+    frame_delay: Duration,
+    format: AnimationFormat,
+    options: EncoderOptions,
+) -> Receiver<Result<SavedFile, ImageError>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(
+            save_animation(path.clone(), frames, frame_delay, format, options)
+                .map(|()| SavedFile::new(&path, None)),
+        );
+    });
+    rx
+}
+
+///Codifica la sequenza di frame come GIF o APNG animata, che si ripete all'infinito.
+///Ogni frame è mostrato per <i>frame_delay</i>. I frame devono avere tutti le stesse dimensioni del primo:
+///quelli diversi (ad esempio perché è cambiata la risoluzione dello schermo durante la registrazione) sono scartati.
+fn save_animation(
+    file_output: std::path::PathBuf,
+    frames: Vec<RgbaImage>,
+    frame_delay: Duration,
+    format: AnimationFormat,
+    options: EncoderOptions,
+) -> image::ImageResult<()> {
+    let (width, height) = match frames.first() {
+        Some(f) => f.dimensions(),
+        None => {
+            return Err(ImageError::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "No frames to save",
+            )))
+        }
+    };
+    let frames: Vec<RgbaImage> = frames
+        .into_iter()
+        .filter(|f| f.dimensions() == (width, height))
+        .collect();
+    let delay_ms = frame_delay.as_millis().clamp(10, u16::MAX as u128) as u32;
+    let file = BufWriter::new(File::create(file_output)?);
+
+    match format {
+        AnimationFormat::Gif => {
+            let mut encoder =
+                image::codecs::gif::GifEncoder::new_with_speed(file, options.gif_speed.clamp(1, 30));
+            encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+            encoder.encode_frames(frames.into_iter().map(|f| {
+                image::Frame::from_parts(f, 0, 0, image::Delay::from_numer_denom_ms(delay_ms, 1))
+            }))
+        }
+        AnimationFormat::Apng => {
+            let to_image_error = metadata::png_to_image_error;
+            let mut encoder = png::Encoder::new(file, width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder
+                .set_animated(frames.len() as u32, 0)
+                .map_err(to_image_error)?;
+            encoder
+                .set_frame_delay(delay_ms as u16, 1000)
+                .map_err(to_image_error)?;
+            let mut writer = encoder.write_header().map_err(to_image_error)?;
+            for f in frames.iter() {
+                writer.write_image_data(f.as_raw()).map_err(to_image_error)?;
+            }
+            writer.finish().map_err(to_image_error)
+        }
+    }
+}
+
+///Rimuove il canale alpha componendo ogni pixel sul colore di sfondo <i>background</i>.
+fn flatten_alpha(img: &RgbaImage, background: [u8; 3]) -> RgbImage {
+    RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let p = img.get_pixel(x, y).0;
+        let alpha = p[3] as u32;
+        image::Rgb([0, 1, 2].map(|c| {
+            ((p[c] as u32 * alpha + background[c] as u32 * (255 - alpha) + 127) / 255) as u8
+        }))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
@@ -145,11 +699,159 @@ mod tests {
         assert!(r.recv().is_ok());
     }
 
+    #[test]
+    fn clipboard_service_test() {
+        //il servizio deve rimanere disponibile tra una richiesta e l'altra, anche se la copia fallisce
+        //(ad esempio, in assenza di un display)
+        let img = image::RgbaImage::from_pixel(2, 2, image::Rgba([1, 2, 3, 255]));
+        for _ in 0..2 {
+            let r = crate::image_coding::start_thread_copy_to_clipboard(&img);
+            assert!(r.recv().is_ok());
+        }
+        crate::image_coding::stop_clipboard_service();
+        let r = crate::image_coding::start_thread_copy_to_clipboard(&img);
+        assert!(r.recv().is_ok());
+    }
+
+    #[test]
+    #[ignore = "requires a display server: run with `xvfb-run cargo test -- --ignored`"]
+    fn clipboard_round_trip_test() {
+        let img = image::RgbaImage::from_pixel(2, 2, image::Rgba([1, 2, 3, 255]));
+        let r = crate::image_coding::start_thread_copy_to_clipboard(&img);
+        assert!(r.recv().unwrap().is_ok());
+        //la lettura avviene da un'altra istanza di Clipboard: il contenuto deve essere ancora servito
+        let pasted = crate::image_coding::start_thread_paste_from_clipboard();
+        assert_eq!(pasted.recv().unwrap(), Ok(img));
+    }
+
+    #[test]
+    fn image_data_to_rgba_test() {
+        let bytes = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let img_data = arboard::ImageData {
+            width: 2,
+            height: 1,
+            bytes: std::borrow::Cow::Borrowed(&bytes),
+        };
+        let img = crate::image_coding::image_data_to_rgba(img_data).unwrap();
+        assert_eq!(img.get_pixel(1, 0), &image::Rgba([5, 6, 7, 8]));
+
+        let truncated = arboard::ImageData {
+            width: 4,
+            height: 4,
+            bytes: std::borrow::Cow::Borrowed(&bytes),
+        };
+        assert!(crate::image_coding::image_data_to_rgba(truncated).is_err());
+    }
+
     #[test]
     fn save_test() {
         let img = image::RgbaImage::new(0, 0);
-        let r = crate::image_coding::start_thread_save_image("./test.png".into(), img);
+        let r = crate::image_coding::start_thread_save_image(
+            "./test.png".into(),
+            img,
+            crate::image_coding::ImageFormat::Png,
+            Default::default(),
+            None,
+        );
         assert!(r.recv().is_ok());
         assert!(PathBuf::from("./test.png").exists());
     }
+
+    #[test]
+    fn load_image_test() {
+        let img = image::RgbaImage::from_pixel(3, 2, image::Rgba([10, 20, 30, 255]));
+        let path = PathBuf::from("./test_load.bmp");
+        let r = crate::image_coding::start_thread_save_image(
+            path.clone(),
+            img.clone(),
+            crate::image_coding::ImageFormat::Bmp,
+            Default::default(),
+            None,
+        );
+        assert!(r.recv().unwrap().is_ok());
+        let loaded = crate::image_coding::start_thread_load_image(path.clone())
+            .recv()
+            .unwrap();
+        assert_eq!(loaded, Ok(img));
+        let _ = std::fs::remove_file(path);
+
+        let missing = crate::image_coding::start_thread_load_image("./missing.png".into());
+        assert!(missing.recv().unwrap().is_err());
+    }
+
+    #[test]
+    fn save_all_formats_test() {
+        let img = image::RgbaImage::from_pixel(4, 4, image::Rgba([10, 20, 30, 255]));
+        for f in crate::image_coding::ImageFormat::available_formats() {
+            let path = PathBuf::from(format!("./test_all_formats.{}", f.extension()));
+            let r = crate::image_coding::start_thread_save_image(
+                path.clone(),
+                img.clone(),
+                f,
+                Default::default(),
+                None,
+            );
+            assert!(r.recv().unwrap().is_ok(), "{:?}", f);
+            assert!(path.exists());
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn unknown_extension_test() {
+        assert!(crate::image_coding::ImageFormat::try_from("xyz").is_err());
+        let img = image::RgbaImage::new(1, 1);
+        let r = crate::image_coding::start_thread_save_image(
+            "./test.xyz".into(),
+            img,
+            crate::image_coding::ImageFormat::Png,
+            Default::default(),
+            None,
+        );
+        assert!(r.recv().unwrap().is_err());
+        assert!(!PathBuf::from("./test.xyz").exists());
+
+        //estensione di un formato diverso da quello richiesto
+        let r = crate::image_coding::start_thread_save_image(
+            "./test_mismatch.png".into(),
+            image::RgbaImage::new(1, 1),
+            crate::image_coding::ImageFormat::Jpeg,
+            Default::default(),
+            None,
+        );
+        assert!(r.recv().unwrap().is_err());
+        assert!(!PathBuf::from("./test_mismatch.png").exists());
+    }
+
+    #[test]
+    fn flatten_alpha_test() {
+        let mut img = image::RgbaImage::new(2, 1);
+        img.put_pixel(0, 0, image::Rgba([0, 0, 0, 0]));
+        img.put_pixel(1, 0, image::Rgba([0, 100, 200, 255]));
+        let rgb = crate::image_coding::flatten_alpha(&img, [255, 255, 255]);
+        assert_eq!(rgb.get_pixel(0, 0).0, [255, 255, 255]);
+        assert_eq!(rgb.get_pixel(1, 0).0, [0, 100, 200]);
+    }
+
+    #[test]
+    fn save_animation_test() {
+        let frames = vec![
+            image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255])),
+            image::RgbaImage::from_pixel(4, 4, image::Rgba([0, 255, 0, 255])),
+            image::RgbaImage::from_pixel(2, 2, image::Rgba([0, 0, 255, 255])),
+        ];
+        for f in crate::image_coding::AnimationFormat::available_formats() {
+            let path = PathBuf::from(format!("./test_animation.{}", f.image_format().extension()));
+            let r = crate::image_coding::start_thread_save_animation(
+                path.clone(),
+                frames.clone(),
+                std::time::Duration::from_millis(100),
+                f,
+                Default::default(),
+            );
+            assert!(r.recv().unwrap().is_ok(), "{:?}", f);
+            assert!(path.exists());
+            let _ = std::fs::remove_file(path);
+        }
+    }
 }
@@ -0,0 +1,225 @@
+/* Fase opzionale di ottimizzazione delle dimensioni dei file PNG, eseguita dopo la codifica standard.
+Sono disponibili due modalità (vedi <i>PngOptimization</i>):
+- lossless: l'immagine viene codificata con tutte le combinazioni di livello di compressione e filtro disponibili, e
+  viene tenuto il risultato più piccolo;
+- palette: l'immagine viene ridotta ad al più 256 colori (con dithering Floyd-Steinberg, se i colori originali sono di più)
+  e salvata come PNG indicizzato.
+In entrambi i casi, se il risultato non è più piccolo della codifica standard, viene tenuta quest'ultima.
+ */
+
+use super::metadata::{add_png_metadata, png_to_image_error, write_png};
+use super::{EncoderOptions, PngCompression, PngFilter, PngOptimization};
+use crate::itc::CaptureMetadata;
+use color_quant::NeuQuant;
+use image::{ImageResult, RgbaImage};
+use std::collections::HashMap;
+
+///Numero massimo di colori della palette di un PNG indicizzato a 8 bit.
+const MAX_PALETTE_COLORS: usize = 256;
+///Qualità del campionamento di NeuQuant: da 1 (migliore, più lento) a 30.
+const NEUQUANT_SAMPLE_FACTOR: i32 = 10;
+
+///Codifica l'immagine in formato PNG, applicando l'ottimizzazione indicata in <i>options</i>.<br>
+///Ritorna i byte del file e il numero di byte risparmiati rispetto alla codifica standard
+///(None se l'ottimizzazione è disattivata).
+pub(super) fn encode_png(
+    img: &RgbaImage,
+    options: &EncoderOptions,
+    metadata: Option<&CaptureMetadata>,
+) -> ImageResult<(Vec<u8>, Option<u64>)> {
+    let mut standard = vec![];
+    write_png(&mut standard, img, options, metadata)?;
+
+    let optimized = match options.png_optimization {
+        PngOptimization::Off => return Ok((standard, None)),
+        PngOptimization::Lossless => encode_lossless(img, options, metadata)?,
+        PngOptimization::Palette => encode_palette(img, metadata)?,
+    };
+    if optimized.len() < standard.len() {
+        let saved = (standard.len() - optimized.len()) as u64;
+        Ok((optimized, Some(saved)))
+    } else {
+        Ok((standard, Some(0)))
+    }
+}
+
+///Prova tutte le combinazioni di livello di compressione e filtro e ritorna la codifica più piccola: la compressione
+///massima non produce sempre il file più piccolo, perché il risultato dipende anche dal filtro applicato alle righe.
+fn encode_lossless(
+    img: &RgbaImage,
+    options: &EncoderOptions,
+    metadata: Option<&CaptureMetadata>,
+) -> ImageResult<Vec<u8>> {
+    let mut best: Option<Vec<u8>> = None;
+    let combinations = PngCompression::available_compressions()
+        .into_iter()
+        .flat_map(|c| PngFilter::available_filters().into_iter().map(move |f| (c, f)));
+    for (png_compression, png_filter) in combinations {
+        let candidate_options = EncoderOptions {
+            png_compression,
+            png_filter,
+            ..*options
+        };
+        let mut candidate = vec![];
+        write_png(&mut candidate, img, &candidate_options, metadata)?;
+        let smaller = match &best {
+            Some(b) => candidate.len() < b.len(),
+            None => true,
+        };
+        if smaller {
+            best = Some(candidate);
+        }
+    }
+    Ok(best.unwrap_or_default())
+}
+
+///Riduce l'immagine ad una palette di al più 256 colori e la codifica come PNG indicizzato.
+fn encode_palette(img: &RgbaImage, metadata: Option<&CaptureMetadata>) -> ImageResult<Vec<u8>> {
+    let (palette, indices) = quantize(img);
+
+    let mut buf = vec![];
+    let mut encoder = png::Encoder::new(&mut buf, img.width(), img.height());
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Best);
+    encoder.set_filter(png::FilterType::NoFilter);
+    encoder.set_palette(palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect::<Vec<u8>>());
+    if palette.iter().any(|c| c[3] != 255) {
+        encoder.set_trns(palette.iter().map(|c| c[3]).collect::<Vec<u8>>());
+    }
+    if let Some(md) = metadata {
+        add_png_metadata(&mut encoder, md)?;
+    }
+    let mut writer = encoder.write_header().map_err(png_to_image_error)?;
+    writer.write_image_data(&indices).map_err(png_to_image_error)?;
+    writer.finish().map_err(png_to_image_error)?;
+    Ok(buf)
+}
+
+///Ritorna la palette (colori RGBA) e, per ogni pixel, l'indice del colore corrispondente.<br>
+///Se l'immagine contiene al più 256 colori, la palette è esatta e la conversione è senza perdita; altrimenti la palette
+///viene calcolata con NeuQuant e l'immagine viene approssimata con dithering.
+fn quantize(img: &RgbaImage) -> (Vec<[u8; 4]>, Vec<u8>) {
+    if let Some(exact) = exact_palette(img) {
+        return exact;
+    }
+
+    let nq = NeuQuant::new(NEUQUANT_SAMPLE_FACTOR, MAX_PALETTE_COLORS, img.as_raw());
+    let palette = nq
+        .color_map_rgba()
+        .chunks_exact(4)
+        .map(|c| [c[0], c[1], c[2], c[3]])
+        .collect();
+    let mut dithered = img.clone();
+    image::imageops::dither(&mut dithered, &nq);
+    let indices = image::imageops::index_colors(&dithered, &nq).into_raw();
+    (palette, indices)
+}
+
+///Costruisce la palette esatta dell'immagine, se questa contiene al più 256 colori diversi.
+fn exact_palette(img: &RgbaImage) -> Option<(Vec<[u8; 4]>, Vec<u8>)> {
+    let mut palette: Vec<[u8; 4]> = vec![];
+    let mut lookup: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut indices = Vec::with_capacity((img.width() * img.height()) as usize);
+    for pixel in img.pixels() {
+        let index = match lookup.get(&pixel.0) {
+            Some(i) => *i,
+            None => {
+                if palette.len() == MAX_PALETTE_COLORS {
+                    return None;
+                }
+                let i = palette.len() as u8;
+                palette.push(pixel.0);
+                lookup.insert(pixel.0, i);
+                i
+            }
+        };
+        indices.push(index);
+    }
+    Some((palette, indices))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///Immagine con molti colori diversi, simile ad una sfumatura.
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(64, 64, |x, y| {
+            image::Rgba([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8, 255])
+        })
+    }
+
+    ///Immagine con rumore pseudo-casuale, difficile da comprimere senza perdita.
+    fn noise() -> RgbaImage {
+        let mut seed: u32 = 1;
+        RgbaImage::from_fn(64, 64, |_, _| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let [r, g, b, _] = seed.to_be_bytes();
+            image::Rgba([r, g, b, 255])
+        })
+    }
+
+    fn decode(bytes: &[u8]) -> RgbaImage {
+        image::load_from_memory_with_format(bytes, image::ImageFormat::Png)
+            .unwrap()
+            .to_rgba8()
+    }
+
+    #[test]
+    fn lossless_test() {
+        let img = gradient();
+        let options = EncoderOptions {
+            png_compression: PngCompression::Fast,
+            png_filter: PngFilter::NoFilter,
+            png_optimization: PngOptimization::Lossless,
+            ..Default::default()
+        };
+        let (bytes, saved) = encode_png(&img, &options, None).unwrap();
+        assert!(saved.unwrap() > 0);
+        assert_eq!(decode(&bytes), img);
+
+        //nessuna combinazione di compressione e filtro produce un file più piccolo
+        for png_compression in PngCompression::available_compressions() {
+            for png_filter in PngFilter::available_filters() {
+                let mut buf = vec![];
+                let options = EncoderOptions { png_compression, png_filter, ..options };
+                write_png(&mut buf, &img, &options, None).unwrap();
+                assert!(bytes.len() <= buf.len());
+            }
+        }
+    }
+
+    #[test]
+    fn palette_test() {
+        let img = noise();
+        let options = EncoderOptions {
+            png_optimization: PngOptimization::Palette,
+            ..Default::default()
+        };
+        let (bytes, saved) = encode_png(&img, &options, None).unwrap();
+        assert!(saved.unwrap() > 0);
+        let decoded = decode(&bytes);
+        assert_eq!(decoded.dimensions(), img.dimensions());
+        let colors: std::collections::HashSet<_> = decoded.pixels().map(|p| p.0).collect();
+        assert!(colors.len() <= MAX_PALETTE_COLORS);
+    }
+
+    #[test]
+    fn exact_palette_test() {
+        //pochi colori, con trasparenza: la conversione in palette deve essere senza perdita
+        let img = RgbaImage::from_fn(32, 32, |x, y| {
+            image::Rgba([(x % 4) as u8 * 60, (y % 4) as u8 * 60, 0, if x < 16 { 255 } else { 128 }])
+        });
+        assert!(exact_palette(&img).is_some());
+        assert!(exact_palette(&gradient()).is_none());
+        let bytes = encode_palette(&img, None).unwrap();
+        assert_eq!(decode(&bytes), img);
+    }
+
+    #[test]
+    fn optimization_off_test() {
+        let (_, saved) = encode_png(&gradient(), &EncoderOptions::default(), None).unwrap();
+        assert_eq!(saved, None);
+    }
+}
@@ -0,0 +1,184 @@
+/* Esportazione di un'immagine come testo da incollare in documenti Markdown o pagine HTML.
+Il testo viene copiato nella clipboard tramite il thread che la possiede (vedi <i>start_thread_copy_text_to_clipboard()</i>).
+Se l'immagine è già stata salvata, il link Markdown e il tag <img> fanno riferimento al path del file; altrimenti
+contengono l'immagine stessa, codificata come data URI.
+ */
+
+use super::{optimize, EncoderOptions, ImageFormat};
+use base64::Engine;
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+///Formati testuali in cui può essere esportata un'immagine.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TextExport {
+    ///<i>data:image/png;base64,...</i>
+    DataUri,
+    ///<i>![alt](src)</i>
+    Markdown,
+    ///<i><img src="..." alt="..."></i>
+    Html,
+}
+
+impl From<TextExport> for &'static str {
+    fn from(value: TextExport) -> Self {
+        match value {
+            TextExport::DataUri => "Data URI",
+            TextExport::Markdown => "Markdown",
+            TextExport::Html => "HTML",
+        }
+    }
+}
+
+impl TextExport {
+    pub fn available_exports() -> Vec<TextExport> {
+        vec![TextExport::DataUri, TextExport::Markdown, TextExport::Html]
+    }
+}
+
+///Immagine da esportare.
+pub enum ExportSource {
+    ///Immagine non ancora salvata, codificata come PNG con i parametri indicati.
+    Image(RgbaImage, EncoderOptions),
+    ///File già salvato: il data URI contiene i byte del file, nel suo formato.
+    File(PathBuf),
+}
+
+///Crea un canale e muove il suo  <i>Sender</i> ad un nuovo thread, il quale compone il testo con <i>export_text()</i>,
+///lo copia nella clipboard ed invia l'esito sul canale.
+pub fn start_thread_copy_as_text(
+    export: TextExport,
+    source: ExportSource,
+) -> Receiver<Result<(), &'static str>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let result = export_text(export, &source).and_then(|text| {
+            match super::start_thread_copy_text_to_clipboard(text).recv() {
+                Ok(Ok(())) => Ok(()),
+                _ => Err("Error: impossible to copy the text on the clipboard"),
+            }
+        });
+        let _ = tx.send(result);
+    });
+    rx
+}
+
+///Compone il testo che rappresenta l'immagine nel formato <i>export</i>.
+fn export_text(export: TextExport, source: &ExportSource) -> Result<String, &'static str> {
+    let src = match (export, source) {
+        (TextExport::DataUri, _) | (_, ExportSource::Image(..)) => data_uri(source)?,
+        (_, ExportSource::File(path)) => path.to_str().ok_or("Error: invalid file path")?.to_string(),
+    };
+    let alt = match source {
+        ExportSource::File(path) => path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("screenshot"),
+        ExportSource::Image(..) => "screenshot",
+    };
+    Ok(match export {
+        TextExport::DataUri => src,
+        TextExport::Markdown => markdown_image(alt, &src),
+        TextExport::Html => html_image(alt, &src),
+    })
+}
+
+///Codifica l'immagine in base64, preceduta dal tipo MIME del suo formato.
+fn data_uri(source: &ExportSource) -> Result<String, &'static str> {
+    let (mime, bytes) = match source {
+        ExportSource::Image(img, options) => {
+            let (bytes, _) = optimize::encode_png(img, options, None)
+                .map_err(|_| "Error: unable to encode the image")?;
+            (ImageFormat::Png.mime_type(), bytes)
+        }
+        ExportSource::File(path) => (
+            file_format(path)?.mime_type(),
+            std::fs::read(path).map_err(|_| "Error: unable to read the file")?,
+        ),
+    };
+    Ok(format!(
+        "data:{};base64,{}",
+        mime,
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    ))
+}
+
+fn file_format(path: &Path) -> Result<ImageFormat, &'static str> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|ext| ImageFormat::try_from(ext).ok())
+        .ok_or("Error: unsupported image format")
+}
+
+///Le destinazioni con spazi o parentesi (frequenti nei path) sono racchiuse tra parentesi angolari, come previsto da
+///CommonMark.
+fn markdown_image(alt: &str, src: &str) -> String {
+    let alt = alt.replace('[', "\\[").replace(']', "\\]");
+    if src.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+        let src = src.replace('<', "%3C").replace('>', "%3E");
+        format!("![{}](<{}>)", alt, src)
+    } else {
+        format!("![{}]({})", alt, src)
+    }
+}
+
+fn html_image(alt: &str, src: &str) -> String {
+    format!(
+        "<img src=\"{}\" alt=\"{}\">",
+        escape_html(src),
+        escape_html(alt)
+    )
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_uri_test() {
+        let img = RgbaImage::from_pixel(2, 2, image::Rgba([10, 20, 30, 255]));
+        let uri = export_text(
+            TextExport::DataUri,
+            &ExportSource::Image(img.clone(), EncoderOptions::default()),
+        )
+        .unwrap();
+        let encoded = uri.strip_prefix("data:image/png;base64,").unwrap();
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .unwrap();
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgba8();
+        assert_eq!(decoded, img);
+    }
+
+    #[test]
+    fn saved_file_test() {
+        let path = PathBuf::from("./my shot (1).png");
+        assert_eq!(
+            export_text(TextExport::Markdown, &ExportSource::File(path.clone())).unwrap(),
+            "![my shot (1)](<./my shot (1).png>)"
+        );
+        assert_eq!(
+            export_text(TextExport::Html, &ExportSource::File(path.clone())).unwrap(),
+            "<img src=\"./my shot (1).png\" alt=\"my shot (1)\">"
+        );
+        //il file non esiste
+        assert!(export_text(TextExport::DataUri, &ExportSource::File(path)).is_err());
+    }
+
+    #[test]
+    fn escape_test() {
+        assert_eq!(markdown_image("a]b", "x.png"), "![a\\]b](x.png)");
+        assert_eq!(
+            html_image("\"quoted\"", "a&b.png"),
+            "<img src=\"a&amp;b.png\" alt=\"&quot;quoted&quot;\">"
+        );
+    }
+}
@@ -1,13 +1,22 @@
 /*Definizione di enum usate nelle interfacce di comunicazione tra diversi moduli.*/
 
+use crate::config::{self, ConfigFile};
+use crate::image_coding::{AnimationFormat, DiffSettings, ImageFormat};
+use chrono::{DateTime, FixedOffset, Local};
+use screenshots::DisplayInfo;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
 use std::{env, time::Duration};
 
-#[derive(Debug, PartialEq)]
-#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum ScreenshotDim {
 
     Fullscreen,
     Rectangle,
+    ///Finestra di primo livello (solo X11), scelta secondo le <i>WindowCaptureSettings</i>.
+    Window,
+    ///Regione fissa di uno schermo, memorizzata in un preset (vedi <i>region_presets</i>).
+    Region,
 }
 
 impl Clone for ScreenshotDim {
@@ -15,10 +24,167 @@ impl Clone for ScreenshotDim {
         match self {
             ScreenshotDim::Fullscreen => ScreenshotDim::Fullscreen,
             ScreenshotDim::Rectangle => ScreenshotDim::Rectangle,
+            ScreenshotDim::Window => ScreenshotDim::Window,
+            ScreenshotDim::Region => ScreenshotDim::Region,
         }
     }
 }
 
+impl From<&ScreenshotDim> for &'static str {
+    fn from(value: &ScreenshotDim) -> Self {
+        match value {
+            ScreenshotDim::Fullscreen => "Fullscreen",
+            ScreenshotDim::Rectangle => "Rectangle",
+            ScreenshotDim::Window => "Window",
+            ScreenshotDim::Region => "Region",
+        }
+    }
+}
+
+impl TryFrom<&str> for ScreenshotDim {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "Fullscreen" => Ok(ScreenshotDim::Fullscreen),
+            "Rectangle" => Ok(ScreenshotDim::Rectangle),
+            "Window" => Ok(ScreenshotDim::Window),
+            "Region" => Ok(ScreenshotDim::Region),
+            _ => Err(format!("Non recognized capture mode: {}", s)),
+        }
+    }
+}
+
+///Finestra da catturare in modalità <i>ScreenshotDim::Window</i>.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum WindowTarget {
+    ///La finestra viene scelta dall'utente con un click, al momento della cattura.
+    Pick,
+    ///Id X11 della finestra.
+    Id(u32),
+}
+
+///Parametri della cattura di una finestra.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct WindowCaptureSettings {
+    pub target: WindowTarget,
+    ///Se true, viene catturata anche la cornice disegnata dal window manager (barra del titolo e bordi).
+    pub decorations: bool,
+}
+
+impl Default for WindowCaptureSettings {
+    fn default() -> Self {
+        Self {
+            target: WindowTarget::Pick,
+            decorations: true,
+        }
+    }
+}
+
+///Informazioni sulla provenienza di uno screenshot, scritte come metadati nei file salvati.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CaptureMetadata {
+    pub timestamp: DateTime<FixedOffset>,
+    pub screen_id: u32,
+    ///Risoluzione dello schermo, come riportata dal sistema.
+    pub screen_size: (u32, u32),
+    pub scale_factor: f32,
+    pub mode: ScreenshotDim,
+    ///Rettangolo ritagliato dallo screenshot dello schermo (x, y, larghezza, altezza), in pixel fisici.
+    pub crop: Option<(u32, u32, u32, u32)>,
+    ///Rettangolo cambiato rispetto allo scatto precedente (x, y, larghezza, altezza), in pixel dell'immagine salvata:
+    ///presente solo nelle catture salvate perché il contenuto dello schermo è cambiato (vedi <i>TimelapseSettings</i>).
+    pub changed_area: Option<(u32, u32, u32, u32)>,
+    pub app_version: String,
+}
+
+impl CaptureMetadata {
+    ///Crea i metadati di una cattura eseguita in questo istante sullo schermo descritto da <i>display</i>.
+    pub fn new(
+        display: &DisplayInfo,
+        mode: ScreenshotDim,
+        crop: Option<(u32, u32, u32, u32)>,
+    ) -> Self {
+        Self {
+            timestamp: Local::now().fixed_offset(),
+            screen_id: display.id,
+            screen_size: (display.width, display.height),
+            scale_factor: display.scale_factor,
+            mode,
+            crop,
+            changed_area: None,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    ///Aggiorna il ritaglio dopo che l'immagine catturata è stata ritagliata ulteriormente (ad esempio nell'editor) sul
+    ///rettangolo <i>cut</i> (x, y, larghezza, altezza), espresso in pixel dell'immagine catturata.
+    pub fn apply_cut(&mut self, (x, y, width, height): (u32, u32, u32, u32)) {
+        let (crop_x, crop_y) = self.crop.map_or((0, 0), |(x, y, ..)| (x, y));
+        self.crop = Some((crop_x + x, crop_y + y, width, height));
+    }
+
+    ///Risoluzione di stampa corrispondente al fattore di scala dello schermo, assumendo 96 DPI per il fattore 1.
+    pub fn dpi(&self) -> u32 {
+        (96.0 * self.scale_factor).round().max(1.0) as u32
+    }
+}
+
+///Parametri dell'ultimo screenshot, per poterlo ripetere senza selezionare di nuovo l'area.
+///Vengono salvati in un file di configurazione, così da poter ripetere la cattura anche dopo un riavvio.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct LastCapture {
+    pub mode: ScreenshotDim,
+    ///Id dello schermo catturato (0 per il desktop virtuale).
+    pub screen_id: u32,
+    ///Area ritagliata dallo screenshot dello schermo (x, y, larghezza, altezza), in pixel fisici.
+    pub area: Option<(u32, u32, u32, u32)>,
+    ///Secondi di delay.
+    pub delay: f64,
+    ///Finestra catturata, se <i>mode</i> è <i>ScreenshotDim::Window</i>.
+    pub window: Option<WindowCaptureSettings>,
+}
+
+impl ConfigFile for LastCapture {
+    const FILE_NAME: &'static str = "last_capture.json";
+    const LEGACY_FILE_NAME: &'static str = ".config_last_capture.json";
+}
+
+impl LastCapture {
+    ///Legge l'ultima cattura dal file di configurazione. Ritorna None se non è ancora stata fatta nessuna cattura
+    ///o se il file non è valido: in quest'ultimo caso scrive un messaggio nello stato di errore globale <i>alert</i>.
+    pub fn load(alert: &RefCell<Option<String>>) -> Option<Self> {
+        config::load().unwrap_or_else(|e| {
+            alert.borrow_mut().replace(format!("Last capture: {}", e));
+            None
+        })
+    }
+
+    pub fn start_thread_serialize(&self) {
+        config::start_thread_save(self.clone());
+    }
+
+    ///Descrizione della cattura, mostrata all'utente.
+    pub fn description(&self) -> String {
+        let mut ret = <&str>::from(&self.mode).to_string();
+        match (self.window, self.area) {
+            (Some(w), _) => match w.target {
+                WindowTarget::Pick => ret.push_str(" picked by clicking"),
+                WindowTarget::Id(id) => ret.push_str(&format!(" {:#x}", id)),
+            },
+            (None, Some((x, y, w, h))) => {
+                ret.push_str(&format!(" {}x{}+{}+{} of screen {}", w, h, x, y, self.screen_id))
+            }
+            (None, None) if self.screen_id == 0 => ret.push_str(" of all screens"),
+            (None, None) => ret.push_str(&format!(" of screen {}", self.screen_id)),
+        }
+        if self.delay > 0.0 {
+            ret.push_str(&format!(", {:.1} s delay", self.delay));
+        }
+        ret
+    }
+}
+
 pub enum SettingsEvent {
     Saved,
     Aborted,
@@ -32,6 +198,54 @@ pub struct Delay {
     pub scalar: f64,
 }
 
+///Parametri di una registrazione animata dello schermo.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecordingSettings {
+    ///Frame catturati al secondo.
+    pub fps: u32,
+    ///Durata massima della registrazione, in secondi: può essere interrotta prima dall'utente.
+    pub duration: f64,
+    pub format: AnimationFormat,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            fps: 10,
+            duration: 10.0,
+            format: AnimationFormat::Gif,
+        }
+    }
+}
+
+///Parametri di una timelapse: catture periodiche dello schermo (o di un'area), salvate direttamente su file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimelapseSettings {
+    ///Secondi tra due scatti consecutivi.
+    pub interval: f64,
+    ///Numero massimo di scatti salvati: gli scatti scartati non vengono contati.
+    pub max_shots: u32,
+    ///Se true, uno scatto identico al precedente non viene salvato.
+    pub skip_identical: bool,
+    ///Se != None, la timelapse sorveglia lo schermo: uno scatto viene salvato solo se, confrontato con l'ultimo scatto
+    ///salvato secondo questi parametri, risulta cambiato (il primo scatto viene sempre salvato).
+    ///In tal caso <i>skip_identical</i> è ignorato.
+    pub watch: Option<DiffSettings>,
+    pub format: ImageFormat,
+}
+
+impl Default for TimelapseSettings {
+    fn default() -> Self {
+        Self {
+            interval: 10.0,
+            max_shots: 100,
+            skip_identical: true,
+            watch: None,
+            format: ImageFormat::Png,
+        }
+    }
+}
+
 ///Secondi
 const DELAY_ANIMATIONS_WINDOWS: f32 = 0.25;
 ///Secondi
@@ -51,3 +265,55 @@ pub fn get_animations_delay() -> Duration {
         _ => Duration::from_secs(1),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_capture_test() {
+        let last = LastCapture {
+            mode: ScreenshotDim::Rectangle,
+            screen_id: 2,
+            area: Some((10, 20, 300, 200)),
+            delay: 1.5,
+            window: None,
+        };
+        let json = serde_json::to_string(&last).unwrap();
+        assert_eq!(serde_json::from_str::<LastCapture>(&json).unwrap(), last);
+        assert_eq!(
+            last.description(),
+            "Rectangle 300x200+10+20 of screen 2, 1.5 s delay"
+        );
+
+        let window = LastCapture {
+            mode: ScreenshotDim::Window,
+            area: None,
+            delay: 0.0,
+            window: Some(WindowCaptureSettings::default()),
+            ..last
+        };
+        assert_eq!(window.description(), "Window picked by clicking");
+    }
+
+    #[test]
+    fn apply_cut_test() {
+        let display = DisplayInfo {
+            id: 1,
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            rotation: 0.0,
+            scale_factor: 1.0,
+            is_primary: true,
+        };
+        let mut md = CaptureMetadata::new(&display, ScreenshotDim::Fullscreen, None);
+        md.apply_cut((100, 50, 300, 200));
+        assert_eq!(md.crop, Some((100, 50, 300, 200)));
+        //un ritaglio successivo è relativo all'immagine già ritagliata
+        let mut md = CaptureMetadata::new(&display, ScreenshotDim::Rectangle, Some((10, 20, 400, 300)));
+        md.apply_cut((5, 5, 100, 100));
+        assert_eq!(md.crop, Some((15, 25, 100, 100)));
+    }
+}
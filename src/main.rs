@@ -1,9 +1,17 @@
+mod cli;
+mod config;
 mod gui;
 mod hotkeys;
 mod image_coding;
 mod itc;
+mod region_presets;
 mod screens_manager;
+mod window_capture;
 
 fn main() {
-    gui::launch_gui();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli::run(&args) {
+        cli::CliOutcome::Exit(code) => std::process::exit(code),
+        cli::CliOutcome::LaunchGui(open_file) => gui::launch_gui(open_file),
+    }
 }
@@ -0,0 +1,236 @@
+/* Modulo per la gestione dei preset di regioni fisse dello schermo.
+Un preset memorizza un rettangolo (in pixel fisici) di uno schermo, identificato dal suo id: la cattura di un preset
+non richiede di selezionare l'area con RectSelection, e avviene anche se lo schermo non è quello selezionato.
+L'id 0 indica il desktop virtuale composto da tutti gli schermi (vedi <i>screens_manager::virtual_desktop_infos()</i>).
+
+I preset occupano <i>MAX_REGION_PRESETS</i> posizioni fisse, a ciascuna delle quali corrisponde una hotkey
+(<i>HotkeyName::RegionPreset</i>): la posizione di un preset non cambia quando un altro preset viene eliminato, per cui
+le hotkeys restano associate allo stesso preset. I preset sono salvati in un file di configurazione in formato json,
+come array in cui le posizioni libere sono <i>null</i>.
+ */
+
+use crate::config::{self, ConfigFile};
+use eframe::emath::{pos2, Rect};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+///Numero massimo di preset, pari al numero di hotkeys ad essi dedicate.
+pub const MAX_REGION_PRESETS: usize = 5;
+
+///Regione fissa di uno schermo, catturabile senza mostrare la selezione dell'area.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionPreset {
+    pub name: String,
+    ///Id dello schermo (<i>DisplayInfo::id</i>), oppure 0 per il desktop virtuale.
+    pub screen_id: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RegionPreset {
+    ///Rettangolo della regione, in coordinate dello screenshot dello schermo. Le coordinate sono calcolate in f32,
+    ///per cui un preset che supera l'intervallo di u32 non va in overflow (e non sarà contenuto nello schermo).
+    pub fn rect(&self) -> Rect {
+        let (x, y) = (self.x as f32, self.y as f32);
+        Rect::from_min_max(
+            pos2(x, y),
+            pos2(x + self.width as f32, y + self.height as f32),
+        )
+    }
+
+    ///Testo con cui il preset viene mostrato all'utente.
+    pub fn label(&self) -> String {
+        format!(
+            "{} ({}x{}+{}+{})",
+            self.name, self.width, self.height, self.x, self.y
+        )
+    }
+}
+
+///Elenco dei preset salvati, condiviso tra la gui e la gestione delle hotkeys.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RegionPresets {
+    ///Preset nelle posizioni occupate, al più <i>MAX_REGION_PRESETS</i>.
+    presets: Vec<Option<RegionPreset>>,
+    ///Ultima area selezionata con RectSelection, proposta per la creazione di un nuovo preset. Non viene salvata.
+    #[serde(skip)]
+    last_selection: Option<RegionPreset>,
+}
+
+impl ConfigFile for RegionPresets {
+    const FILE_NAME: &'static str = "regions.json";
+    const LEGACY_FILE_NAME: &'static str = ".config_regions.json";
+    ///Nella versione 1 i preset erano memorizzati senza posizioni libere: l'array viene letto così com'è, per cui
+    ///ciascun preset mantiene la posizione (e la hotkey) che aveva.
+    const VERSION: u32 = 2;
+}
+
+impl RegionPresets {
+    ///Legge i preset dal file di configurazione; se il file non esiste o non è valido, l'elenco è vuoto.<br>
+    ///Se il file esiste ma non è valido, scrive un messaggio nello stato di errore globale <i>alert</i>.
+    pub fn new(alert: &RefCell<Option<String>>) -> Self {
+        let mut ret: Self = config::load()
+            .unwrap_or_else(|e| {
+                alert.borrow_mut().replace(format!("Region presets: {}", e));
+                None
+            })
+            .unwrap_or_default();
+        ret.presets.truncate(MAX_REGION_PRESETS);
+        ret
+    }
+
+    pub fn start_thread_serialize(&self) {
+        config::start_thread_save(self.clone());
+    }
+
+    ///Preset nella posizione <i>index</i>, se occupata.
+    pub fn get(&self, index: usize) -> Option<&RegionPreset> {
+        self.presets.get(index).and_then(Option::as_ref)
+    }
+
+    ///Preset salvati, assieme alla loro posizione.
+    pub fn list(&self) -> impl Iterator<Item = (usize, &RegionPreset)> {
+        self.presets
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.as_ref().map(|p| (i, p)))
+    }
+
+    ///Aggiunge un preset nella prima posizione libera, dopo aver controllato che:
+    ///- il nome non sia vuoto e non sia già usato da un altro preset;
+    ///- la regione non sia vuota e non superi l'intervallo delle coordinate;
+    ///- non sia già stato raggiunto il numero massimo di preset.
+    ///
+    ///Ritorna la posizione in cui il preset è stato inserito.
+    pub fn add(&mut self, preset: RegionPreset) -> Result<usize, &'static str> {
+        if preset.name.trim().is_empty() {
+            return Err("The preset needs a name");
+        }
+        if self.list().any(|(_, p)| p.name == preset.name) {
+            return Err("A preset with this name already exists");
+        }
+        if preset.width == 0 || preset.height == 0 {
+            return Err("The region is empty");
+        }
+        if preset.x.checked_add(preset.width).is_none()
+            || preset.y.checked_add(preset.height).is_none()
+        {
+            return Err("The region is out of range");
+        }
+        match self.presets.iter().position(Option::is_none) {
+            Some(i) => {
+                self.presets[i] = Some(preset);
+                Ok(i)
+            }
+            None if self.presets.len() < MAX_REGION_PRESETS => {
+                self.presets.push(Some(preset));
+                Ok(self.presets.len() - 1)
+            }
+            None => Err("Too many presets: delete one first"),
+        }
+    }
+
+    ///Elimina il preset nella posizione <i>index</i>, lasciando invariata la posizione degli altri preset.
+    pub fn remove(&mut self, index: usize) {
+        if let Some(p) = self.presets.get_mut(index) {
+            *p = None;
+        }
+        while let Some(None) = self.presets.last() {
+            self.presets.pop();
+        }
+    }
+
+    pub fn get_last_selection(&self) -> Option<&RegionPreset> {
+        self.last_selection.as_ref()
+    }
+
+    ///Memorizza l'area selezionata con RectSelection sullo schermo <i>screen_id</i>, come (x, y, larghezza, altezza).
+    pub fn set_last_selection(&mut self, screen_id: u32, (x, y, width, height): (u32, u32, u32, u32)) {
+        self.last_selection = Some(RegionPreset {
+            name: String::new(),
+            screen_id,
+            x,
+            y,
+            width,
+            height,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(name: &str) -> RegionPreset {
+        RegionPreset {
+            name: name.to_string(),
+            screen_id: 1,
+            x: 10,
+            y: 20,
+            width: 300,
+            height: 200,
+        }
+    }
+
+    #[test]
+    fn add_test() {
+        let mut presets = RegionPresets::default();
+        assert!(presets.add(preset("dashboard")).is_ok());
+        assert!(presets.add(preset("dashboard")).is_err());
+        assert!(presets.add(preset(" ")).is_err());
+        assert!(presets.add(RegionPreset { width: 0, ..preset("empty") }).is_err());
+        for i in 1..MAX_REGION_PRESETS {
+            assert!(presets.add(preset(&i.to_string())).is_ok());
+        }
+        assert!(presets.add(preset("one more")).is_err());
+        assert!(presets.add(RegionPreset { x: u32::MAX, ..preset("overflow") }).is_err());
+
+        //gli altri preset mantengono la loro posizione, e quella liberata viene riusata
+        presets.remove(0);
+        assert_eq!(presets.list().count(), MAX_REGION_PRESETS - 1);
+        assert!(presets.get(0).is_none());
+        assert_eq!(presets.get(1).unwrap().name, "1");
+        assert_eq!(presets.add(preset("new")), Ok(0));
+        presets.remove(MAX_REGION_PRESETS - 1);
+        presets.remove(MAX_REGION_PRESETS - 2);
+        assert_eq!(presets.presets.len(), MAX_REGION_PRESETS - 2);
+        assert_eq!(presets.add(preset("last")), Ok(MAX_REGION_PRESETS - 2));
+    }
+
+    #[test]
+    fn rect_test() {
+        let p = RegionPreset {
+            x: u32::MAX,
+            y: u32::MAX - 1,
+            ..preset("far")
+        };
+        let rect = p.rect();
+        assert!(rect.min.x <= rect.max.x && rect.min.y <= rect.max.y);
+        assert!(rect.max.x >= u32::MAX as f32);
+    }
+
+    #[test]
+    fn serialize_test() {
+        let mut presets = RegionPresets::default();
+        presets.add(preset("dashboard")).unwrap();
+        presets.set_last_selection(2, (0, 0, 10, 10));
+        presets.add(preset("second")).unwrap();
+        presets.remove(0);
+        let json = config::to_json(&presets).unwrap();
+        let read: RegionPresets = config::from_json(&json).unwrap();
+        assert_eq!(read.list().collect::<Vec<_>>(), presets.list().collect::<Vec<_>>());
+        assert_eq!(read.get(1).unwrap().name, "second");
+        assert!(read.get_last_selection().is_none());
+        assert_eq!(
+            read.get(1).unwrap().rect(),
+            Rect::from_min_max(pos2(10.0, 20.0), pos2(310.0, 220.0))
+        );
+
+        //i file della versione 1 non hanno posizioni libere
+        let v1 = r#"{"version":1,"presets":[{"name":"a","screen_id":1,"x":0,"y":0,"width":5,"height":5}]}"#;
+        let read: RegionPresets = config::from_json(v1).unwrap();
+        assert_eq!(read.get(0).unwrap().name, "a");
+    }
+}
@@ -0,0 +1,32 @@
+/* Astrazione del sistema con cui vengono rilevati e catturati gli schermi.
+ScreensManager non dipende direttamente dal crate screenshots, ma da un oggetto che implementa CaptureBackend: questo
+permette di sostituire l'implementazione reale con una simulata (vedi il modulo mock), per verificare la logica di
+ScreensManager (caricamento delle icone, mantenimento della selezione, fallback sullo schermo primario) senza un display.
+ */
+
+use image::RgbaImage;
+use screenshots::{DisplayInfo, Screen};
+
+///Operazioni sugli schermi necessarie a <i>ScreensManager</i>.<br>
+///I metodi vengono richiamati da thread diversi, anche in parallelo: gli errori sono descritti da una stringa, che viene
+///scritta su stderr, mentre all'utente viene mostrato un messaggio generico.
+pub trait CaptureBackend: Send + Sync {
+    ///Elenca gli schermi collegati.
+    fn displays(&self) -> Result<Vec<DisplayInfo>, String>;
+
+    ///Esegue uno screenshot dello schermo descritto da <i>display</i>, in pixel fisici.
+    fn capture(&self, display: &DisplayInfo) -> Result<RgbaImage, String>;
+}
+
+///Implementazione di default, basata sul crate screenshots.
+pub struct ScreenshotsBackend;
+
+impl CaptureBackend for ScreenshotsBackend {
+    fn displays(&self) -> Result<Vec<DisplayInfo>, String> {
+        DisplayInfo::all().map_err(|e| format!("{:?}", e))
+    }
+
+    fn capture(&self, display: &DisplayInfo) -> Result<RgbaImage, String> {
+        Screen::new(display).capture().map_err(|e| format!("{:?}", e))
+    }
+}
@@ -0,0 +1,94 @@
+/* Backend simulato, usato nei test di ScreensManager.
+Gli schermi sono configurabili (e modificabili durante il test, per simulare il collegamento e lo scollegamento di un
+monitor) e ogni screenshot è un'immagine di un solo colore, ricavato dall'id dello schermo, con le dimensioni dello
+schermo moltiplicate per il fattore di scala. Per simulare un cambiamento del contenuto dello schermo, è possibile
+disegnare un rettangolo bianco sugli screenshot (vedi <i>set_spot()</i>).
+ */
+
+use super::CaptureBackend;
+use image::{imageops, Rgba, RgbaImage};
+use screenshots::DisplayInfo;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[derive(Default)]
+pub struct MockBackend {
+    displays: Mutex<Vec<DisplayInfo>>,
+    ///Se true, tutte le catture falliscono.
+    fail_captures: AtomicBool,
+    ///Numero di catture eseguite.
+    captures: AtomicUsize,
+    ///Rettangolo (x, y, larghezza, altezza) disegnato in bianco su tutti gli screenshot.
+    spot: Mutex<Option<(u32, u32, u32, u32)>>,
+}
+
+impl MockBackend {
+    pub fn new(displays: Vec<DisplayInfo>) -> Self {
+        Self {
+            displays: Mutex::new(displays),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_displays(&self, displays: Vec<DisplayInfo>) {
+        *self.displays.lock().unwrap() = displays;
+    }
+
+    pub fn set_fail_captures(&self, fail: bool) {
+        self.fail_captures.store(fail, Ordering::Relaxed);
+    }
+
+    pub fn set_spot(&self, spot: Option<(u32, u32, u32, u32)>) {
+        *self.spot.lock().unwrap() = spot;
+    }
+
+    pub fn captures(&self) -> usize {
+        self.captures.load(Ordering::Relaxed)
+    }
+
+    ///Colore degli screenshot dello schermo con id <i>id</i>.
+    pub fn color(id: u32) -> Rgba<u8> {
+        Rgba([id as u8, 0, 255 - id as u8, 255])
+    }
+}
+
+///Descrizione di uno schermo simulato, di fattore di scala 1, primario se ha id 1.
+pub fn display(id: u32, x: i32, y: i32, width: u32, height: u32) -> DisplayInfo {
+    DisplayInfo {
+        id,
+        x,
+        y,
+        width,
+        height,
+        rotation: 0.0,
+        scale_factor: 1.0,
+        is_primary: id == 1,
+    }
+}
+
+impl CaptureBackend for MockBackend {
+    fn displays(&self) -> Result<Vec<DisplayInfo>, String> {
+        Ok(self.displays.lock().unwrap().clone())
+    }
+
+    fn capture(&self, display: &DisplayInfo) -> Result<RgbaImage, String> {
+        self.captures.fetch_add(1, Ordering::Relaxed);
+        if self.fail_captures.load(Ordering::Relaxed) {
+            return Err("capture failed".to_string());
+        }
+        if !self.displays.lock().unwrap().iter().any(|di| di.id == display.id) {
+            return Err(format!("display {} not connected", display.id));
+        }
+        let scaled = |v: u32| (v as f32 * display.scale_factor).round() as u32;
+        let mut img = RgbaImage::from_pixel(
+            scaled(display.width),
+            scaled(display.height),
+            Self::color(display.id),
+        );
+        if let Some((x, y, w, h)) = *self.spot.lock().unwrap() {
+            let white = RgbaImage::from_pixel(w, h, Rgba([255, 255, 255, 255]));
+            imageops::replace(&mut img, &white, x as i64, y as i64);
+        }
+        Ok(img)
+    }
+}
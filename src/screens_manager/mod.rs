@@ -1,15 +1,177 @@
 /* Modulo per la gestione di tutti gli schermi a disposizione, inclusa la possibilità di eseguire screenshots.
 Mantiene memorizzata una lista contenente, per ogni schermo disponibile, le informazioni principali (id, risoluzione) e uno screenshot fullscreen, utilizzato come icona per rendere riconoscibile lo schermo all'utente.
-L'aggiornamento della lista avviene su richiesta, quando viene richiamato <i>update_available_screens()</i>.
+Le icone vengono conservate in una cache indicizzata dall'id dello schermo: uno schermo viene catturato di nuovo solo se
+compare per la prima volta, se ne cambia la risoluzione o se l'utente chiede esplicitamente di aggiornare la lista (vedi
+<i>refresh_screens()</i>), per mostrare il contenuto attuale degli schermi.
+L'aggiornamento della lista avviene su richiesta, quando viene richiamato <i>update_available_screens()</i>, oppure
+automaticamente quando un thread watcher (vedi <i>start_thread_watch_screens()</i>) rileva che uno schermo è stato collegato,
+scollegato o riconfigurato.
 
 Per praticità, il modulo mette a disposizione la possibilità di memorizzare qual'è lo schermo selezionato dall'utente, su cui saranno eseguite le richieste di screenshot.
+In alternativa ad un singolo schermo, è possibile selezionare tutti gli schermi: gli screenshot vengono allora eseguiti in parallelo
+su ogni schermo e composti in un'unica immagine del desktop virtuale (vedi <i>compose_virtual_desktop()</i>).
+Le regioni fisse (preset) e le catture ripetute vengono invece eseguite sullo schermo indicato, senza modificare la selezione.
+Sullo schermo selezionato possono essere eseguite anche catture periodiche: registrazioni animate (vedi
+<i>start_thread_recording()</i>) e timelapse, in cui ogni scatto viene salvato appena catturato (vedi
+<i>start_thread_timelapse()</i>).
+
+La rilevazione e la cattura degli schermi sono delegate ad un <i>CaptureBackend</i> (di default, il crate screenshots).
 */
 
+mod backend;
+#[cfg(test)]
+mod mock;
+
+pub use backend::{CaptureBackend, ScreenshotsBackend};
+
+use crate::image_coding::compare_images;
+use crate::itc::{CaptureMetadata, ScreenshotDim, TimelapseSettings};
+use eframe::egui::Context;
+use eframe::emath::Rect;
 use image::{imageops::FilterType, RgbaImage};
-use screenshots::{DisplayInfo, Screen};
+use screenshots::DisplayInfo;
+use std::collections::HashMap;
 use std::io::Write;
+use std::path::PathBuf;
 use std::sync::mpsc::{channel, Receiver};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+///Memoria massima occupata dai frame di una registrazione (vedi <i>ScreensManager::start_thread_recording()</i>).
+pub const MAX_RECORDING_BYTES: usize = 1 << 30;
+
+///Risultato di una registrazione: i frame catturati, la durata di ciascuno di essi e se la registrazione è stata
+///interrotta per il raggiungimento di <i>MAX_RECORDING_BYTES</i>.
+pub type RecordingResult = Result<(Vec<RgbaImage>, Duration, bool), &'static str>;
+
+///Differenze tra due rilevazioni successive degli schermi, notificate dal watcher.
+#[derive(Debug, Default)]
+pub struct ScreensChange {
+    pub added: Vec<DisplayInfo>,
+    pub removed: Vec<DisplayInfo>,
+    ///Schermi ancora collegati la cui posizione, risoluzione, rotazione o fattore di scala è cambiata (o che sono
+    ///diventati primari).
+    pub changed: Vec<DisplayInfo>,
+    ///True se lo schermo selezionato è stato scollegato e al suo posto è stato selezionato lo schermo primario.
+    pub selection_lost: bool,
+}
+
+impl ScreensChange {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+///Eventi inviati dal thread che esegue una timelapse (vedi <i>ScreensManager::start_thread_timelapse()</i>).
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimelapseEvent {
+    ///Lo scatto è stato salvato nel file indicato.
+    Saved(PathBuf),
+    ///Lo scatto è identico al precedente (o non è cambiato abbastanza, se la timelapse sorveglia lo schermo) e non è
+    ///stato salvato.
+    Skipped,
+    ///La timelapse è terminata, perché è stato raggiunto il numero massimo di scatti o perché è stata interrotta.
+    Finished,
+    ///La timelapse è stata interrotta da un errore di cattura o di salvataggio.
+    Failed(String),
+}
+
+///Coordina un thread che esegue degli scatti periodici con la gui, che deve nascondere la propria finestra durante
+///ciascuno scatto perché non compaia nelle immagini.<br>
+///Il thread chiede di nascondere la finestra con <i>self::hide_window()</i>, che attende la conferma della gui, e la
+///fa mostrare di nuovo con <i>self::show_window()</i>; la gui legge le richieste con <i>self::window_visibility()</i>.
+#[derive(Debug, Default)]
+pub struct ShotGate {
+    ///Richiesta del thread: true se la finestra deve essere nascosta.
+    hide: AtomicBool,
+    ///Stato applicato dalla gui: true se la finestra è stata nascosta.
+    hidden: AtomicBool,
+}
+
+impl ShotGate {
+    ///Tempo massimo di attesa della conferma della gui, dopo il quale lo scatto viene eseguito comunque.
+    const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+    ///Chiede alla gui di nascondere la finestra e attende che la richiesta sia stata applicata (al più
+    ///<i>Self::ACK_TIMEOUT</i>, o fino all'impostazione di <i>stop</i>), più il tempo necessario al sistema operativo per
+    ///completare l'animazione di chiusura (vedi <i>itc::get_animations_delay()</i>).
+    pub fn hide_window(&self, stop: &AtomicBool) {
+        self.hide.store(true, Ordering::Relaxed);
+        let start = Instant::now();
+        while !self.hidden.load(Ordering::Relaxed)
+            && !stop.load(Ordering::Relaxed)
+            && start.elapsed() < Self::ACK_TIMEOUT
+        {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        std::thread::sleep(crate::itc::get_animations_delay());
+    }
+
+    ///Chiede alla gui di mostrare di nuovo la finestra.
+    pub fn show_window(&self) {
+        self.hide.store(false, Ordering::Relaxed);
+    }
+
+    ///Ritorna la visibilità che la finestra deve assumere, se è cambiata dall'ultima chiamata; altrimenti None.<br>
+    ///La gui deve applicarla subito con <i>eframe::Frame::set_visible()</i>: la chiamata vale come conferma.
+    pub fn window_visibility(&self) -> Option<bool> {
+        let hide = self.hide.load(Ordering::Relaxed);
+        if self.hidden.swap(hide, Ordering::Relaxed) != hide {
+            Some(!hide)
+        } else {
+            None
+        }
+    }
+}
+
+///Area dello schermo da mascherare nei frame di una registrazione: la finestra di stato della gui, che resta visibile
+///durante la registrazione per mostrare il tempo trascorso e il bottone "Stop".<br>
+///La gui aggiorna il rettangolo ad ogni frame con <i>self::set()</i>, così da seguire gli spostamenti della finestra;
+///il thread che registra lo legge prima di ogni cattura e riempie l'area di nero.
+#[derive(Debug, Default)]
+pub struct ExcludedArea {
+    ///Rettangolo in pixel fisici, in coordinate dello screenshot dello schermo registrato (prima del ritaglio).
+    ///None finché la gui non lo ha comunicato.
+    rect: Mutex<Option<Rect>>,
+}
+
+impl ExcludedArea {
+    ///Tempo massimo di attesa del primo rettangolo, dopo il quale la registrazione inizia comunque (ad esempio se il
+    ///sistema non comunica la posizione della finestra).
+    const WAIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+    pub fn set(&self, rect: Rect) {
+        *self.rect.lock().unwrap() = Some(rect);
+    }
+
+    fn get(&self) -> Option<Rect> {
+        *self.rect.lock().unwrap()
+    }
+
+    ///Attende che la gui abbia comunicato il rettangolo, al più <i>Self::WAIT_TIMEOUT</i> o fino all'impostazione di
+    ///<i>stop</i>.
+    fn wait(&self, stop: &AtomicBool) {
+        let start = Instant::now();
+        while self.get().is_none()
+            && !stop.load(Ordering::Relaxed)
+            && start.elapsed() < Self::WAIT_TIMEOUT
+        {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+///Stato dell'icona di uno schermo.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScreenIcon {
+    ///La cattura dello schermo è in corso.
+    Loading,
+    Ready(RgbaImage),
+    ///La cattura dello schermo è fallita: al posto dell'icona viene mostrato un segnaposto con il messaggio.
+    Failed(&'static str),
+}
 
 pub struct ScreensManager {
     ///Lista di schermi disponibili e relative icone.
@@ -20,34 +182,50 @@ pub struct ScreensManager {
     ///la lunghezza della lista) mentre il thread della gui accede alla lista per poterla mostrare, permettendo anche ad un
     ///ulteriore thread di accedere alla lista per poter eseguire uno screenshot (vedi <i>self:: start_thread_fullscreen_screenshot()</i>).
     ///
-    ///Le immagini associate agli oggetti Screen sono intese come icone, utili per il riconoscimento dello schermo da parte
+    ///Le icone associate alle informazioni degli schermi utili per il riconoscimento dello schermo da parte
     ///dell'utente. Sono incapsulate in Mutex per permettere la parallelizzazione dell'operazione di creazione delle icone di
     ///tutti gli schermi collegati (utile perché, in quanto operazioni con le immagini,si tratta di computazione onerosa, ma il modulo è disegnato per
     ///essere scalabile nel numero di schermi).
-    screens: RwLock<Vec<(Screen, Mutex<Option<RgbaImage>>)>>, 
+    screens: RwLock<Vec<(DisplayInfo, Mutex<ScreenIcon>)>>,
+    ///Ultima icona creata per ciascuno schermo, con le informazioni dello schermo al momento della cattura.
+    icon_cache: Mutex<HashMap<u32, (DisplayInfo, RgbaImage)>>,
     ///Indice che fa riferimento al vettore <i>self::screens</i>
     ///Necessario RwLock al posto di Mutex per evitare che un thread che esegue uno screenshot venga bloccato se
     ///contemporaneamente la gui mostra la combo box per la selezione degli schermi.
     curr_screen_index: RwLock<usize>,
+    ///Se true, gli screenshot vengono eseguiti su tutti gli schermi, ignorando <i>self::curr_screen_index</i>.
+    all_screens: AtomicBool,
     ///Larghezza delle icone che verranno prodotte da <i>self::load_icons()</i>.
     icon_width: u32,
+    ///Sistema con cui vengono rilevati e catturati gli schermi.
+    backend: Arc<dyn CaptureBackend>,
 }
 
 impl ScreensManager {
     ///Rileva tutti gli schermi attualmente disponibili e imposta lo schermo primario come quello selezionato
     ///di default.
+    ///Attende che la prima rilevazione degli schermi sia terminata (ma non il caricamento delle icone), in modo che
+    ///la selezione dello schermo primario avvenga su una lista già popolata.
     pub fn new(icon_width: u32) -> Arc<Self> {
+        Self::with_backend(icon_width, Arc::new(ScreenshotsBackend))
+    }
+
+    ///Come <i>Self::new()</i>, ma rileva e cattura gli schermi tramite <i>backend</i>.
+    pub fn with_backend(icon_width: u32, backend: Arc<dyn CaptureBackend>) -> Arc<Self> {
         let ret = Arc::new(Self {
             screens: RwLock::new(vec![]),
             curr_screen_index: RwLock::new(0),
+            all_screens: AtomicBool::new(false),
             icon_width,
+            icon_cache: Mutex::new(HashMap::new()),
+            backend,
         });
-        ret.update_available_screens();
+        let _ = ret.update_available_screens().join();
         ret.select_primary_screen();
         ret
     }
 
-    ///Aggiorna il vettore di Screen, rilevando le modifiche hardware.
+    ///Aggiorna il vettore degli schermi, rilevando le modifiche hardware.
     /// Anche l'indice viene modificato, nel caso lo schermo precedentemente selezionato cambi
     /// di posizione nel vettore.
     /// Nel caso lo schermo precedentemente selezionato non venga piu' rilevato,
@@ -57,35 +235,98 @@ impl ScreensManager {
     ///Infatti, essendo il modulo pensato per essere scalabile nel numero di schermi, la lista può diventare lunga
     ///e le operazioni su di essa onerose.
     ///Per poter eseguire l'elaborazione, il thread dovrà ottenere il lock di <i>self::screens</i> in modalità write.
-    pub fn update_available_screens(self: &Arc<Self>) {
+    ///
+    ///Ritorna il <i>JoinHandle</i> del thread, per i chiamanti che devono attendere la fine della rilevazione.
+    pub fn update_available_screens(self: &Arc<Self>) -> JoinHandle<()> {
         let arc_clone = self.clone();
         std::thread::spawn(move || {
-            let curr_id = if !arc_clone.get_screens().is_empty() {
-                Some(arc_clone.get_current_screen_infos().unwrap().id)
-            } else {
-                None
+            if let Some(screens) = arc_clone.detect_screens() {
+                arc_clone.replace_screens(screens);
+            }
+        })
+    }
+
+    ///Come <i>self::update_available_screens()</i>, ma svuota prima la cache delle icone: tutti gli schermi vengono
+    ///catturati di nuovo. Da richiamare quando è l'utente a chiedere l'aggiornamento della lista.
+    pub fn refresh_screens(self: &Arc<Self>) -> JoinHandle<()> {
+        self.icon_cache.lock().unwrap().clear();
+        self.update_available_screens()
+    }
+
+    ///Lancia un thread watcher che, ogni <i>period</i>, rileva gli schermi collegati e li confronta con la lista
+    ///corrente (vedi <i>diff_displays()</i>).<br>
+    ///Se ci sono differenze, aggiorna la lista e la selezione come <i>self::update_available_screens()</i>, invia le
+    ///differenze sul canale il cui <i>Receiver</i> è ritornato dal metodo corrente e richiede il repaint della gui,
+    ///così che la notifica venga letta anche se l'utente non sta interagendo con l'applicazione.<br>
+    ///Il thread termina quando il <i>Receiver</i> viene distrutto.
+    pub fn start_thread_watch_screens(
+        self: &Arc<Self>,
+        arc_ctx: Arc<Context>,
+        period: Duration,
+    ) -> Receiver<ScreensChange> {
+        let (tx, rx) = channel();
+        let sc = self.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(period);
+            let screens = match sc.detect_screens() {
+                Some(screens) => screens,
+                None => continue,
             };
+            let old: Vec<DisplayInfo> = sc.get_screens().iter().map(|(di, _)| *di).collect();
+            let mut change = diff_displays(&old, &screens);
+            if change.is_empty() {
+                continue;
+            }
+            change.selection_lost = sc.replace_screens(screens) && !sc.is_all_screens_selected();
+            if tx.send(change).is_err() {
+                return;
+            }
+            arc_ctx.request_repaint();
+        });
+        rx
+    }
+
+    ///Sostituisce la lista degli schermi con <i>screens</i>, dopo averne ottenuto il lock in scrittura, e avvia il
+    ///caricamento delle icone non presenti nella cache.<br>
+    ///L'indice dello schermo selezionato viene aggiornato alla nuova posizione dello schermo nella lista; se lo schermo
+    ///non è più collegato, viene selezionato quello primario e il metodo ritorna true.
+    fn replace_screens(self: &Arc<Self>, screens: Vec<DisplayInfo>) -> bool {
+        let curr_id = self
+            .get_screens()
+            .get(self.get_current_screen_index())
+            .map(|s| s.0.id);
+
+        {
+            let mut write_lk = self.screens.write().unwrap();
+            write_lk.clear();
+            for s in screens {
+                let icon = match self.cached_icon(&s) {
+                    Some(icon) => ScreenIcon::Ready(icon),
+                    None => ScreenIcon::Loading,
+                };
+                write_lk.push((s, Mutex::new(icon)));
+            }
+        }
+        self.load_icons();
 
+        match curr_id {
+            Some(id) => match self
+                .get_screens()
+                .iter()
+                .position(|s| s.0.id == id)
             {
-                let mut write_lk = arc_clone.screens.write().unwrap();
-                write_lk.clear();
-                for s in Screen::all().unwrap() {
-                    write_lk.push((s, Mutex::new(None)));
+                Some(i) => {
+                    *self.curr_screen_index.write().unwrap() = i;
+                    false
                 }
-            }
-            arc_clone.load_icons();
-
-            if let Some(id) = curr_id {
-                match arc_clone
-                    .get_screens()
-                    .iter()
-                    .position(|s| s.0.display_info.id == id)
-                {
-                    Some(i) => *arc_clone.curr_screen_index.write().unwrap() = i,
-                    None => arc_clone.select_primary_screen(),
+                None => {
+                    *self.curr_screen_index.write().unwrap() = 0;
+                    self.select_primary_screen();
+                    true
                 }
-            }
-        });
+            },
+            None => false,
+        }
     }
 
     ///Tra gli schermi disponibili (ottenuti dall'ultima rilevazione), permette di selezionare quello su cui
@@ -94,12 +335,25 @@ impl ScreensManager {
     ///leggere <i>self::screens</i>, dopo averne ottenuto il lock in lettura. Questo implica che non si possa cambiare la
     ///selezione durante l'esecuzione di un refresh, ma che si possa fare mentre la lista <i>self::screens</i> viene acceduta
     ///per essere mostrata.
+    ///Annulla l'eventuale selezione di tutti gli schermi.
     pub fn select_screen(self: &Arc<Self>, index: usize) {
         if index < self.get_screens().len() {
             *self.curr_screen_index.write().unwrap() = index;
+            self.all_screens.store(false, Ordering::Relaxed);
         }
     }
 
+    ///Seleziona tutti gli schermi: i prossimi screenshot comprenderanno l'intero desktop virtuale.
+    ///L'indice dello schermo selezionato in precedenza viene mantenuto, e torna ad essere usato alla successiva
+    ///chiamata di <i>self::select_screen()</i>.
+    pub fn select_all_screens(self: &Arc<Self>) {
+        self.all_screens.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_all_screens_selected(self: &Arc<Self>) -> bool {
+        self.all_screens.load(Ordering::Relaxed)
+    }
+
     ///Richiama il metodo <i>self::select_screen()</i> passando come indice quello dello schermo che rileva
     ///come primario.
     ///Per trovare tale indice, è necessario ottenere il lock in lettura su <i>self::screens</i>: questo è implicitamente fatto con
@@ -108,14 +362,14 @@ impl ScreensManager {
         if let Some(i) = self
             .get_screens()
             .iter()
-            .position(|s| s.0.display_info.is_primary)
+            .position(|s| s.0.is_primary)
         {
             *self.curr_screen_index.write().unwrap() = i;
         }
     }
 
     ///Lancia un thread che:
-    ///- esegue uno screenshot sullo schermo attualmente selezionato;
+    ///- esegue uno screenshot sullo schermo attualmente selezionato (o su tutti gli schermi);
     ///- invia l'immagine sul canale il cui <i>Receiver</i> è ritornato dal metodo corrente.
     ///Oppure invia sul canale un messaggio di errore.
     pub fn start_thread_fullscreen_screenshot(
@@ -131,22 +385,217 @@ impl ScreensManager {
         rx
     }
 
-    ///Ottiene lock in lettura su <i>self::screens</i> per poter accedere alla struttura Screen relativa
-    ///allo schermo attualmente selezionato e richiamare <i>capture()</i> su essa.
+    ///Lancia un thread che:
+    ///- esegue uno screenshot dello schermo con id <i>screen_id</i> (0 per il desktop virtuale), senza modificare lo
+    ///  schermo selezionato;
+    ///- ritaglia lo screenshot su <i>area</i>, se specificata;
+    ///- invia l'immagine sul canale il cui <i>Receiver</i> è ritornato dal metodo corrente.
+    ///
+    ///Se lo schermo non è più collegato o l'area non è interamente contenuta nello schermo, invia sul canale un
+    ///messaggio di errore.
+    pub fn start_thread_screen_screenshot(
+        self: &Arc<Self>,
+        screen_id: u32,
+        area: Option<Rect>,
+    ) -> Receiver<Result<RgbaImage, &'static str>> {
+        let (tx, rx) = channel();
+        let sc = self.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send(sc.screen_screenshot(screen_id, area));
+        });
+        rx
+    }
+
+    ///Lancia un thread che registra lo schermo attualmente selezionato:
+    ///- esegue uno screenshot ogni <i>1/fps</i> secondi, ritagliandolo su <i>area</i> se specificata;
+    ///- si ferma allo scadere di <i>duration</i>, quando <i>stop</i> viene impostato a true, oppure quando i frame
+    ///  catturati occupano <i>MAX_RECORDING_BYTES</i>;
+    ///- invia sul canale i frame catturati assieme alla durata media di ciascun frame, che può essere maggiore di
+    ///  <i>1/fps</i> se gli screenshot richiedono più tempo del periodo di campionamento, e a un flag che indica se
+    ///  la registrazione è stata interrotta per il raggiungimento del limite di memoria.
+    ///
+    ///Se uno screenshot fallisce, la registrazione viene interrotta e sul canale viene inviato un messaggio di errore.
+    ///
+    ///Se è indicato <i>excluded</i>, la registrazione inizia dopo che la gui ne ha comunicato il rettangolo, che viene
+    ///mascherato in ogni frame (vedi <i>ExcludedArea</i>).
+    pub fn start_thread_recording(
+        self: &Arc<Self>,
+        area: Option<Rect>,
+        fps: u32,
+        duration: Duration,
+        stop: Arc<AtomicBool>,
+        excluded: Option<Arc<ExcludedArea>>,
+    ) -> Receiver<RecordingResult> {
+        let (tx, rx) = channel();
+        let sc = self.clone();
+        std::thread::spawn(move || {
+            if let Some(excluded) = &excluded {
+                excluded.wait(&stop);
+            }
+            let period = Duration::from_secs_f64(1.0 / fps.max(1) as f64);
+            let start = Instant::now();
+            let mut next = start;
+            let mut frames = vec![];
+            let mut bytes = 0;
+            let mut limit_reached = false;
+            while !stop.load(Ordering::Relaxed) && start.elapsed() < duration {
+                match sc.fullscreen_screenshot() {
+                    Ok(mut img) => {
+                        if let Some(rect) = excluded.as_ref().and_then(|e| e.get()) {
+                            mask_area(&mut img, rect);
+                        }
+                        let img = crop_area(img, area);
+                        bytes += img.as_raw().len();
+                        frames.push(img);
+                        //i frame restano in memoria, non compressi, fino al salvataggio
+                        if bytes >= MAX_RECORDING_BYTES {
+                            limit_reached = true;
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        return;
+                    }
+                }
+                next += period;
+                let now = Instant::now();
+                if next > now {
+                    std::thread::sleep(next - now);
+                } else {
+                    next = now;
+                }
+            }
+            let frame_delay = start.elapsed() / frames.len().max(1) as u32;
+            let _ = tx.send(Ok((frames, frame_delay, limit_reached)));
+        });
+        rx
+    }
+
+    ///Lancia un thread che, ogni <i>settings.interval</i> secondi, cattura lo schermo selezionato (ritagliando l'immagine
+    ///su <i>area</i>, se specificata) e la passa a <i>save</i>, assieme ai metadati della cattura, perché la salvi e
+    ///ritorni il path del file scritto.<br>
+    ///Per ogni scatto viene inviato sul canale un <i>TimelapseEvent</i>: gli scatti identici al precedente vengono
+    ///scartati, se richiesto da <i>settings.skip_identical</i>. Se <i>settings.watch</i> è specificato, vengono invece
+    ///scartati gli scatti non cambiati rispetto all'ultimo salvato (vedi <i>image_coding::compare_images()</i>): il
+    ///rettangolo cambiato viene scritto nei metadati.
+    ///Il thread termina (inviando <i>TimelapseEvent::Finished</i>) dopo <i>settings.max_shots</i> scatti salvati, oppure
+    ///quando viene impostato il flag <i>stop</i>, controllato anche durante l'attesa tra due scatti; in caso di errore
+    ///invia invece <i>TimelapseEvent::Failed</i>.
+    ///I salvataggi sono eseguiti in sequenza dallo stesso thread, così che il nome di ogni file possa dipendere da
+    ///quelli già scritti (ad esempio, con un contatore incrementale).
+    ///Se è indicato <i>gate</i>, la finestra della gui viene nascosta durante ogni scatto (vedi <i>ShotGate</i>).
+    pub fn start_thread_timelapse<F>(
+        self: &Arc<Self>,
+        area: Option<Rect>,
+        settings: TimelapseSettings,
+        stop: Arc<AtomicBool>,
+        gate: Option<Arc<ShotGate>>,
+        mut save: F,
+    ) -> Receiver<TimelapseEvent>
+    where
+        F: FnMut(RgbaImage, CaptureMetadata) -> Result<PathBuf, String> + Send + 'static,
+    {
+        let (tx, rx) = channel();
+        let sc = self.clone();
+        std::thread::spawn(move || {
+            let period = Duration::from_secs_f64(settings.interval.max(0.0));
+            let (mode, crop) = match area {
+                Some(r) => (
+                    ScreenshotDim::Rectangle,
+                    Some((r.left() as u32, r.top() as u32, r.width() as u32, r.height() as u32)),
+                ),
+                None => (ScreenshotDim::Fullscreen, None),
+            };
+            let keep_previous = settings.skip_identical || settings.watch.is_some();
+            let mut next = Instant::now();
+            //ultimo scatto salvato, con cui viene confrontato il successivo
+            let mut previous: Option<RgbaImage> = None;
+            let mut saved = 0;
+            while saved < settings.max_shots && !stop.load(Ordering::Relaxed) {
+                if let Some(gate) = &gate {
+                    gate.hide_window(&stop);
+                }
+                let shot = sc.fullscreen_screenshot().and_then(|img| {
+                    let display = sc
+                        .get_current_screen_infos()
+                        .ok_or("Error: the screen to capture is not connected")?;
+                    Ok((crop_area(img, area), display))
+                });
+                if let Some(gate) = &gate {
+                    gate.show_window();
+                }
+                let event = match shot {
+                    Ok((img, display)) => {
+                        //None se lo scatto va scartato, altrimenti il rettangolo cambiato da scrivere nei metadati
+                        let changed_area = match (&previous, settings.watch) {
+                            (None, _) => Some(None),
+                            (Some(prev), Some(ds)) => compare_images(prev, &img, &ds).changed_area.map(Some),
+                            (Some(prev), None) if settings.skip_identical && *prev == img => None,
+                            (Some(_), None) => Some(None),
+                        };
+                        match changed_area {
+                            None => TimelapseEvent::Skipped,
+                            Some(changed_area) => {
+                                if keep_previous {
+                                    previous = Some(img.clone());
+                                }
+                                let mut metadata = CaptureMetadata::new(&display, mode.clone(), crop);
+                                metadata.changed_area = changed_area;
+                                match save(img, metadata) {
+                                    Ok(path) => {
+                                        saved += 1;
+                                        TimelapseEvent::Saved(path)
+                                    }
+                                    Err(e) => TimelapseEvent::Failed(e),
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => TimelapseEvent::Failed(e.to_string()),
+                };
+                let failed = matches!(event, TimelapseEvent::Failed(_));
+                if tx.send(event).is_err() || failed {
+                    return;
+                }
+                if saved == settings.max_shots {
+                    break;
+                }
+                //l'attesa viene suddivisa in brevi intervalli, per reagire rapidamente alla richiesta di interruzione
+                next = (next + period).max(Instant::now());
+                while !stop.load(Ordering::Relaxed) {
+                    let now = Instant::now();
+                    if next <= now {
+                        break;
+                    }
+                    std::thread::sleep((next - now).min(Duration::from_millis(100)));
+                }
+            }
+            let _ = tx.send(TimelapseEvent::Finished);
+        });
+        rx
+    }
+
+    ///Ottiene lock in lettura su <i>self::screens</i> per poter accedere alle informazioni dello schermo attualmente
+    ///selezionato e richiederne la cattura al backend.
     ///L'acquisizione del lock implica che il metodo corrente si blocchi se è contemporaneamente eseguito l'aggiornamento di tale lista.
+    ///Se sono selezionati tutti gli schermi, richiama invece <i>self::all_screens_screenshot()</i>.<br>
+    ///Se lo schermo selezionato è stato scollegato (e la lista non è ancora stata aggiornata), ritorna un errore.
     fn fullscreen_screenshot(self: &Arc<Self>) -> Result<RgbaImage, &'static str> {
-        match self
+        if self.is_all_screens_selected() {
+            return self.all_screens_screenshot();
+        }
+        let screen = self
             .get_screens()
             .get(*self.curr_screen_index.read().unwrap())
-            .unwrap()
-            .0
-            .capture()
-        {
+            .map(|(di, _)| *di)
+            .ok_or("Error: the screen to capture is not connected")?;
+        match self.backend.capture(&screen) {
             Ok(shot) => Ok(shot),
             Err(s) => {
                 let _ = write!(
                     std::io::stderr(),
-                    "Error: unable to perform screenshot: {:?}",
+                    "Error: unable to perform screenshot: {}",
                     s
                 );
                 Err("Error: unable to perform screenshot")
@@ -154,45 +603,196 @@ impl ScreensManager {
         }
     }
 
+    fn screen_screenshot(
+        self: &Arc<Self>,
+        screen_id: u32,
+        area: Option<Rect>,
+    ) -> Result<RgbaImage, &'static str> {
+        let img = if screen_id == 0 {
+            self.all_screens_screenshot()?
+        } else {
+            let screen = self
+                .get_screens()
+                .iter()
+                .map(|(di, _)| *di)
+                .find(|di| di.id == screen_id)
+                .ok_or("Error: the screen to capture is not connected")?;
+            self.backend.capture(&screen).map_err(|s| {
+                let _ = write!(
+                    std::io::stderr(),
+                    "Error: unable to perform screenshot: {}",
+                    s
+                );
+                "Error: unable to perform screenshot"
+            })?
+        };
+        let area = match area {
+            Some(area) => area,
+            None => return Ok(img),
+        };
+        if area.right() > img.width() as f32 || area.bottom() > img.height() as f32 {
+            return Err("Error: the region to capture is outside of the screen");
+        }
+        Ok(image::imageops::crop_imm(
+            &img,
+            area.left() as u32,
+            area.top() as u32,
+            area.width() as u32,
+            area.height() as u32,
+        )
+        .to_image())
+    }
+
+    ///Esegue in parallelo, con un thread per schermo, uno screenshot di ogni schermo rilevato, poi compone le immagini
+    ///ottenute con <i>compose_virtual_desktop()</i>.
+    ///Il lock in lettura su <i>self::screens</i> è mantenuto solo per copiare la lista degli schermi, non durante le catture.
+    fn all_screens_screenshot(self: &Arc<Self>) -> Result<RgbaImage, &'static str> {
+        let screens: Vec<DisplayInfo> = self.get_screens().iter().map(|(di, _)| *di).collect();
+        let handles: Vec<_> = screens
+            .into_iter()
+            .map(|di| {
+                let sc = self.clone();
+                std::thread::spawn(move || sc.backend.capture(&di).map(|img| (di, img)))
+            })
+            .collect();
+        let mut shots = vec![];
+        for h in handles {
+            match h.join() {
+                Ok(Ok(shot)) => shots.push(shot),
+                Ok(Err(s)) => {
+                    let _ = write!(
+                        std::io::stderr(),
+                        "Error: unable to perform screenshot: {}",
+                        s
+                    );
+                    return Err("Error: unable to perform screenshot");
+                }
+                Err(_) => return Err("Error: unable to perform screenshot"),
+            }
+        }
+        if shots.is_empty() {
+            return Err("Error: no screen available");
+        }
+        Ok(compose_virtual_desktop(shots))
+    }
+
+    ///Ritorna il numero di schermi rilevati dall'ultimo aggiornamento.
+    ///Bloccante se è in corso l'aggiornamento della lista.
+    pub fn get_screens_count(self: &Arc<Self>) -> usize {
+        self.get_screens().len()
+    }
+
     pub fn get_current_screen_index(self: &Arc<Self>) -> usize {
         *self.curr_screen_index.read().unwrap()
     }
 
     ///Ritorna None nel caso le info sugli schermi non siano ancora state caricate (vettore di screen vuoto).
+    ///Se sono selezionati tutti gli schermi, ritorna le info del desktop virtuale (vedi <i>virtual_desktop_infos()</i>).
     pub fn get_current_screen_infos(self: &Arc<Self>) -> Option<DisplayInfo> {
-        self.get_screens()
+        let screens = self.get_screens();
+        if self.is_all_screens_selected() {
+            let infos: Vec<DisplayInfo> = screens.iter().map(|(di, _)| *di).collect();
+            return virtual_desktop_infos(&infos);
+        }
+        screens
             .get(*self.curr_screen_index.read().unwrap())
-            .map(|(screen, _)| screen.display_info)
+            .map(|(di, _)| *di)
+    }
+
+    ///Ritorna le info dello schermo con id <i>screen_id</i> (o del desktop virtuale, se <i>screen_id</i> è 0), se
+    ///collegato.
+    pub fn get_screen_infos(self: &Arc<Self>, screen_id: u32) -> Option<DisplayInfo> {
+        let infos: Vec<DisplayInfo> = self.get_screens().iter().map(|(di, _)| *di).collect();
+        if screen_id == 0 {
+            return virtual_desktop_infos(&infos);
+        }
+        infos.into_iter().find(|di| di.id == screen_id)
     }
 
-    /// Lancia un thread per ogni screen nel vettore di screen per parallelizzare la creazione di tutte le corrispondenti icone.
-    /// In particolare, ogni thread scatta uno screenshot del proprio schermo, poi ridimensiona l'immagine
-    /// (riducendola alla dimensione specificata in ScreensManager::icon_width) e la salva nella corretta posizione all'interno
-    /// del vettore di screen.
+    /// Lancia un thread per ogni schermo la cui icona è in caricamento, per parallelizzare la creazione delle icone.
+    /// In particolare, ogni thread scatta un solo screenshot del proprio schermo, lo riduce alla larghezza
+    /// <i>ScreensManager::icon_width</i> (vedi <i>make_icon()</i>), lo inserisce nella cache e lo salva nella voce
+    /// dello schermo all'interno del vettore.<br>
+    /// Il lock in lettura sul vettore non viene mantenuto durante la cattura, così da non bloccare un eventuale refresh.
+    /// Se la cattura fallisce, l'icona diventa <i>ScreenIcon::Failed</i>.
     fn load_icons(self: &Arc<Self>) {
-        for (index, _) in self.get_screens().iter().enumerate() {
+        let to_load: Vec<DisplayInfo> = self
+            .get_screens()
+            .iter()
+            .filter(|(_, icon)| *icon.lock().unwrap() == ScreenIcon::Loading)
+            .map(|(di, _)| *di)
+            .collect();
+        for di in to_load {
             let arc = self.clone();
             std::thread::spawn(move || {
-                let screens = arc.get_screens();
-                let (s, i) = screens.get(index).unwrap();
-                let img = s.capture().unwrap();
-                let height = arc.icon_width * img.height() / img.width();
-                let icon = image::imageops::resize(
-                    &s.capture().unwrap(),
-                    arc.icon_width,
-                    height,
-                    FilterType::Gaussian,
-                );
-                let mut g = i.lock().unwrap();
-                *g = Some(icon);
+                let icon = match arc.backend.capture(&di) {
+                    Ok(img) => {
+                        let icon = make_icon(&img, arc.icon_width);
+                        arc.icon_cache.lock().unwrap().insert(di.id, (di, icon.clone()));
+                        ScreenIcon::Ready(icon)
+                    }
+                    Err(e) => {
+                        let _ = writeln!(
+                            std::io::stderr(),
+                            "Error: unable to capture the icon of screen {}: {}",
+                            di.id,
+                            e
+                        );
+                        ScreenIcon::Failed("Unable to capture this screen")
+                    }
+                };
+                //la lista potrebbe essere stata aggiornata durante la cattura: lo schermo viene cercato per id
+                if let Some((_, i)) = arc.get_screens().iter().find(|(d, _)| d.id == di.id) {
+                    *i.lock().unwrap() = icon;
+                }
             });
         }
     }
 
+    ///Icona in cache dello schermo <i>di</i>, se è stata creata con la risoluzione attuale dello schermo.
+    fn cached_icon(self: &Arc<Self>, di: &DisplayInfo) -> Option<RgbaImage> {
+        self.icon_cache
+            .lock()
+            .unwrap()
+            .get(&di.id)
+            .filter(|(cached, _)| same_resolution(cached, di))
+            .map(|(_, icon)| icon.clone())
+    }
+
+    ///Metadati della cattura di una finestra che occupa <i>rect</i> (x, y, larghezza, altezza, in pixel fisici del
+    ///desktop virtuale). Lo schermo è quello che contiene il centro della finestra (o, se il centro non è su nessuno
+    ///schermo, lo schermo selezionato); il ritaglio è l'area della finestra in pixel fisici di quello schermo, limitata
+    ///al suo angolo in alto a sinistra.
+    pub fn window_metadata(
+        self: &Arc<Self>,
+        (x, y, width, height): (i32, i32, u32, u32),
+    ) -> Option<CaptureMetadata> {
+        let origin = |di: &DisplayInfo| {
+            (
+                (di.x as f32 * di.scale_factor).round() as i32,
+                (di.y as f32 * di.scale_factor).round() as i32,
+            )
+        };
+        let (cx, cy) = (x + width as i32 / 2, y + height as i32 / 2);
+        let display = self
+            .get_screens()
+            .iter()
+            .map(|(di, _)| *di)
+            .find(|di| {
+                let (ox, oy) = origin(di);
+                let scaled = |v: u32| (v as f32 * di.scale_factor).round() as i32;
+                (ox..ox + scaled(di.width)).contains(&cx) && (oy..oy + scaled(di.height)).contains(&cy)
+            })
+            .or_else(|| self.get_current_screen_infos())?;
+        let (ox, oy) = origin(&display);
+        let crop = ((x - ox).max(0) as u32, (y - oy).max(0) as u32, width, height);
+        Some(CaptureMetadata::new(&display, ScreenshotDim::Window, Some(crop)))
+    }
+
     ///Non bloccante.
     pub fn try_get_screens<'a>(
         self: &'a Arc<Self>,
-    ) -> Option<RwLockReadGuard<'a, Vec<(Screen, Mutex<Option<RgbaImage>>)>>> {
+    ) -> Option<RwLockReadGuard<'a, Vec<(DisplayInfo, Mutex<ScreenIcon>)>>> {
         match self.screens.try_read() {
             Ok(g) => Some(g),
             Err(..) => None,
@@ -202,7 +802,535 @@ impl ScreensManager {
     ///Bloccante.
     fn get_screens<'a>(
         self: &'a Arc<Self>,
-    ) -> RwLockReadGuard<'a, Vec<(Screen, Mutex<Option<RgbaImage>>)>> {
+    ) -> RwLockReadGuard<'a, Vec<(DisplayInfo, Mutex<ScreenIcon>)>> {
         self.screens.read().unwrap()
     }
+
+    ///Rileva gli schermi collegati. In caso di errore lo scrive su stderr e ritorna None.
+    fn detect_screens(self: &Arc<Self>) -> Option<Vec<DisplayInfo>> {
+        match self.backend.displays() {
+            Ok(screens) => Some(screens),
+            Err(e) => {
+                let _ = writeln!(std::io::stderr(), "Error: unable to detect the screens: {}", e);
+                None
+            }
+        }
+    }
+}
+
+///Confronta due rilevazioni degli schermi, identificati dal loro id. Il campo <i>selection_lost</i> del risultato è
+///sempre false.
+pub fn diff_displays(old: &[DisplayInfo], new: &[DisplayInfo]) -> ScreensChange {
+    let find = |infos: &[DisplayInfo], id: u32| infos.iter().find(|di| di.id == id).copied();
+    let same = |a: &DisplayInfo, b: &DisplayInfo| {
+        (a.x, a.y, a.is_primary) == (b.x, b.y, b.is_primary) && same_resolution(a, b)
+    };
+    ScreensChange {
+        added: new.iter().filter(|di| find(old, di.id).is_none()).copied().collect(),
+        removed: old.iter().filter(|di| find(new, di.id).is_none()).copied().collect(),
+        changed: new
+            .iter()
+            .filter(|di| find(old, di.id).is_some_and(|o| !same(&o, di)))
+            .copied()
+            .collect(),
+        selection_lost: false,
+    }
+}
+
+///True se i due schermi hanno la stessa risoluzione, rotazione e fattore di scala.
+fn same_resolution(a: &DisplayInfo, b: &DisplayInfo) -> bool {
+    (a.width, a.height) == (b.width, b.height)
+        && a.rotation == b.rotation
+        && a.scale_factor == b.scale_factor
+}
+
+///Riduce lo screenshot di uno schermo ad un'icona larga <b>width</b> pixel, mantenendo le proporzioni.<br>
+///Usa <i>imageops::thumbnail()</i>, che calcola la media dei pixel (filtro box): sugli schermi ad alta risoluzione è
+///molto più veloce dei filtri di ricampionamento di <i>imageops::resize()</i>, con una qualità sufficiente per un'icona.
+pub fn make_icon(img: &RgbaImage, width: u32) -> RgbaImage {
+    let height = (width as u64 * img.height() as u64 / img.width().max(1) as u64).max(1);
+    image::imageops::thumbnail(img, width, height as u32)
+}
+
+///Ritaglia lo screenshot <i>img</i> sull'area <i>area</i>, se specificata.
+fn crop_area(img: RgbaImage, area: Option<Rect>) -> RgbaImage {
+    match area {
+        Some(r) => image::imageops::crop_imm(
+            &img,
+            r.left() as u32,
+            r.top() as u32,
+            r.width() as u32,
+            r.height() as u32,
+        )
+        .to_image(),
+        None => img,
+    }
+}
+
+///Riempie di nero la parte di <i>rect</i> contenuta nell'immagine.
+fn mask_area(img: &mut RgbaImage, rect: Rect) {
+    //le conversioni da f32 a u32 saturano: le coordinate negative diventano 0
+    let (x0, y0) = (rect.left() as u32, rect.top() as u32);
+    let x1 = (rect.right() as u32).min(img.width());
+    let y1 = (rect.bottom() as u32).min(img.height());
+    for y in y0..y1 {
+        for x in x0..x1 {
+            img.put_pixel(x, y, image::Rgba([0, 0, 0, 255]));
+        }
+    }
+}
+
+///Rettangolo che contiene tutti gli schermi, in coordinate del desktop virtuale: (x minima, y minima, x massima, y massima).
+fn desktop_bounds(infos: &[DisplayInfo]) -> (i32, i32, i32, i32) {
+    infos.iter().fold(
+        (i32::MAX, i32::MAX, i32::MIN, i32::MIN),
+        |(x0, y0, x1, y1), di| {
+            (
+                x0.min(di.x),
+                y0.min(di.y),
+                x1.max(di.x + di.width as i32),
+                y1.max(di.y + di.height as i32),
+            )
+        },
+    )
+}
+
+///Fattore di scala dell'immagine composta: il massimo tra quelli degli schermi, in modo da non perdere risoluzione
+///sugli schermi ad alta densità.
+fn desktop_scale_factor(infos: &[DisplayInfo]) -> f32 {
+    infos
+        .iter()
+        .map(|di| di.scale_factor)
+        .filter(|s| s.is_finite() && *s > 0.0)
+        .fold(1.0, f32::max)
+}
+
+///Descrive il desktop virtuale come se fosse un unico schermo, con id 0, posizione e dimensioni del rettangolo che
+///contiene tutti gli schermi e fattore di scala dell'immagine composta.<br>
+///Ritorna None se la lista è vuota.
+pub fn virtual_desktop_infos(infos: &[DisplayInfo]) -> Option<DisplayInfo> {
+    if infos.is_empty() {
+        return None;
+    }
+    let (x0, y0, x1, y1) = desktop_bounds(infos);
+    Some(DisplayInfo {
+        id: 0,
+        x: x0,
+        y: y0,
+        width: (x1 - x0) as u32,
+        height: (y1 - y0) as u32,
+        rotation: 0.0,
+        scale_factor: desktop_scale_factor(infos),
+        is_primary: false,
+    })
+}
+
+///Compone gli screenshot dei singoli schermi in un'unica immagine del desktop virtuale.<br>
+///Ogni screenshot viene posizionato in base alle coordinate x/y del relativo schermo e, se la sua risoluzione non
+///corrisponde al fattore di scala dell'immagine composta, ridimensionato. Le zone non coperte da alcuno schermo
+///restano trasparenti.
+pub fn compose_virtual_desktop(shots: Vec<(DisplayInfo, RgbaImage)>) -> RgbaImage {
+    let infos: Vec<DisplayInfo> = shots.iter().map(|(di, _)| *di).collect();
+    let (x0, y0, x1, y1) = desktop_bounds(&infos);
+    let scale = desktop_scale_factor(&infos);
+    let scaled = |v: i32| (v as f32 * scale).round() as i64;
+    let mut desktop = RgbaImage::new(scaled(x1 - x0) as u32, scaled(y1 - y0) as u32);
+    for (di, img) in shots {
+        let (width, height) = (scaled(di.width as i32) as u32, scaled(di.height as i32) as u32);
+        let img = if img.dimensions() == (width, height) {
+            img
+        } else {
+            image::imageops::resize(&img, width, height, FilterType::Triangle)
+        };
+        image::imageops::replace(&mut desktop, &img, scaled(di.x - x0), scaled(di.y - y0));
+    }
+    desktop
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{self, MockBackend};
+    use super::*;
+    use crate::image_coding::DiffSettings;
+
+    fn display(id: u32, x: i32, y: i32, width: u32, height: u32, scale_factor: f32) -> DisplayInfo {
+        DisplayInfo {
+            id,
+            x,
+            y,
+            width,
+            height,
+            rotation: 0.0,
+            scale_factor,
+            is_primary: id == 1,
+        }
+    }
+
+    #[test]
+    fn compose_virtual_desktop_test() {
+        //due schermi affiancati di altezza diversa, il secondo a sinistra del primo
+        let left = display(2, -4, 0, 4, 2, 1.0);
+        let right = display(1, 0, 0, 2, 3, 1.0);
+        let red = image::Rgba([255, 0, 0, 255]);
+        let green = image::Rgba([0, 255, 0, 255]);
+        let desktop = compose_virtual_desktop(vec![
+            (right, RgbaImage::from_pixel(2, 3, red)),
+            (left, RgbaImage::from_pixel(4, 2, green)),
+        ]);
+        assert_eq!(desktop.dimensions(), (6, 3));
+        assert_eq!(*desktop.get_pixel(0, 0), green);
+        assert_eq!(*desktop.get_pixel(4, 2), red);
+        //sotto lo schermo sinistro, più basso, resta un vuoto trasparente
+        assert_eq!(desktop.get_pixel(0, 2).0[3], 0);
+    }
+
+    #[test]
+    fn compose_scaled_test() {
+        //il secondo schermo ha densità doppia: il primo viene ingrandito
+        let low = display(1, 0, 0, 2, 2, 1.0);
+        let high = display(2, 2, 0, 2, 2, 2.0);
+        let desktop = compose_virtual_desktop(vec![
+            (low, RgbaImage::from_pixel(2, 2, image::Rgba([1, 1, 1, 255]))),
+            (high, RgbaImage::from_pixel(4, 4, image::Rgba([2, 2, 2, 255]))),
+        ]);
+        assert_eq!(desktop.dimensions(), (8, 4));
+        assert_eq!(desktop.get_pixel(3, 3).0, [1, 1, 1, 255]);
+        assert_eq!(desktop.get_pixel(4, 0).0, [2, 2, 2, 255]);
+
+        let di = virtual_desktop_infos(&[low, high]).unwrap();
+        assert_eq!((di.x, di.y, di.width, di.height), (0, 0, 4, 2));
+        assert_eq!(di.scale_factor, 2.0);
+        assert!(virtual_desktop_infos(&[]).is_none());
+    }
+
+    #[test]
+    fn diff_displays_test() {
+        let first = display(1, 0, 0, 1920, 1080, 1.0);
+        let second = display(2, 1920, 0, 1280, 1024, 1.0);
+        assert!(diff_displays(&[first, second], &[first, second]).is_empty());
+
+        let unplugged = diff_displays(&[first, second], &[first]);
+        assert_eq!(unplugged.removed.iter().map(|di| di.id).collect::<Vec<_>>(), vec![2]);
+        assert!(unplugged.added.is_empty() && unplugged.changed.is_empty());
+
+        let plugged = diff_displays(&[first], &[second, first]);
+        assert_eq!(plugged.added.iter().map(|di| di.id).collect::<Vec<_>>(), vec![2]);
+
+        //stesso schermo, ma con una risoluzione diversa
+        let resized = diff_displays(&[first], &[display(1, 0, 0, 1280, 720, 1.0)]);
+        assert_eq!(resized.changed.len(), 1);
+        assert!(resized.added.is_empty() && resized.removed.is_empty());
+    }
+
+    fn manager(backend: &Arc<MockBackend>) -> Arc<ScreensManager> {
+        ScreensManager::with_backend(10, backend.clone())
+    }
+
+    ///Attende che i thread di caricamento delle icone abbiano terminato e ritorna le icone.
+    fn wait_icons(sm: &Arc<ScreensManager>) -> Vec<ScreenIcon> {
+        for _ in 0..200 {
+            let icons: Vec<ScreenIcon> =
+                sm.get_screens().iter().map(|(_, i)| i.lock().unwrap().clone()).collect();
+            if !icons.contains(&ScreenIcon::Loading) {
+                return icons;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("icons not loaded");
+    }
+
+    fn ready(icon: &ScreenIcon) -> &RgbaImage {
+        match icon {
+            ScreenIcon::Ready(img) => img,
+            _ => panic!("icon not available: {:?}", icon),
+        }
+    }
+
+    #[test]
+    fn primary_and_icons_test() {
+        let backend = Arc::new(MockBackend::new(vec![
+            mock::display(2, -100, 0, 100, 50),
+            mock::display(1, 0, 0, 200, 100),
+        ]));
+        let sm = manager(&backend);
+        assert_eq!(sm.get_screens_count(), 2);
+        assert_eq!(sm.get_current_screen_index(), 1);
+        assert_eq!(sm.get_current_screen_infos().unwrap().id, 1);
+
+        let icons = wait_icons(&sm);
+        assert_eq!(ready(&icons[0]).dimensions(), (10, 5));
+        assert_eq!(*ready(&icons[0]).get_pixel(0, 0), MockBackend::color(2));
+        assert_eq!(*ready(&icons[1]).get_pixel(0, 0), MockBackend::color(1));
+    }
+
+    #[test]
+    fn icon_cache_test() {
+        let first = mock::display(1, 0, 0, 200, 100);
+        let second = mock::display(2, 200, 0, 100, 100);
+        let backend = Arc::new(MockBackend::new(vec![first, second]));
+        let sm = manager(&backend);
+        wait_icons(&sm);
+        let captures = backend.captures();
+
+        //schermi invariati (anche se spostati): le icone vengono prese dalla cache, senza catturare
+        backend.set_displays(vec![second, mock::display(1, 100, 0, 200, 100)]);
+        sm.update_available_screens().join().unwrap();
+        assert!(wait_icons(&sm).iter().all(|i| matches!(i, ScreenIcon::Ready(_))));
+        assert_eq!(backend.captures(), captures);
+
+        //cambia la risoluzione di uno schermo, e la sua cattura fallisce: segnaposto al posto dell'icona
+        backend.set_fail_captures(true);
+        backend.set_displays(vec![second, mock::display(1, 0, 0, 100, 100)]);
+        sm.update_available_screens().join().unwrap();
+        let icons = wait_icons(&sm);
+        assert_eq!(backend.captures(), captures + 1);
+        assert!(matches!(icons[0], ScreenIcon::Ready(_)));
+        assert!(matches!(icons[1], ScreenIcon::Failed(_)));
+
+        //aggiornamento esplicito: tutte le icone vengono catturate di nuovo
+        backend.set_fail_captures(false);
+        sm.refresh_screens().join().unwrap();
+        assert!(wait_icons(&sm).iter().all(|i| matches!(i, ScreenIcon::Ready(_))));
+        assert_eq!(backend.captures(), captures + 3);
+    }
+
+    #[test]
+    fn make_icon_test() {
+        let img = RgbaImage::from_pixel(1920, 1080, image::Rgba([10, 20, 30, 255]));
+        let icon = make_icon(&img, 150);
+        assert_eq!(icon.dimensions(), (150, 84));
+        assert_eq!(icon.get_pixel(75, 40).0, [10, 20, 30, 255]);
+        //immagini molto larghe: l'icona è alta almeno un pixel
+        assert_eq!(make_icon(&RgbaImage::new(1000, 1), 10).dimensions(), (10, 1));
+    }
+
+    #[test]
+    fn refresh_keeps_selection_test() {
+        let first = mock::display(1, 0, 0, 200, 100);
+        let second = mock::display(2, 200, 0, 100, 100);
+        let backend = Arc::new(MockBackend::new(vec![first, second]));
+        let sm = manager(&backend);
+        sm.select_screen(1);
+        sm.select_screen(5); //indice non valido: ignorato
+        assert_eq!(sm.get_current_screen_infos().unwrap().id, 2);
+
+        //lo schermo selezionato cambia posizione nella lista
+        backend.set_displays(vec![mock::display(3, -50, 0, 50, 50), second, first]);
+        sm.update_available_screens().join().unwrap();
+        assert_eq!(sm.get_current_screen_index(), 1);
+        assert_eq!(sm.get_current_screen_infos().unwrap().id, 2);
+
+        //lo schermo selezionato viene scollegato: viene selezionato quello primario
+        backend.set_displays(vec![mock::display(3, -50, 0, 50, 50), first]);
+        sm.update_available_screens().join().unwrap();
+        assert_eq!(sm.get_current_screen_infos().unwrap().id, 1);
+        assert_eq!(wait_icons(&sm).len(), 2);
+    }
+
+    #[test]
+    fn watch_screens_test() {
+        let first = mock::display(1, 0, 0, 200, 100);
+        let second = mock::display(2, 200, 0, 100, 100);
+        let backend = Arc::new(MockBackend::new(vec![first, second]));
+        let sm = manager(&backend);
+        sm.select_screen(1);
+        let rx = sm.start_thread_watch_screens(Arc::new(Context::default()), Duration::from_millis(10));
+
+        backend.set_displays(vec![first]);
+        let change = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(change.removed.iter().map(|di| di.id).collect::<Vec<_>>(), vec![2]);
+        assert!(change.selection_lost);
+        assert_eq!(sm.get_screens_count(), 1);
+        assert_eq!(sm.get_current_screen_infos().unwrap().id, 1);
+
+        backend.set_displays(vec![first, second]);
+        let change = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(change.added.iter().map(|di| di.id).collect::<Vec<_>>(), vec![2]);
+        assert!(!change.selection_lost);
+    }
+
+    #[test]
+    fn capture_test() {
+        let first = mock::display(1, 0, 0, 200, 100);
+        let second = mock::display(2, 200, 0, 100, 50);
+        let backend = Arc::new(MockBackend::new(vec![first, second]));
+        let sm = manager(&backend);
+
+        let img = sm.start_thread_fullscreen_screenshot().recv().unwrap().unwrap();
+        assert_eq!(img.dimensions(), (200, 100));
+        assert_eq!(*img.get_pixel(0, 0), MockBackend::color(1));
+
+        sm.select_all_screens();
+        let img = sm.start_thread_fullscreen_screenshot().recv().unwrap().unwrap();
+        assert_eq!(img.dimensions(), (300, 100));
+        assert_eq!(*img.get_pixel(250, 0), MockBackend::color(2));
+        assert_eq!(img.get_pixel(250, 75).0[3], 0);
+
+        let area = Rect::from_min_max(eframe::emath::pos2(10.0, 10.0), eframe::emath::pos2(30.0, 20.0));
+        let img = sm.start_thread_screen_screenshot(2, Some(area)).recv().unwrap().unwrap();
+        assert_eq!(img.dimensions(), (20, 10));
+        let outside = Rect::from_min_max(eframe::emath::pos2(90.0, 40.0), eframe::emath::pos2(110.0, 60.0));
+        assert!(sm.start_thread_screen_screenshot(2, Some(outside)).recv().unwrap().is_err());
+        assert!(sm.start_thread_screen_screenshot(3, None).recv().unwrap().is_err());
+
+        //schermo scollegato prima dell'aggiornamento della lista, oppure cattura fallita: errore, non panic
+        sm.select_screen(1);
+        backend.set_displays(vec![first]);
+        assert!(sm.start_thread_fullscreen_screenshot().recv().unwrap().is_err());
+        backend.set_fail_captures(true);
+        sm.select_screen(0);
+        let captures = backend.captures();
+        assert!(sm.start_thread_fullscreen_screenshot().recv().unwrap().is_err());
+        assert_eq!(backend.captures(), captures + 1);
+    }
+
+    #[test]
+    fn timelapse_test() {
+        let backend = Arc::new(MockBackend::new(vec![mock::display(1, 0, 0, 200, 100)]));
+        let sm = manager(&backend);
+        let settings = TimelapseSettings {
+            interval: 0.001,
+            max_shots: 3,
+            skip_identical: false,
+            ..Default::default()
+        };
+        let area = Rect::from_min_max(eframe::emath::pos2(10.0, 10.0), eframe::emath::pos2(30.0, 20.0));
+        let sizes = Arc::new(Mutex::new(vec![]));
+        let s = sizes.clone();
+        let rx = sm.start_thread_timelapse(Some(area), settings, Arc::new(AtomicBool::new(false)), None, move |img, md| {
+            assert_eq!((md.mode, md.crop, md.changed_area), (ScreenshotDim::Rectangle, Some((10, 10, 20, 10)), None));
+            let mut s = s.lock().unwrap();
+            s.push(img.dimensions());
+            Ok(PathBuf::from(format!("shot{}.png", s.len())))
+        });
+        let events: Vec<_> = rx.iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                TimelapseEvent::Saved(PathBuf::from("shot1.png")),
+                TimelapseEvent::Saved(PathBuf::from("shot2.png")),
+                TimelapseEvent::Saved(PathBuf::from("shot3.png")),
+                TimelapseEvent::Finished,
+            ]
+        );
+        assert_eq!(*sizes.lock().unwrap(), vec![(20, 10); 3]);
+
+        //gli screenshot del backend simulato sono tutti uguali: viene salvato solo il primo, fino all'interruzione
+        let stop = Arc::new(AtomicBool::new(false));
+        let rx = sm.start_thread_timelapse(
+            None,
+            TimelapseSettings { skip_identical: true, ..settings },
+            stop.clone(),
+            None,
+            |_, _| Ok(PathBuf::from("shot.png")),
+        );
+        assert_eq!(rx.recv().unwrap(), TimelapseEvent::Saved(PathBuf::from("shot.png")));
+        assert_eq!(rx.recv().unwrap(), TimelapseEvent::Skipped);
+        stop.store(true, Ordering::Relaxed);
+        let rest: Vec<_> = rx.iter().collect();
+        assert_eq!(rest.last(), Some(&TimelapseEvent::Finished));
+        assert!(rest[..rest.len() - 1].iter().all(|e| *e == TimelapseEvent::Skipped));
+
+        //errore di salvataggio: la timelapse viene interrotta
+        let rx = sm.start_thread_timelapse(None, settings, Arc::new(AtomicBool::new(false)), None, |_, _| {
+            Err("disk full".to_string())
+        });
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![TimelapseEvent::Failed("disk full".to_string())]);
+    }
+
+    #[test]
+    fn watch_changes_test() {
+        let backend = Arc::new(MockBackend::new(vec![mock::display(1, 0, 0, 200, 100)]));
+        let sm = manager(&backend);
+        let settings = TimelapseSettings {
+            interval: 0.001,
+            max_shots: 3,
+            watch: Some(DiffSettings {
+                block_size: 10,
+                threshold: 16,
+            }),
+            ..Default::default()
+        };
+        let (tx, changes) = std::sync::mpsc::channel();
+        let rx = sm.start_thread_timelapse(None, settings, Arc::new(AtomicBool::new(false)), None, move |_, md| {
+            tx.send(md.changed_area).unwrap();
+            Ok(PathBuf::from("change.png"))
+        });
+
+        //il primo scatto viene sempre salvato, i successivi solo se lo schermo cambia
+        assert_eq!(changes.recv().unwrap(), None);
+        assert_eq!(rx.recv().unwrap(), TimelapseEvent::Saved(PathBuf::from("change.png")));
+        assert_eq!(rx.recv().unwrap(), TimelapseEvent::Skipped);
+        backend.set_spot(Some((25, 32, 10, 10)));
+        assert_eq!(changes.recv().unwrap(), Some((20, 30, 20, 20)));
+        //il confronto avviene con l'ultimo scatto salvato: la macchia rimossa è un nuovo cambiamento
+        backend.set_spot(None);
+        assert_eq!(changes.recv().unwrap(), Some((20, 30, 20, 20)));
+        assert_eq!(rx.iter().last(), Some(TimelapseEvent::Finished));
+    }
+
+    #[test]
+    fn window_metadata_test() {
+        let first = mock::display(1, 0, 0, 100, 100);
+        let mut second = mock::display(2, 100, 0, 100, 100);
+        second.scale_factor = 2.0;
+        let backend = Arc::new(MockBackend::new(vec![first, second]));
+        let sm = manager(&backend);
+
+        //il centro della finestra è sul secondo schermo, la cui origine in pixel fisici è (200, 0)
+        let md = sm.window_metadata((210, 20, 50, 40)).unwrap();
+        assert_eq!((md.screen_id, md.scale_factor), (2, 2.0));
+        assert_eq!(md.crop, Some((10, 20, 50, 40)));
+        assert_eq!(md.mode, ScreenshotDim::Window);
+        //finestra fuori da tutti gli schermi: viene usato lo schermo selezionato
+        let md = sm.window_metadata((-500, -500, 10, 10)).unwrap();
+        assert_eq!(md.screen_id, 1);
+        assert_eq!(md.crop, Some((0, 0, 10, 10)));
+    }
+
+    #[test]
+    fn recording_excluded_area_test() {
+        let backend = Arc::new(MockBackend::new(vec![mock::display(1, 0, 0, 200, 100)]));
+        let sm = manager(&backend);
+        let excluded = Arc::new(ExcludedArea::default());
+        //la finestra di stato sporge dallo schermo: viene mascherata solo la parte contenuta
+        excluded.set(Rect::from_min_max(eframe::emath::pos2(150.0, -10.0), eframe::emath::pos2(250.0, 30.0)));
+        let area = Rect::from_min_max(eframe::emath::pos2(100.0, 0.0), eframe::emath::pos2(200.0, 50.0));
+        let rx = sm.start_thread_recording(
+            Some(area),
+            50,
+            Duration::from_millis(50),
+            Arc::new(AtomicBool::new(false)),
+            Some(excluded),
+        );
+        let (frames, _, limit_reached) = rx.recv().unwrap().unwrap();
+        assert!(!frames.is_empty() && !limit_reached);
+        for img in frames {
+            assert_eq!(img.dimensions(), (100, 50));
+            assert_eq!(*img.get_pixel(49, 29), MockBackend::color(1));
+            assert_eq!(img.get_pixel(50, 0).0, [0, 0, 0, 255]);
+            assert_eq!(img.get_pixel(99, 29).0, [0, 0, 0, 255]);
+            assert_eq!(*img.get_pixel(99, 30), MockBackend::color(1));
+        }
+    }
+
+    #[test]
+    fn shot_gate_test() {
+        let gate = Arc::new(ShotGate::default());
+        let g = gate.clone();
+        let (tx, shots) = std::sync::mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            g.hide_window(&AtomicBool::new(false));
+            tx.send(()).unwrap();
+            g.show_window();
+        });
+        //lo scatto attende che la gui abbia nascosto la finestra
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(shots.try_recv().is_err());
+        assert_eq!(gate.window_visibility(), Some(false));
+        shots.recv().unwrap();
+        handle.join().unwrap();
+        assert_eq!(gate.window_visibility(), Some(true));
+        assert_eq!(gate.window_visibility(), None);
+    }
 }
@@ -0,0 +1,256 @@
+/* Modulo per la cattura di una singola finestra di primo livello.
+Le finestre vengono elencate (con titolo, classe e posizione) interrogando direttamente il server X11: la funzionalità è
+quindi disponibile solo su Linux con X11 (o XWayland, limitatamente alle finestre dei client X). Sulle altre piattaforme
+le funzioni del modulo ritornano un errore.
+
+La finestra può essere indicata tramite il suo id, oppure scelta dall'utente con un click: in questo caso il puntatore
+viene catturato (grab) fino al click, e la pressione di un tasto qualsiasi annulla la scelta.
+Come per gli schermi, le operazioni sono eseguite in thread worker che inviano il risultato su un canale.
+
+Il modulo fornisce anche l'immagine del puntatore del mouse (<i>CursorImage</i>), che gli screenshot non includono: viene
+mantenuta separata dall'immagine catturata, per poter essere mostrata o nascosta nell'editor prima del salvataggio.
+ */
+
+#[cfg(target_os = "linux")]
+mod x11;
+
+use crate::itc::WindowCaptureSettings;
+#[cfg(target_os = "linux")]
+use crate::itc::WindowTarget;
+use image::RgbaImage;
+#[cfg(target_os = "linux")]
+use std::io::Write;
+use std::sync::mpsc::{channel, Receiver};
+
+///Rettangolo in coordinate del desktop virtuale (della finestra root di X11), in pixel fisici.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WindowRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+///Descrizione di una finestra di primo livello.
+#[derive(Debug, PartialEq, Clone)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    ///Classe della finestra (seconda stringa di WM_CLASS), che identifica l'applicazione.
+    pub class: String,
+    ///Area del contenuto della finestra.
+    pub bounds: WindowRect,
+    ///Area comprensiva della cornice disegnata dal window manager; coincide con <i>bounds</i> se la finestra non
+    ///ha decorazioni.
+    pub frame: WindowRect,
+}
+
+///Immagine del puntatore del mouse, da sovrapporre ad uno screenshot.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CursorImage {
+    ///Posizione dell'angolo in alto a sinistra dell'immagine, in coordinate del desktop virtuale oppure, dopo
+    ///<i>relative_to()</i>, dello screenshot.
+    pub x: i32,
+    pub y: i32,
+    pub image: RgbaImage,
+}
+
+impl CursorImage {
+    ///Riporta la posizione nel sistema di coordinate di uno screenshot, il cui angolo in alto a sinistra si trova in
+    ///(<b>x</b>, <b>y</b>) nel desktop virtuale.
+    pub fn relative_to(self, x: i32, y: i32) -> Self {
+        Self {
+            x: self.x - x,
+            y: self.y - y,
+            ..self
+        }
+    }
+
+    ///Disegna il puntatore su <b>img</b>, rispettandone la trasparenza. Le parti esterne all'immagine sono ignorate.
+    pub fn draw_onto(&self, img: &mut RgbaImage) {
+        image::imageops::overlay(img, &self.image, self.x as i64, self.y as i64);
+    }
+}
+
+impl WindowInfo {
+    ///Area da catturare, con o senza decorazioni.
+    pub fn capture_rect(&self, decorations: bool) -> WindowRect {
+        if decorations {
+            self.frame
+        } else {
+            self.bounds
+        }
+    }
+
+    ///Testo con cui la finestra viene mostrata all'utente.
+    pub fn label(&self) -> String {
+        let title = if self.title.is_empty() {
+            "(untitled)"
+        } else {
+            self.title.as_str()
+        };
+        if self.class.is_empty() {
+            title.to_string()
+        } else {
+            format!("{} — {}", title, self.class)
+        }
+    }
+}
+
+///Elenca le finestre di primo livello visibili, in ordine di sovrapposizione (dalla più in basso alla più in alto)
+///se il window manager lo rende disponibile.
+pub fn list_windows() -> Result<Vec<WindowInfo>, &'static str> {
+    #[cfg(target_os = "linux")]
+    {
+        x11::list_windows().map_err(|e| report("unable to list the windows", e))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        Err(UNSUPPORTED)
+    }
+}
+
+///Crea un canale e muove il suo  <i>Sender</i> ad un nuovo thread, il quale si occupa di eseguire <i>list_windows()</i>
+///ed inviare il risultato sul canale.
+pub fn start_thread_list_windows() -> Receiver<Result<Vec<WindowInfo>, &'static str>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(list_windows());
+    });
+    rx
+}
+
+///Lancia un thread che:
+///- individua la finestra indicata in <i>settings</i>, eventualmente attendendo che l'utente la scelga con un click;
+///- cattura l'area della finestra (con o senza decorazioni);
+///- invia sul canale il cui <i>Receiver</i> è ritornato dal metodo corrente l'immagine e l'area catturata, con cui
+///  individuare lo schermo su cui si trova la finestra.
+///
+///Oppure invia sul canale un messaggio di errore.
+pub fn start_thread_window_screenshot(
+    settings: WindowCaptureSettings,
+) -> Receiver<Result<(RgbaImage, WindowRect), &'static str>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(window_screenshot(settings));
+    });
+    rx
+}
+
+fn window_screenshot(
+    settings: WindowCaptureSettings,
+) -> Result<(RgbaImage, WindowRect), &'static str> {
+    #[cfg(target_os = "linux")]
+    {
+        let window = match settings.target {
+            WindowTarget::Pick => x11::pick_window()
+                .map_err(|e| report("unable to select the window", e))?
+                .ok_or("Window selection cancelled")?,
+            WindowTarget::Id(id) => id,
+        };
+        let info = x11::window_info(window)
+            .map_err(|e| report("unable to read the window geometry", e))?
+            .ok_or("Error: the window is not visible")?;
+        let rect = info.capture_rect(settings.decorations);
+        x11::capture_rect(rect)
+            .map(|img| (img, rect))
+            .map_err(|e| report("unable to perform screenshot", e))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = settings;
+        Err(UNSUPPORTED)
+    }
+}
+
+///Legge l'immagine e la posizione correnti del puntatore del mouse.
+pub fn cursor_image() -> Result<CursorImage, &'static str> {
+    #[cfg(target_os = "linux")]
+    {
+        x11::cursor_image()
+            .map(|(x, y, image)| CursorImage { x, y, image })
+            .map_err(|e| report("unable to read the mouse cursor", e))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        Err("Error: the mouse cursor can only be captured on X11")
+    }
+}
+
+///Crea un canale e muove il suo <i>Sender</i> ad un nuovo thread, il quale si occupa di eseguire <i>cursor_image()</i>
+///ed inviare il risultato sul canale.
+pub fn start_thread_cursor_image() -> Receiver<Result<CursorImage, &'static str>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(cursor_image());
+    });
+    rx
+}
+
+#[cfg(not(target_os = "linux"))]
+const UNSUPPORTED: &str = "Error: window capture is only supported on X11";
+
+///Scrive su stderr il dettaglio dell'errore e ritorna il messaggio da mostrare all'utente.
+#[cfg(target_os = "linux")]
+fn report(what: &str, e: x11::X11Error) -> &'static str {
+    let _ = writeln!(std::io::stderr(), "Error: {}: {}", what, e);
+    match e {
+        x11::X11Error::Connection(_) => "Error: unable to connect to the X server",
+        _ => "Error: window capture failed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_rect_test() {
+        let info = WindowInfo {
+            id: 1,
+            title: String::new(),
+            class: "XTerm".to_string(),
+            bounds: WindowRect {
+                x: 10,
+                y: 30,
+                width: 100,
+                height: 50,
+            },
+            frame: WindowRect {
+                x: 8,
+                y: 8,
+                width: 104,
+                height: 74,
+            },
+        };
+        assert_eq!(info.capture_rect(true), info.frame);
+        assert_eq!(info.capture_rect(false), info.bounds);
+        assert_eq!(info.label(), "(untitled) — XTerm");
+    }
+
+    #[test]
+    fn cursor_draw_onto_test() {
+        let mut image = RgbaImage::from_pixel(2, 2, image::Rgba([255, 255, 255, 255]));
+        image.put_pixel(1, 1, image::Rgba([255, 0, 0, 128]));
+        let cursor = CursorImage {
+            x: 100,
+            y: 50,
+            image,
+        }
+        .relative_to(99, 49);
+        assert_eq!((cursor.x, cursor.y), (1, 1));
+
+        let mut img = RgbaImage::from_pixel(3, 3, image::Rgba([0, 0, 0, 255]));
+        cursor.draw_onto(&mut img);
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(img.get_pixel(1, 1).0, [255, 255, 255, 255]);
+        assert_eq!(img.get_pixel(2, 2).0[1], 0);
+        assert!(img.get_pixel(2, 2).0[0] > 100);
+
+        //il cursore può trovarsi in parte fuori dallo screenshot
+        let mut img = RgbaImage::from_pixel(3, 3, image::Rgba([0, 0, 0, 255]));
+        cursor.clone().relative_to(2, 2).draw_onto(&mut img);
+        assert!(img.get_pixel(0, 0).0[0] > 100);
+        assert_eq!(img.get_pixel(1, 1).0, [0, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,492 @@
+/* Accesso diretto al server X11 tramite il protocollo (x11rb), senza dipendere da Xlib.
+L'elenco delle finestre di primo livello viene letto dalla proprietà _NET_CLIENT_LIST della finestra root, gestita dai
+window manager conformi a EWMH; in sua assenza vengono considerati i figli visibili della root.
+Molti window manager inseriscono ogni finestra in una cornice (reparenting): in questo caso le decorazioni sono date
+dalla finestra figlia diretta della root che contiene il client, altrimenti dalla proprietà _NET_FRAME_EXTENTS.
+L'immagine del puntatore, che non compare negli screenshot, viene letta con l'estensione XFixes.
+ */
+
+use super::{WindowInfo, WindowRect};
+use image::RgbaImage;
+use std::collections::VecDeque;
+use std::fmt;
+use x11rb::connection::Connection;
+use x11rb::errors::{ConnectError, ConnectionError, ReplyError, ReplyOrIdError};
+use x11rb::protocol::xfixes::ConnectionExt as _;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ConnectionExt, EventMask, GetPropertyReply, GrabMode, GrabStatus, ImageFormat,
+    ImageOrder, MapState, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::{CURRENT_TIME, NONE};
+
+///Glifo del cursore a croce nel font "cursor" standard di X11.
+const XC_CROSSHAIR: u16 = 34;
+
+#[derive(Debug)]
+pub enum X11Error {
+    ///Non è stato possibile connettersi al server (ad es. DISPLAY non impostata).
+    Connection(ConnectError),
+    ///Errore ritornato dal server o interruzione della connessione.
+    Protocol(String),
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for X11Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            X11Error::Connection(e) => write!(f, "{}", e),
+            X11Error::Protocol(e) => write!(f, "{}", e),
+            X11Error::Unsupported(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<ConnectionError> for X11Error {
+    fn from(value: ConnectionError) -> Self {
+        X11Error::Protocol(value.to_string())
+    }
+}
+
+impl From<ReplyError> for X11Error {
+    fn from(value: ReplyError) -> Self {
+        X11Error::Protocol(value.to_string())
+    }
+}
+
+impl From<ReplyOrIdError> for X11Error {
+    fn from(value: ReplyOrIdError) -> Self {
+        X11Error::Protocol(value.to_string())
+    }
+}
+
+type X11Result<T> = Result<T, X11Error>;
+
+///Connessione al server, con la finestra root dello schermo di default e gli atomi usati dal modulo.
+struct X11 {
+    conn: RustConnection,
+    screen: usize,
+    root: Window,
+    net_client_list: Atom,
+    net_wm_name: Atom,
+    net_frame_extents: Atom,
+    utf8_string: Atom,
+    wm_state: Atom,
+}
+
+impl X11 {
+    fn connect() -> X11Result<Self> {
+        let (conn, screen) = RustConnection::connect(None).map_err(X11Error::Connection)?;
+        let root = conn.setup().roots[screen].root;
+        let intern = |name: &[u8]| -> X11Result<Atom> { Ok(conn.intern_atom(false, name)?.reply()?.atom) };
+        let net_client_list = intern(b"_NET_CLIENT_LIST")?;
+        let net_wm_name = intern(b"_NET_WM_NAME")?;
+        let net_frame_extents = intern(b"_NET_FRAME_EXTENTS")?;
+        let utf8_string = intern(b"UTF8_STRING")?;
+        let wm_state = intern(b"WM_STATE")?;
+        Ok(Self {
+            conn,
+            screen,
+            root,
+            net_client_list,
+            net_wm_name,
+            net_frame_extents,
+            utf8_string,
+            wm_state,
+        })
+    }
+
+    fn property(
+        &self,
+        window: Window,
+        property: impl Into<Atom>,
+        type_: impl Into<Atom>,
+    ) -> X11Result<GetPropertyReply> {
+        Ok(self
+            .conn
+            .get_property(false, window, property, type_, 0, u32::MAX / 4)?
+            .reply()?)
+    }
+
+    ///Finestre dei client di primo livello, dalla più in basso alla più in alta.
+    fn client_windows(&self) -> X11Result<Vec<Window>> {
+        let list = self.property(self.root, self.net_client_list, AtomEnum::WINDOW)?;
+        if let Some(ids) = list.value32() {
+            let ids: Vec<Window> = ids.collect();
+            if !ids.is_empty() {
+                return Ok(ids);
+            }
+        }
+
+        //window manager non conforme ad EWMH (o assente)
+        let tree = self.conn.query_tree(self.root)?.reply()?;
+        let mut ret = vec![];
+        for child in tree.children {
+            let viewable = match self.conn.get_window_attributes(child)?.reply() {
+                Ok(attrs) => attrs.map_state == MapState::VIEWABLE && !attrs.override_redirect,
+                Err(_) => false, //la finestra è stata distrutta nel frattempo
+            };
+            if viewable {
+                ret.push(self.client_of(child)?);
+            }
+        }
+        Ok(ret)
+    }
+
+    ///Cerca, tra i discendenti di <i>top</i> (compreso), la finestra del client: quella con la proprietà WM_STATE,
+    ///impostata dal window manager. Se non viene trovata ritorna <i>top</i>.
+    fn client_of(&self, top: Window) -> X11Result<Window> {
+        let mut queue = VecDeque::from([top]);
+        while let Some(window) = queue.pop_front() {
+            let state = self
+                .conn
+                .get_property(false, window, self.wm_state, AtomEnum::ANY, 0, 0)?
+                .reply()?;
+            if state.type_ != NONE {
+                return Ok(window);
+            }
+            queue.extend(self.conn.query_tree(window)?.reply()?.children);
+        }
+        Ok(top)
+    }
+
+    ///Ritorna None se la finestra non è visibile.
+    fn info(&self, window: Window) -> X11Result<Option<WindowInfo>> {
+        let attrs = self.conn.get_window_attributes(window)?.reply()?;
+        if attrs.map_state != MapState::VIEWABLE {
+            return Ok(None);
+        }
+        let geometry = self.conn.get_geometry(window)?.reply()?;
+        let position = self
+            .conn
+            .translate_coordinates(window, self.root, 0, 0)?
+            .reply()?;
+        let bounds = WindowRect {
+            x: position.dst_x as i32,
+            y: position.dst_y as i32,
+            width: geometry.width as u32,
+            height: geometry.height as u32,
+        };
+        Ok(Some(WindowInfo {
+            id: window,
+            title: self.title(window)?,
+            class: self.class(window)?,
+            bounds,
+            frame: self.frame(window, bounds)?,
+        }))
+    }
+
+    fn title(&self, window: Window) -> X11Result<String> {
+        let name = self.property(window, self.net_wm_name, self.utf8_string)?;
+        if let Some(bytes) = name.value8() {
+            let bytes: Vec<u8> = bytes.collect();
+            if !bytes.is_empty() {
+                return Ok(String::from_utf8_lossy(&bytes).into_owned());
+            }
+        }
+        let name = self.property(window, AtomEnum::WM_NAME, AtomEnum::STRING)?;
+        Ok(name.value8().map(latin1).unwrap_or_default())
+    }
+
+    ///WM_CLASS contiene due stringhe terminate da NUL: il nome dell'istanza e la classe.
+    fn class(&self, window: Window) -> X11Result<String> {
+        let class = self.property(window, AtomEnum::WM_CLASS, AtomEnum::STRING)?;
+        let bytes: Vec<u8> = class.value8().map(|b| b.collect()).unwrap_or_default();
+        Ok(bytes
+            .split(|b| *b == 0)
+            .rfind(|s| !s.is_empty())
+            .map(|s| latin1(s.iter().copied()))
+            .unwrap_or_default())
+    }
+
+    fn frame(&self, window: Window, bounds: WindowRect) -> X11Result<WindowRect> {
+        //antenato figlio diretto della root: è la cornice creata dai window manager che fanno reparenting
+        let mut top = window;
+        loop {
+            let parent = self.conn.query_tree(top)?.reply()?.parent;
+            if parent == self.root || parent == NONE {
+                break;
+            }
+            top = parent;
+        }
+        if top != window {
+            let geometry = self.conn.get_geometry(top)?.reply()?;
+            let border = geometry.border_width as u32;
+            return Ok(WindowRect {
+                x: geometry.x as i32,
+                y: geometry.y as i32,
+                width: geometry.width as u32 + 2 * border,
+                height: geometry.height as u32 + 2 * border,
+            });
+        }
+
+        //estensioni della cornice nell'ordine: sinistra, destra, sopra, sotto
+        let extents = self.property(window, self.net_frame_extents, AtomEnum::CARDINAL)?;
+        if let Some(values) = extents.value32() {
+            if let [left, right, top, bottom] = values.collect::<Vec<u32>>()[..] {
+                return Ok(WindowRect {
+                    x: bounds.x - left as i32,
+                    y: bounds.y - top as i32,
+                    width: bounds.width + left + right,
+                    height: bounds.height + top + bottom,
+                });
+            }
+        }
+        Ok(bounds)
+    }
+
+    ///Attende il click che sceglie la finestra; ritorna None se viene premuto un tasto o un pulsante diverso dal
+    ///sinistro, oppure se il click avviene sullo sfondo del desktop.
+    fn wait_click(&self) -> X11Result<Option<Window>> {
+        loop {
+            match self.conn.wait_for_event()? {
+                Event::ButtonPress(e) => {
+                    return Ok(if e.detail == 1 && e.child != NONE {
+                        Some(e.child)
+                    } else {
+                        None
+                    });
+                }
+                Event::KeyPress(_) => return Ok(None),
+                _ => (),
+            }
+        }
+    }
+}
+
+fn latin1(bytes: impl Iterator<Item = u8>) -> String {
+    bytes.map(char::from).collect()
+}
+
+pub fn list_windows() -> X11Result<Vec<WindowInfo>> {
+    let x11 = X11::connect()?;
+    //le finestre possono essere distrutte mentre vengono interrogate: quelle con errori vengono ignorate
+    Ok(x11
+        .client_windows()?
+        .into_iter()
+        .filter_map(|window| x11.info(window).ok().flatten())
+        .collect())
+}
+
+pub fn window_info(window: u32) -> X11Result<Option<WindowInfo>> {
+    X11::connect()?.info(window)
+}
+
+///Cattura il puntatore e la tastiera, mostra un cursore a croce e attende che l'utente scelga una finestra.
+///Ritorna l'id della finestra del client, o None se la scelta è stata annullata.
+pub fn pick_window() -> X11Result<Option<u32>> {
+    let x11 = X11::connect()?;
+    let conn = &x11.conn;
+
+    let font = conn.generate_id()?;
+    conn.open_font(font, b"cursor")?;
+    let cursor = conn.generate_id()?;
+    conn.create_glyph_cursor(
+        cursor,
+        font,
+        font,
+        XC_CROSSHAIR,
+        XC_CROSSHAIR + 1,
+        0,
+        0,
+        0,
+        0xffff,
+        0xffff,
+        0xffff,
+    )?;
+
+    let grab = conn
+        .grab_pointer(
+            false,
+            x11.root,
+            u32::from(EventMask::BUTTON_PRESS) as u16,
+            GrabMode::ASYNC,
+            GrabMode::ASYNC,
+            NONE,
+            cursor,
+            CURRENT_TIME,
+        )?
+        .reply()?;
+    let picked = if grab.status == GrabStatus::SUCCESS {
+        //senza la tastiera non è possibile annullare, ma la scelta col mouse resta possibile
+        let _ = conn
+            .grab_keyboard(false, x11.root, CURRENT_TIME, GrabMode::ASYNC, GrabMode::ASYNC)?
+            .reply();
+        x11.wait_click()
+    } else {
+        Err(X11Error::Unsupported("the pointer is grabbed by another application"))
+    };
+
+    conn.ungrab_pointer(CURRENT_TIME)?;
+    conn.ungrab_keyboard(CURRENT_TIME)?;
+    conn.free_cursor(cursor)?;
+    conn.close_font(font)?;
+    conn.flush()?;
+
+    match picked? {
+        Some(top) => Ok(Some(x11.client_of(top)?)),
+        None => Ok(None),
+    }
+}
+
+///Cattura l'area indicata dello schermo, limitata alla parte visibile.
+pub fn capture_rect(rect: WindowRect) -> X11Result<RgbaImage> {
+    let x11 = X11::connect()?;
+    let setup = x11.conn.setup();
+    let screen = &setup.roots[x11.screen];
+
+    let x0 = rect.x.max(0);
+    let y0 = rect.y.max(0);
+    let x1 = (rect.x + rect.width as i32).min(screen.width_in_pixels as i32);
+    let y1 = (rect.y + rect.height as i32).min(screen.height_in_pixels as i32);
+    if x1 <= x0 || y1 <= y0 {
+        return Err(X11Error::Unsupported("the window is outside of the screen"));
+    }
+    let (width, height) = ((x1 - x0) as u16, (y1 - y0) as u16);
+
+    let reply = x11
+        .conn
+        .get_image(ImageFormat::Z_PIXMAP, x11.root, x0 as i16, y0 as i16, width, height, !0)?
+        .reply()?;
+    let bits_per_pixel = setup
+        .pixmap_formats
+        .iter()
+        .find(|f| f.depth == reply.depth)
+        .map(|f| f.bits_per_pixel);
+    if bits_per_pixel != Some(32) {
+        return Err(X11Error::Unsupported("unsupported screen depth"));
+    }
+    to_rgba(
+        &reply.data,
+        width as u32,
+        height as u32,
+        setup.image_byte_order == ImageOrder::LSB_FIRST,
+    )
+    .ok_or(X11Error::Unsupported("invalid image data"))
+}
+
+///Immagine corrente del puntatore, con la posizione del suo angolo in alto a sinistra (cioè la posizione del
+///puntatore meno quella del suo hotspot) in coordinate della finestra root.
+pub fn cursor_image() -> X11Result<(i32, i32, RgbaImage)> {
+    let (conn, _) = RustConnection::connect(None).map_err(X11Error::Connection)?;
+    conn.xfixes_query_version(4, 0)?.reply()?;
+    let reply = conn.xfixes_get_cursor_image()?.reply()?;
+    let image = argb_to_rgba(&reply.cursor_image, reply.width as u32, reply.height as u32)
+        .ok_or(X11Error::Unsupported("invalid cursor image"))?;
+    Ok((
+        reply.x as i32 - reply.xhot as i32,
+        reply.y as i32 - reply.yhot as i32,
+        image,
+    ))
+}
+
+///Converte i pixel ARGB con alpha premoltiplicato, come ritornati da XFixes, in RGBA non premoltiplicato.
+fn argb_to_rgba(data: &[u32], width: u32, height: u32) -> Option<RgbaImage> {
+    let pixels = data
+        .iter()
+        .flat_map(|p| {
+            let [a, r, g, b] = p.to_be_bytes();
+            let unpremultiply = |c: u8| match a {
+                0 => 0,
+                _ => ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8,
+            };
+            [unpremultiply(r), unpremultiply(g), unpremultiply(b), a]
+        })
+        .collect();
+    RgbaImage::from_raw(width, height, pixels)
+}
+
+///Converte i pixel a 32 bit di un'immagine ZPixmap (TrueColor, 8 bit per canale) in RGBA opaco.
+fn to_rgba(data: &[u8], width: u32, height: u32, lsb_first: bool) -> Option<RgbaImage> {
+    let pixels = data
+        .chunks_exact(4)
+        .flat_map(|p| {
+            if lsb_first {
+                [p[2], p[1], p[0], 255]
+            } else {
+                [p[1], p[2], p[3], 255]
+            }
+        })
+        .collect();
+    RgbaImage::from_raw(width, height, pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x11rb::protocol::xproto::{CreateWindowAux, PropMode, WindowClass};
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::COPY_DEPTH_FROM_PARENT;
+
+    #[test]
+    fn to_rgba_test() {
+        let lsb = to_rgba(&[1, 2, 3, 0, 4, 5, 6, 0], 2, 1, true).unwrap();
+        assert_eq!(lsb.into_raw(), vec![3, 2, 1, 255, 6, 5, 4, 255]);
+        let msb = to_rgba(&[0, 1, 2, 3], 1, 1, false).unwrap();
+        assert_eq!(msb.into_raw(), vec![1, 2, 3, 255]);
+        assert!(to_rgba(&[0, 1, 2, 3], 2, 1, true).is_none());
+    }
+
+    #[test]
+    fn argb_to_rgba_test() {
+        let img = argb_to_rgba(&[0xff102030, 0x80400000, 0x00000000], 3, 1).unwrap();
+        assert_eq!(
+            img.into_raw(),
+            vec![0x10, 0x20, 0x30, 0xff, 0x80, 0, 0, 0x80, 0, 0, 0, 0]
+        );
+        assert!(argb_to_rgba(&[0], 2, 1).is_none());
+    }
+
+    #[test]
+    #[ignore = "requires a display server: run with `xvfb-run cargo test -- --ignored`"]
+    fn dummy_window_test() {
+        let (conn, screen) = RustConnection::connect(None).unwrap();
+        let root = conn.setup().roots[screen].root;
+        let window = conn.generate_id().unwrap();
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            20,
+            30,
+            64,
+            48,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new().background_pixel(0x00ff0000),
+        )
+        .unwrap();
+        conn.change_property8(PropMode::REPLACE, window, AtomEnum::WM_NAME, AtomEnum::STRING, b"dummy window")
+            .unwrap();
+        conn.change_property8(PropMode::REPLACE, window, AtomEnum::WM_CLASS, AtomEnum::STRING, b"dummy\0Dummy\0")
+            .unwrap();
+        conn.map_window(window).unwrap();
+        conn.get_input_focus().unwrap().reply().unwrap();
+
+        //la finestra viene mappata in modo asincrono
+        let info = (0..50)
+            .find_map(|_| {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                window_info(window).ok().flatten()
+            })
+            .unwrap();
+        assert_eq!(info.title, "dummy window");
+        assert_eq!(info.class, "Dummy");
+        assert_eq!(
+            info.bounds,
+            WindowRect {
+                x: 20,
+                y: 30,
+                width: 64,
+                height: 48
+            }
+        );
+        assert!(list_windows().unwrap().iter().any(|w| w.id == window));
+
+        let img = capture_rect(info.capture_rect(false)).unwrap();
+        assert_eq!(img.dimensions(), (64, 48));
+        assert_eq!(img.get_pixel(32, 24).0, [255, 0, 0, 255]);
+    }
+}